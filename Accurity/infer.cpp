@@ -57,6 +57,7 @@ Infer::Infer(string configFilepath, string segment_data_input_path,
     _total_no_of_snps_used = 0;
     _total_no_of_segments = 0;
     _total_no_of_segments_used = 0;
+    _tumor_in_normal_fraction = -1;
 
     _period_discover_run_type = 1;
     _genome_len_cnv_all = 0;
@@ -1087,6 +1088,11 @@ int Infer::output_logL(OnePeriod &best_period_obj,
                 << _total_no_of_snps << "\t"
                 << _total_no_of_snps_used
                 << endl;
+    _infer_outf << "tumor_in_normal_fraction" << endl;
+    if (_tumor_in_normal_fraction >= 0)
+        _infer_outf << _tumor_in_normal_fraction << endl;
+    else
+        _infer_outf << "NA" << endl;
     if (_debug > 0)
     {
         // output all candidate snp likelihoods of the best period object
@@ -1587,6 +1593,10 @@ int Infer::getSNPDataFromFile(string input_file_path)
         std::vector<std::string> element_vec = string_split(line, "\t");
         chr_string = element_vec[0];
         std::getline(input_stream, line);
+        if (chr_string.find("#tumor_in_normal_fraction=") == 0) {
+            _tumor_in_normal_fraction = stod(chr_string.substr(chr_string.find('=') + 1));
+            continue;
+        }
         if (chr_string[0]=='#' || element_vec[1]=="pos") {
             //ignore comments and header
             continue;
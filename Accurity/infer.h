@@ -292,6 +292,8 @@ class Infer {
     int _total_no_of_snps_used;
     int _total_no_of_segments;
     int _total_no_of_segments_used;
+    //from the #tumor_in_normal_fraction= line of the SNP file. -1 if absent.
+    double _tumor_in_normal_fraction;
 
     OnePeriod _period_obj_from_autocor;  // period_int, lower bound, upper
     // bound
@@ -0,0 +1,268 @@
+use flate2;
+use flate2::Compression;
+use rust_htslib::bam;
+use rust_htslib::bam::Read as bamRead;
+use rust_htslib::bcf;
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path};
+
+use ref_bias::RefBias;
+use select_het_snp::calc_tumor_maf_normalized;
+
+
+//a normal site is het if both alleles have at least this many reads and the minor one this fraction of the depth.
+const MIN_NORMAL_ALLELE_OBS: usize = 2;
+const MIN_NORMAL_MINOR_FRACTION: f32 = 0.2;
+
+/// A bi-allelic SNP site of the known-sites VCF. pos is 0-based.
+struct SNPSite {
+    pos: u32,
+    ref_base: u8,
+    alt_base: u8,
+}
+
+/// Ref/alt counts of tumor and normal at one site.
+struct SiteCount {
+    chr: String,
+    pos: u32,
+    tumor_ref_obs: usize,
+    tumor_alt_obs: usize,
+    normal_ref_obs: usize,
+    normal_alt_obs: usize,
+}
+
+pub struct AlleleCount<'a> {
+    tumor_bam_path: &'a Path,
+    normal_bam_path: &'a Path,
+    known_sites_path: &'a Path,
+    output_file_path: &'a Path,
+    min_base_quality: u8,
+    min_mapping_quality: u8,
+    min_coverage: usize,
+    max_coverage: usize,
+    debug: i32,
+}
+
+impl<'a> AlleleCount<'a> {
+    pub fn new(tumor_bam_path: &'a str,
+               normal_bam_path: &'a str,
+               known_sites_path: &'a str,
+               output_file_path: &'a str,
+               min_base_quality: u8,
+               min_mapping_quality: u8,
+               min_coverage: usize,
+               max_coverage: usize,
+               debug: i32,
+    ) -> AlleleCount<'a> {
+        AlleleCount {
+            tumor_bam_path: Path::new(tumor_bam_path),
+            normal_bam_path: Path::new(normal_bam_path),
+            known_sites_path: Path::new(known_sites_path),
+            output_file_path: Path::new(output_file_path),
+            min_base_quality,
+            min_mapping_quality,
+            min_coverage,
+            max_coverage,
+            debug,
+        }
+    }
+
+    /// Read bi-allelic SNPs of the known-sites VCF, grouped by chromosome in the order of the file.
+    fn read_in_sites(&self) -> Vec<(String, Vec<SNPSite>)> {
+        println_stderr!("Reading known sites from {:?} ...", self.known_sites_path);
+        let mut vcf = bcf::Reader::from_path(&self.known_sites_path).ok().expect("Error opening known-sites file.");
+        let vcf_header = vcf.header().clone();
+        let mut chr_site_vec: Vec<(String, Vec<SNPSite>)> = Vec::new();
+        let mut no_of_sites = 0usize;
+        for rec in vcf.records() {
+            let record = rec.ok().expect("Error reading known-sites record.");
+            let alleles = record.alleles();
+            if alleles.len() != 2 || alleles[0].len() != 1 || alleles[1].len() != 1 {
+                continue;
+            }
+            let chr = String::from_utf8_lossy(vcf_header.rid2name(record.rid().expect("Error reading rid."))).to_string();
+            let is_new_chr = match chr_site_vec.last() {
+                Some(&(ref last_chr, _)) => *last_chr != chr,
+                None => true,
+            };
+            if is_new_chr {
+                chr_site_vec.push((chr, Vec::new()));
+            }
+            chr_site_vec.last_mut().unwrap().1.push(SNPSite {
+                pos: record.pos(),
+                ref_base: alleles[0][0].to_ascii_uppercase(),
+                alt_base: alleles[1][0].to_ascii_uppercase(),
+            });
+            no_of_sites += 1;
+        }
+        println_stderr!("{} bi-allelic SNP sites on {} chromosomes.", no_of_sites, chr_site_vec.len());
+        chr_site_vec
+    }
+
+    /// Pile up one chromosome of a BAM over the given sites. Returns (ref count, alt count) per site index.
+    /// Each fragment is counted once. If its two mates overlap the site with different bases, it is not counted.
+    fn count_alleles_of_one_chr(&self, bam_reader: &mut bam::IndexedReader, chr: &str,
+                                site_vec: &[SNPSite], wanted_site_index_vec: &[usize]) -> HashMap<usize, (usize, usize)> {
+        let mut site_index2count: HashMap<usize, (usize, usize)> = HashMap::new();
+        if wanted_site_index_vec.is_empty() {
+            return site_index2count;
+        }
+        let tid = match bam_reader.header().tid(chr.as_bytes()) {
+            Some(tid) => tid,
+            None => {
+                println_stderr!("WARNING: chromosome {} is not in the bam file.", chr);
+                return site_index2count;
+            }
+        };
+        let pos2site_index: HashMap<u32, usize> = wanted_site_index_vec.iter()
+            .map(|site_index| (site_vec[*site_index].pos, *site_index)).collect();
+        let span_start = wanted_site_index_vec.iter().map(|site_index| site_vec[*site_index].pos).min().unwrap();
+        let span_end = wanted_site_index_vec.iter().map(|site_index| site_vec[*site_index].pos).max().unwrap() + 1;
+        bam_reader.fetch(tid, span_start, span_end).ok().expect("Error fetching bam region. Is the bam indexed?");
+        for p in bam_reader.pileup() {
+            let pileup = p.ok().expect("Error reading pileup.");
+            let site_index = match pos2site_index.get(&pileup.pos()) {
+                Some(site_index) => *site_index,
+                None => continue,
+            };
+            let site = &site_vec[site_index];
+            //qname => base. Discordant mates get b'N'.
+            let mut qname2base: HashMap<Vec<u8>, u8> = HashMap::new();
+            for alignment in pileup.alignments() {
+                let qpos = match alignment.qpos() {
+                    Some(qpos) => qpos,
+                    None => continue,
+                };
+                let record = alignment.record();
+                if record.mapq() < self.min_mapping_quality || record.is_unmapped() || record.is_secondary() ||
+                    record.is_duplicate() || record.is_supplementary() || record.is_quality_check_failed() {
+                    continue;
+                }
+                if record.qual()[qpos] < self.min_base_quality {
+                    continue;
+                }
+                let base = record.seq()[qpos].to_ascii_uppercase();
+                let mate_base = qname2base.entry(record.qname().to_vec()).or_insert(base);
+                if *mate_base != base {
+                    *mate_base = b'N';
+                }
+            }
+            let mut no_of_ref_obs = 0usize;
+            let mut no_of_alt_obs = 0usize;
+            for base in qname2base.values() {
+                if *base == site.ref_base {
+                    no_of_ref_obs += 1;
+                } else if *base == site.alt_base {
+                    no_of_alt_obs += 1;
+                }
+            }
+            site_index2count.insert(site_index, (no_of_ref_obs, no_of_alt_obs));
+        }
+        site_index2count
+    }
+
+    fn is_normal_het(&self, no_of_ref_obs: usize, no_of_alt_obs: usize) -> bool {
+        let depth = no_of_ref_obs + no_of_alt_obs;
+        depth >= self.min_coverage && depth <= self.max_coverage &&
+            cmp::min(no_of_ref_obs, no_of_alt_obs) >= MIN_NORMAL_ALLELE_OBS &&
+            cmp::min(no_of_ref_obs, no_of_alt_obs) as f32 / depth as f32 >= MIN_NORMAL_MINOR_FRACTION
+    }
+
+    fn output_site_counts(&self, site_count_vec: &[SiteCount], no_of_sites: usize, ref_bias: &RefBias) {
+        println_stderr!("Outputting {} het SNPs to {:?} ...", site_count_vec.len(), self.output_file_path);
+        let output_f = File::create(&self.output_file_path)
+            .expect(&format!("Error in creating output file {:?}", &self.output_file_path));
+        let mut gz_writer = flate2::GzBuilder::new()
+            .filename(self.output_file_path.file_stem().unwrap().to_str().unwrap())
+            .comment("Comment")
+            .write(output_f, Compression::default());
+        gz_writer.write_fmt(format_args!("#min_base_quality={}, min_mapping_quality={}, min_coverage={}, \
+                max_coverage={}, min_normal_allele_obs={}, min_normal_minor_fraction={}\n",
+                                         self.min_base_quality, self.min_mapping_quality, self.min_coverage,
+                                         self.max_coverage, MIN_NORMAL_ALLELE_OBS, MIN_NORMAL_MINOR_FRACTION)).unwrap();
+        gz_writer.write_fmt(format_args!("#known_sites:{:?}\n", &self.known_sites_path)).unwrap();
+        gz_writer.write_fmt(format_args!("#tumor bam:{:?}\n", &self.tumor_bam_path)).unwrap();
+        gz_writer.write_fmt(format_args!("#normal bam:{:?}\n", &self.normal_bam_path)).unwrap();
+        gz_writer.write_fmt(format_args!("#no_of_known_sites: {}\n", no_of_sites)).unwrap();
+        for (i, bin_max_depth) in ref_bias.bin_max_depth_vec.iter().enumerate() {
+            gz_writer.write_fmt(format_args!("#normal_ref_fraction depth<={}: {} ({} hets)\n", bin_max_depth,
+                                             ref_bias.ref_fraction_vec[i], ref_bias.no_of_snps_vec[i])).unwrap();
+        }
+        gz_writer.write_fmt(format_args!("chr\tpos\ttumor_maf_normalized\ttumor_depth\t\
+                tumor_maf\ttumor_ro\ttumor_ao\t\
+                normal_maf\tnormal_ro\tnormal_ao\t\
+                tumor_baf\ttumor_baf_corrected\n")).unwrap();
+        for site_count in site_count_vec.iter() {
+            let tumor_depth = site_count.tumor_ref_obs + site_count.tumor_alt_obs;
+            let normal_depth = site_count.normal_ref_obs + site_count.normal_alt_obs;
+            let tumor_maf = cmp::max(site_count.tumor_ref_obs, site_count.tumor_alt_obs) as f32 / tumor_depth as f32;
+            let normal_maf = cmp::max(site_count.normal_ref_obs, site_count.normal_alt_obs) as f32 / normal_depth as f32;
+            let tumor_maf_normalized = calc_tumor_maf_normalized(site_count.tumor_ref_obs, site_count.tumor_alt_obs,
+                                                                 site_count.normal_ref_obs, site_count.normal_alt_obs);
+            let tumor_baf = site_count.tumor_alt_obs as f64 / tumor_depth as f64;
+            //the bias table is binned by normal depth.
+            let tumor_baf_corrected = 1.0 - ref_bias.correct_ref_fraction(1.0 - tumor_baf, normal_depth);
+            gz_writer.write_fmt(format_args!("{}\t{}\t{}\t{}\t\
+                    {}\t{}\t{}\t\
+                    {}\t{}\t{}\t\
+                    {}\t{}\n",
+                                             site_count.chr, site_count.pos, tumor_maf_normalized, tumor_depth,
+                                             tumor_maf, site_count.tumor_ref_obs, site_count.tumor_alt_obs,
+                                             normal_maf, site_count.normal_ref_obs, site_count.normal_alt_obs,
+                                             tumor_baf, tumor_baf_corrected)
+            ).unwrap();
+        }
+        gz_writer.write_fmt(format_args!("#no_of_intersect: {}\n", site_count_vec.len())).unwrap();
+        gz_writer.finish()
+            .expect(&format!("ERROR finish() failure for gz_writer of {:?}.", &self.output_file_path));
+        println_stderr!("Output done.");
+    }
+
+    pub fn run(&self) {
+        let chr_site_vec = self.read_in_sites();
+        let mut tumor_bam = bam::IndexedReader::from_path(&self.tumor_bam_path)
+            .ok().expect("Error opening tumor bam. Is it indexed?");
+        let mut normal_bam = bam::IndexedReader::from_path(&self.normal_bam_path)
+            .ok().expect("Error opening normal bam. Is it indexed?");
+        let mut site_count_vec: Vec<SiteCount> = Vec::new();
+        let mut no_of_sites = 0usize;
+        for &(ref chr, ref site_vec) in chr_site_vec.iter() {
+            no_of_sites += site_vec.len();
+            let all_site_index_vec: Vec<usize> = (0..site_vec.len()).collect();
+            let normal_count = self.count_alleles_of_one_chr(&mut normal_bam, chr, site_vec, &all_site_index_vec);
+            //only normal hets are piled up in the tumor.
+            let mut het_site_index_vec: Vec<usize> = normal_count.iter()
+                .filter(|&(_, &(ref_obs, alt_obs))| self.is_normal_het(ref_obs, alt_obs))
+                .map(|(site_index, _)| *site_index).collect();
+            het_site_index_vec.sort();
+            let tumor_count = self.count_alleles_of_one_chr(&mut tumor_bam, chr, site_vec, &het_site_index_vec);
+            let no_of_sites_before = site_count_vec.len();
+            for site_index in het_site_index_vec.iter() {
+                let (normal_ref_obs, normal_alt_obs) = normal_count[site_index];
+                if let Some(&(tumor_ref_obs, tumor_alt_obs)) = tumor_count.get(site_index) {
+                    let tumor_depth = tumor_ref_obs + tumor_alt_obs;
+                    if tumor_depth >= self.min_coverage && tumor_depth <= self.max_coverage {
+                        site_count_vec.push(SiteCount {
+                            chr: chr.clone(),
+                            pos: site_vec[*site_index].pos,
+                            tumor_ref_obs,
+                            tumor_alt_obs,
+                            normal_ref_obs,
+                            normal_alt_obs,
+                        });
+                    }
+                }
+            }
+            println_stderr!("Chromosome {}: {} known sites, {} normal hets, {} hets with tumor coverage.",
+                            chr, site_vec.len(), het_site_index_vec.len(), site_count_vec.len() - no_of_sites_before);
+        }
+        let ref_obs_depth_vec: Vec<(usize, usize)> = site_count_vec.iter()
+            .map(|site_count| (site_count.normal_ref_obs, site_count.normal_ref_obs + site_count.normal_alt_obs))
+            .collect();
+        let ref_bias = RefBias::estimate(&ref_obs_depth_vec);
+        self.output_site_counts(&site_count_vec, no_of_sites, &ref_bias);
+    }
+}
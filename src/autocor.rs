@@ -0,0 +1,197 @@
+use std::f64;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path};
+
+use infer::{InferSegment, MAX_RATIO};
+
+
+//coverage ratios are binned at 1/RESOLUTION, as in read_para.h.
+pub const RESOLUTION: usize = 1000;
+//candidate periods, in 1/RESOLUTION, are within [PERIOD_MIN, PERIOD_MAX].
+pub const PERIOD_MIN: usize = 100;
+pub const PERIOD_MAX: usize = 1000;
+//half of the bounds of a period are at least this wide.
+const PERIOD_HALF_WIDTH_MAX: usize = 20;
+//the autocorrelation at each shift sums only this many largest products.
+//0.5: half of one interval; 0.1: peak half width; 10 peaks. Same as read_para.h.
+const MAX_NUM_OF_COR_TO_SUM: usize = 500;
+//a local maximum of the autocorrelation must be the largest within this many shifts on either side.
+const PEAK_SCOPE: usize = 20;
+//normal density one standard deviation away from the mean, relative to the density at the mean.
+const DEV1: f64 = 0.606;
+//1/sqrt(2*pi)
+const GAUSSIAN_DENSITY_FRONT_SCALAR: f64 = 0.398_942_280_4;
+
+/// Add a normal density of sample_size * N(mean, stddev) to pdf_vec within two stddevs of mean.
+/// mean and stddev are in units of the pdf_vec index. Ported from Infer::kernel_smoothing() in infer.cpp.
+pub fn kernel_smoothing(mean: f64, stddev: f64, sample_size: f64, pdf_vec: &mut [f64]) {
+    if pdf_vec.is_empty() || stddev <= 0.0 {
+        return;
+    }
+    let i_start = (mean - 2.0 * stddev).floor().max(0.0) as usize;
+    let i_end = (mean + 2.0 * stddev).ceil().min((pdf_vec.len() - 1) as f64);
+    if i_end < 0.0 {
+        return;
+    }
+    for i in i_start..(i_end as usize + 1) {
+        let distance = i as f64 - mean;
+        pdf_vec[i] += sample_size * GAUSSIAN_DENSITY_FRONT_SCALAR / stddev *
+            (-distance * distance / (2.0 * stddev * stddev)).exp();
+    }
+}
+
+/// Window-weighted, kernel-smoothed histogram of segment coverage ratios over [0, MAX_RATIO] at 1/RESOLUTION.
+/// Each segment contributes no_of_windows * N(ratio, stddev).
+pub fn build_ratio_histogram(segment_vec: &[InferSegment]) -> Vec<f64> {
+    let mut ratio_pdf_vec = vec![0f64; (MAX_RATIO * RESOLUTION as f64) as usize + 1];
+    for segment in segment_vec.iter() {
+        kernel_smoothing(segment.ratio * RESOLUTION as f64, segment.stddev * RESOLUTION as f64,
+                         segment.no_of_windows as f64, &mut ratio_pdf_vec);
+    }
+    ratio_pdf_vec
+}
+
+/// Autocorrelation of the ratio histogram at shifts 0..=PERIOD_MAX, smoothed by a moving average of 5 shifts
+/// (fewer at either end). Ported from Infer::calculate_autocor() in infer.cpp.
+pub fn calc_autocor(ratio_pdf_vec: &[f64]) -> Vec<f64> {
+    let mut cor_raw_vec: Vec<f64> = Vec::with_capacity(PERIOD_MAX + 1);
+    for shift in 0..(PERIOD_MAX + 1) {
+        let mut term_vec: Vec<f64> = if shift < ratio_pdf_vec.len() {
+            ratio_pdf_vec.iter().zip(ratio_pdf_vec[shift..].iter()).map(|(a, b)| a * b).collect()
+        } else {
+            Vec::new()
+        };
+        term_vec.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        term_vec.truncate(MAX_NUM_OF_COR_TO_SUM);
+        cor_raw_vec.push(term_vec.iter().sum());
+    }
+    (0..(PERIOD_MAX + 1)).map(|shift| {
+        let start = if shift >= 2 { shift - 2 } else { 0 };
+        let end = if shift + 2 <= PERIOD_MAX { shift + 2 } else { PERIOD_MAX };
+        cor_raw_vec[start..(end + 1)].iter().sum::<f64>() / (end - start + 1) as f64
+    }).collect()
+}
+
+/// Write the autocorrelation curve, as auto.tsv of the C++ infer.
+pub fn output_autocor(cor_vec: &[f64], output_file_path: &Path) {
+    let mut output_f = File::create(output_file_path)
+        .expect(&format!("Error in creating output file {:?}", output_file_path));
+    output_f.write_fmt(format_args!("read_count_ratio\tcorrelation\n")).unwrap();
+    for (shift, cor) in cor_vec.iter().enumerate() {
+        output_f.write_fmt(format_args!("{}\t{}\n", shift as f64 / RESOLUTION as f64, cor)).unwrap();
+    }
+}
+
+/// A local maximum of the autocorrelation. period and bounds are in 1/RESOLUTION.
+#[derive(Clone, Debug)]
+pub struct CandidatePeriod {
+    pub period: usize,
+    pub autocor: f64,
+    //autocorrelation above the minimum at smaller shifts, i.e. the depth of the valley before the peak.
+    pub rise: f64,
+    pub lower_bound: usize,
+    pub upper_bound: usize,
+}
+
+/// Shifts around period where the autocorrelation stays above DEV1 of its value at period, within
+/// [PERIOD_MIN, PERIOD_MAX]. Same as the period_min/period_max walks of Infer::infer_candidate_period_by_autocor().
+fn find_period_bounds(cor_vec: &[f64], period: usize) -> (usize, usize) {
+    let threshold = DEV1 * cor_vec[period];
+    let mut lower_bound = period - 1;
+    while lower_bound > PERIOD_MIN &&
+        (cor_vec[lower_bound] > threshold || period - lower_bound < PERIOD_HALF_WIDTH_MAX) {
+        lower_bound -= 1;
+    }
+    let mut upper_bound = period + 1;
+    while upper_bound < PERIOD_MAX &&
+        (cor_vec[upper_bound] > threshold || upper_bound - period < PERIOD_HALF_WIDTH_MAX) {
+        upper_bound += 1;
+    }
+    //a period at PERIOD_MIN or PERIOD_MAX starts its walk outside the range.
+    (lower_bound.max(PERIOD_MIN), upper_bound.min(PERIOD_MAX))
+}
+
+/// Local maxima of the autocorrelation within [PERIOD_MIN, PERIOD_MAX], ranked by their rise over the
+/// preceding valley, largest first. The first is the best period of infer_candidate_period_by_autocor().
+pub fn rank_candidate_periods(cor_vec: &[f64]) -> Vec<CandidatePeriod> {
+    let mut candidate_vec: Vec<CandidatePeriod> = Vec::new();
+    let mut valley = f64::MAX;
+    for shift in 0..cor_vec.len() {
+        valley = valley.min(cor_vec[shift]);
+        if shift < PERIOD_MIN || shift > PERIOD_MAX {
+            continue;
+        }
+        let scope_start = if shift >= PEAK_SCOPE { shift - PEAK_SCOPE } else { 0 };
+        let scope_end = (shift + PEAK_SCOPE).min(cor_vec.len() - 1);
+        let is_peak = cor_vec[scope_start..shift].iter().all(|cor| *cor < cor_vec[shift]) &&
+            cor_vec[(shift + 1)..(scope_end + 1)].iter().all(|cor| *cor <= cor_vec[shift]);
+        if !is_peak {
+            continue;
+        }
+        let (lower_bound, upper_bound) = find_period_bounds(cor_vec, shift);
+        candidate_vec.push(CandidatePeriod {
+            period: shift,
+            autocor: cor_vec[shift],
+            rise: cor_vec[shift] - valley,
+            lower_bound,
+            upper_bound,
+        });
+    }
+    candidate_vec.sort_by(|a, b| b.rise.partial_cmp(&a.rise).unwrap());
+    candidate_vec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Segments of 100 windows at each ratio, with a small stddev.
+    fn build_segment_vec(ratio_vec: &[f64]) -> Vec<InferSegment> {
+        ratio_vec.iter().enumerate().map(|(index, ratio)| InferSegment {
+            chr: format!("chr{}", index + 1),
+            start: 0,
+            end: 1000000,
+            ratio: *ratio,
+            stddev: 0.02,
+            no_of_windows: 100,
+            no_of_snps: 0,
+            maf_mean: 0.0,
+            maf_stddev: 0.0,
+            snp_coverage_mean: 0.0,
+        }).collect()
+    }
+
+    #[test]
+    fn top_period_is_the_peak_spacing() {
+        for &spacing in [0.3, 0.45].iter() {
+            let ratio_vec: Vec<f64> = (0..5).map(|i| 0.4 + i as f64 * spacing).filter(|ratio| *ratio < MAX_RATIO)
+                .collect();
+            let cor_vec = calc_autocor(&build_ratio_histogram(&build_segment_vec(&ratio_vec)));
+            assert_eq!(cor_vec.len(), PERIOD_MAX + 1);
+            let candidate_vec = rank_candidate_periods(&cor_vec);
+            let expected_period = (spacing * RESOLUTION as f64).round() as usize;
+            let best = &candidate_vec[0];
+            assert!((best.period as i64 - expected_period as i64).abs() <= 2, "period {} for spacing {}",
+                    best.period, spacing);
+            assert!(best.lower_bound < best.period && best.period < best.upper_bound);
+            assert!(best.period - best.lower_bound >= PERIOD_HALF_WIDTH_MAX &&
+                best.upper_bound - best.period >= PERIOD_HALF_WIDTH_MAX);
+            //the peak at twice the spacing, if any, is ranked lower.
+            assert!(candidate_vec.iter().skip(1).all(|candidate| candidate.rise <= best.rise));
+        }
+    }
+
+    #[test]
+    fn period_bounds_stay_within_the_range() {
+        let cor_vec = vec![1.0; PERIOD_MAX + 1];
+        assert_eq!(find_period_bounds(&cor_vec, PERIOD_MIN), (PERIOD_MIN, PERIOD_MAX));
+        assert_eq!(find_period_bounds(&cor_vec, PERIOD_MAX), (PERIOD_MIN, PERIOD_MAX));
+        //a sharp peak still gets PERIOD_HALF_WIDTH_MAX on the side away from the edge.
+        let mut cor_vec = vec![0.0; PERIOD_MAX + 1];
+        cor_vec[PERIOD_MIN] = 1.0;
+        cor_vec[PERIOD_MAX] = 1.0;
+        assert_eq!(find_period_bounds(&cor_vec, PERIOD_MIN), (PERIOD_MIN, PERIOD_MIN + PERIOD_HALF_WIDTH_MAX));
+        assert_eq!(find_period_bounds(&cor_vec, PERIOD_MAX), (PERIOD_MAX - PERIOD_HALF_WIDTH_MAX, PERIOD_MAX));
+    }
+}
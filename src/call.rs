@@ -0,0 +1,204 @@
+use std::f64;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path};
+
+use infer;
+use infer::InferSegment;
+use maf_model::{calc_folded_maf, clamp_purity, expected_ratio, CopyState};
+use roh;
+use wgd;
+
+
+//a segment whose float copy number is further than this from its called total copy number is a non-integer fit.
+pub const INTEGER_FIT_TOLERANCE: f64 = 0.2;
+//total copy numbers up to this many above the float copy number are tried.
+const EXTRA_CN_TO_TRY: usize = 2;
+//a segment with more than this fraction within germline runs of homozygosity gets no allele-specific call.
+pub const GERMLINE_ROH_FRACTION_MAX: f64 = 0.5;
+
+/// Allele-specific copy number call of one segment.
+pub struct SegmentCall {
+    pub copy_state: CopyState,
+    //the total copy number that fits the coverage ratio exactly
+    pub copy_no_float: f64,
+    pub expected_ratio: f64,
+    //folded at the mean SNP depth of the segment. NaN if the segment has no SNPs.
+    pub expected_maf: f64,
+    pub log_likelihood: f64,
+    //posterior probability of copy_state among all states tried, under a flat prior
+    pub confidence: f64,
+    //fraction of tumor cells carrying copy_state against a background of copy number 2. 1 for integer fits.
+    //NaN if copy_state has total copy number 2 and the fit is not integer.
+    pub cancer_cell_fraction: f64,
+}
+
+/// NaN as NA, like other outputs.
+pub fn format_float(value: f64) -> String {
+    if value.is_nan() { "NA".to_string() } else { format!("{:.4}", value) }
+}
+
+/// Float total copy number whose expected coverage ratio is ratio.
+pub fn calc_copy_no_float(ratio: f64, purity: f64, ploidy: f64) -> f64 {
+    (ratio * (2.0 * (1.0 - purity) + ploidy * purity) - 2.0 * (1.0 - purity)) / purity
+}
+
+/// Log-likelihood of a segment whose expected coverage ratio is ratio and expected (folded) MAF is maf.
+/// maf is ignored if the segment has no SNPs. The same terms as in the purity/ploidy search of infer.
+pub fn calc_segment_log_likelihood(segment: &InferSegment, ratio: f64, maf: f64) -> f64 {
+    let ratio_log_likelihood = infer::calc_ratio_log_likelihood(segment, ratio);
+    if segment.no_of_snps == 0 {
+        ratio_log_likelihood
+    } else {
+        ratio_log_likelihood + infer::calc_maf_log_likelihood(segment, maf)
+    }
+}
+
+/// Maximum-likelihood (total, minor) copy number state of a segment from its coverage ratio and, if it has
+/// SNPs, their MAF.
+pub fn call_segment(segment: &InferSegment, purity: f64, ploidy: f64) -> SegmentCall {
+    let copy_no_float = calc_copy_no_float(segment.ratio, purity, ploidy);
+    let max_total_cn = copy_no_float.max(0.0).round() as usize + EXTRA_CN_TO_TRY;
+    let depth = segment.snp_coverage_mean.round() as usize;
+    //(copy state, log-likelihood, expected maf)
+    let mut state_vec: Vec<(CopyState, f64, f64)> = Vec::new();
+    for total_cn in 0..(max_total_cn + 1) {
+        for minor_cn in 0..(total_cn / 2 + 1) {
+            let copy_state = CopyState::new(total_cn, minor_cn);
+            let (ratio, maf) = copy_state.expected_ratio_maf(purity, ploidy, 0);
+            let maf = if segment.no_of_snps == 0 { f64::NAN } else { calc_folded_maf(maf, depth) };
+            state_vec.push((copy_state, calc_segment_log_likelihood(segment, ratio, maf), maf));
+        }
+    }
+    let &(copy_state, log_likelihood, expected_maf) = state_vec.iter()
+        .fold(None, |best: Option<&(CopyState, f64, f64)>, state| match best {
+            Some(best) if best.1 >= state.1 => Some(best),
+            _ => Some(state),
+        }).unwrap();
+    let likelihood_sum = state_vec.iter().fold(0f64, |sum, state| sum + (state.1 - log_likelihood).exp());
+    let cancer_cell_fraction = if (copy_no_float - copy_state.total_cn as f64).abs() <= INTEGER_FIT_TOLERANCE {
+        1.0
+    } else if copy_state.total_cn == 2 {
+        f64::NAN
+    } else {
+        ((copy_no_float - 2.0) / (copy_state.total_cn as f64 - 2.0)).max(0.0).min(1.0)
+    };
+    SegmentCall {
+        copy_state,
+        copy_no_float,
+        expected_ratio: expected_ratio(purity, ploidy, copy_state.total_cn),
+        expected_maf,
+        log_likelihood,
+        confidence: 1.0 / likelihood_sum,
+        cancer_cell_fraction,
+    }
+}
+
+/// Call absolute allele-specific copy numbers of autosomal segments given purity and ploidy, which come from
+/// infer.out.tsv or the user. Output is one line per segment, like cnv.output.tsv of the C++ infer.
+pub struct Call<'a> {
+    segment_file_path: &'a Path,
+    het_snp_file_path: &'a Path,
+    output_file_path: &'a Path,
+    purity: f64,
+    ploidy: f64,
+    segment_stddev_divider: f64,
+    snp_coverage_min: usize,
+    roh_file_path: Option<&'a Path>,
+}
+
+impl<'a> Call<'a> {
+    pub fn new(segment_file_path: &'a str,
+               het_snp_file_path: &'a str,
+               output_file_path: &'a str,
+               purity: f64,
+               ploidy: f64,
+               segment_stddev_divider: f64,
+               snp_coverage_min: usize,
+    ) -> Call<'a> {
+        if purity <= 0.0 || purity > 1.0 {
+            panic!("purity {} is not within (0, 1].", purity);
+        }
+        if ploidy <= 0.0 {
+            panic!("ploidy {} is not positive.", ploidy);
+        }
+        Call {
+            segment_file_path: Path::new(segment_file_path),
+            het_snp_file_path: Path::new(het_snp_file_path),
+            output_file_path: Path::new(output_file_path),
+            purity: clamp_purity(purity),
+            ploidy,
+            segment_stddev_divider,
+            snp_coverage_min,
+            roh_file_path: None,
+        }
+    }
+
+    /// Runs of homozygosity of the normal (select_het_snp --roh_output). They hold almost no hets, and a segment
+    /// without hets would be called LOH for lack of evidence. Segments mostly within them get NA as
+    /// allele-specific copy numbers and are left out of whole-genome-doubling detection.
+    pub fn set_roh_file(&mut self, roh_file_path: &'a str) {
+        self.roh_file_path = Some(Path::new(roh_file_path));
+    }
+
+    pub fn run(&self) {
+        println_stderr!("Calling copy numbers at purity={}, ploidy={} ...", self.purity, self.ploidy);
+        let (mut segment_vec, _) = infer::read_segment_file(self.segment_file_path, self.segment_stddev_divider,
+                                                            f64::MAX);
+        let het_snp_data = infer::read_het_snp_file(self.het_snp_file_path, self.snp_coverage_min);
+        infer::assign_snps_to_segments(&mut segment_vec, &het_snp_data);
+        let roh_segment_vec = self.roh_file_path.map(|roh_file_path| roh::read_roh_bed(roh_file_path));
+
+        let mut output_f = File::create(self.output_file_path)
+            .expect(&format!("Error in creating output file {:?}", self.output_file_path));
+        output_f.write_fmt(format_args!("#purity={}, ploidy={}\n", self.purity, self.ploidy)).unwrap();
+        output_f.write_fmt(format_args!("chr\tstart\tend\tcp\tmajor_allele_cp\tminor_allele_cp\tcopy_no_float\t\
+            ratio\tratio_stddev\tno_of_windows\tno_of_snps\tmaf_mean\tmaf_stddev\tratio_expected\tmaf_expected\t\
+            logL\tconfidence\tcancer_cell_fraction\tgermline_roh_fraction\n")).unwrap();
+        let mut no_of_non_integer_segments = 0usize;
+        let mut no_of_roh_segments = 0usize;
+        let mut no_of_snp_less_segments = 0usize;
+        let mut weight_copy_state_vec: Vec<(f64, CopyState)> = Vec::with_capacity(segment_vec.len());
+        for segment in segment_vec.iter() {
+            let segment_call = call_segment(segment, self.purity, self.ploidy);
+            //segment start and end are 1-based starts of its first and last windows.
+            let germline_roh_fraction = match roh_segment_vec {
+                Some(ref roh_segment_vec) => roh::calc_roh_fraction(roh_segment_vec, &segment.chr, segment.start - 1,
+                                                                    segment.end),
+                None => f64::NAN,
+            };
+            //without SNPs (or with too few to be assigned any), all minor copy numbers fit equally and the call
+            //would default to LOH.
+            let (major_cn, minor_cn) = if germline_roh_fraction > GERMLINE_ROH_FRACTION_MAX {
+                no_of_roh_segments += 1;
+                ("NA".to_string(), "NA".to_string())
+            } else if segment.no_of_snps == 0 {
+                no_of_snp_less_segments += 1;
+                ("NA".to_string(), "NA".to_string())
+            } else {
+                weight_copy_state_vec.push((segment.no_of_windows as f64, segment_call.copy_state));
+                (segment_call.copy_state.major_cn().to_string(), segment_call.copy_state.minor_cn.to_string())
+            };
+            if segment_call.cancer_cell_fraction != 1.0 {
+                no_of_non_integer_segments += 1;
+            }
+            output_f.write_fmt(format_args!("{}\t{}\t{}\t{}\t{}\t{}\t{:.4}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.4}\t{}\t{}\t{:.4}\t{}\t{}\n",
+                                            segment.chr, segment.start, segment.end,
+                                            segment_call.copy_state.total_cn, major_cn, minor_cn,
+                                            segment_call.copy_no_float,
+                                            segment.ratio, segment.stddev, segment.no_of_windows, segment.no_of_snps,
+                                            segment.maf_mean, segment.maf_stddev, segment_call.expected_ratio,
+                                            format_float(segment_call.expected_maf), segment_call.log_likelihood,
+                                            segment_call.confidence,
+                                            format_float(segment_call.cancer_cell_fraction),
+                                            format_float(germline_roh_fraction))).unwrap();
+        }
+        println_stderr!("{} segments called, {} with a non-integer fit, {} within germline runs of homozygosity, \
+                        {} without SNPs. Output to {:?}.", segment_vec.len(), no_of_non_integer_segments,
+                        no_of_roh_segments, no_of_snp_less_segments, self.output_file_path);
+        let wgd_status = wgd::detect_wgd(&weight_copy_state_vec, self.ploidy);
+        println_stderr!("Whole-genome doubling: {}, {} doublings. Fraction with major copy number >= 2: {}, LOH: {}.",
+                        wgd_status.is_wgd, wgd_status.no_of_doublings, wgd_status.fraction_major_cn_ge_2,
+                        wgd_status.loh_fraction);
+    }
+}
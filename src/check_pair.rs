@@ -0,0 +1,201 @@
+use rust_htslib::bcf;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path};
+
+use calc_median_usize;
+use genotype::{classify_genotype, GenotypeClass};
+
+
+//fewer homozygous sites than this are too few to tell whether tumor and normal come from the same individual.
+const MIN_NO_OF_HOM_HOM: usize = 100;
+
+/// numerator/denominator, None if denominator is 0.
+fn calc_fraction(numerator: usize, denominator: usize) -> Option<f32> {
+    if denominator == 0 { None } else { Some(numerator as f32 / denominator as f32) }
+}
+
+/// None as NA, like other outputs.
+fn format_option<T: ToString>(value: Option<T>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "NA".to_string(),
+    }
+}
+
+/// Genotype concordance between a tumor and its matched normal, to catch swapped or mislabelled pairs.
+/// The test is on sites homozygous in both: tumor LOH turns hets into homs but can not turn one homozygote
+/// into the opposite one, whereas about 10% of such sites differ between unrelated individuals.
+/// VCFs from freebayes without --report-monomorphic have almost no hom-ref records, so a normal hom-alt site
+/// absent from the tumor VCF counts as tumor hom-ref, provided the tumor is well covered (median FORMAT/DP of
+/// its SNPs at least min_coverage) and the site is not one of its low-coverage records.
+/// The fraction of normal hets that are homozygous in the tumor is reported too, but only for information,
+/// as tumor LOH raises it as much as a swap does.
+pub struct CheckPair<'a> {
+    snp_file_path_tumor: &'a Path,
+    snp_file_path_normal: &'a Path,
+    output_file_path: &'a Path,
+    min_coverage: usize,
+    max_hom_opposite_fraction: f32,
+}
+
+impl<'a> CheckPair<'a> {
+    pub fn new(snp_file_path_tumor: &'a str,
+               snp_file_path_normal: &'a str,
+               output_file_path: &'a str,
+               min_coverage: usize,
+               max_hom_opposite_fraction: f32,
+    ) -> CheckPair<'a> {
+        CheckPair {
+            snp_file_path_tumor: Path::new(snp_file_path_tumor),
+            snp_file_path_normal: Path::new(snp_file_path_normal),
+            output_file_path: Path::new(output_file_path),
+            min_coverage,
+            max_hom_opposite_fraction,
+        }
+    }
+
+    /// Genotypes of SNP records of the first sample with FORMAT/DP at least min_coverage (records without DP
+    /// are kept), the sites of other SNP records, i.e. with low coverage or a missing genotype,
+    /// and the median FORMAT/DP of all SNP records (None if none has DP).
+    fn read_in_genotypes(&self, snp_file_path: &Path) -> (HashMap<(String, u64), GenotypeClass>,
+                                                          HashSet<(String, u64)>, Option<usize>) {
+        println_stderr!("Reading genotypes from {:?} ...", snp_file_path);
+        let mut vcf = bcf::Reader::from_path(&snp_file_path).ok().expect("Error opening file.");
+        let vcf_header = vcf.header().clone();
+        let mut chr_pos2genotype: HashMap<(String, u64), GenotypeClass> = HashMap::new();
+        let mut uncalled_chr_pos_set: HashSet<(String, u64)> = HashSet::new();
+        let mut depth_vec: Vec<usize> = Vec::new();
+        for rec in vcf.records() {
+            let mut record = rec.ok().expect("Error reading record.");
+            if record.alleles().iter().any(|allele| allele.len() != 1) {
+                continue;
+            }
+            let chr = String::from_utf8_lossy(vcf_header.rid2name(record.rid().expect("Error reading rid."))).to_string();
+            if let Ok(dp_vec) = record.format(b"DP").integer() {
+                if dp_vec[0][0] >= 0 {
+                    depth_vec.push(dp_vec[0][0] as usize);
+                }
+                if dp_vec[0][0] >= 0 && (dp_vec[0][0] as usize) < self.min_coverage {
+                    uncalled_chr_pos_set.insert((chr, record.pos() as u64));
+                    continue;
+                }
+            }
+            let genotype_class: GenotypeClass;
+            {
+                let genotypes = record.genotypes().expect("Error reading genotypes");
+                genotype_class = classify_genotype(&genotypes.get(0)).class;
+            }
+            if genotype_class == GenotypeClass::Missing {
+                uncalled_chr_pos_set.insert((chr, record.pos() as u64));
+                continue;
+            }
+            chr_pos2genotype.insert((chr, record.pos() as u64), genotype_class);
+        }
+        let median_depth = if depth_vec.is_empty() { None } else { Some(calc_median_usize(&mut depth_vec)) };
+        println_stderr!("{} genotyped SNPs, {} more with low coverage or no genotype. Median depth {}.",
+                        chr_pos2genotype.len(), uncalled_chr_pos_set.len(), format_option(median_depth));
+        (chr_pos2genotype, uncalled_chr_pos_set, median_depth)
+    }
+
+    pub fn run(&self) {
+        let (chr_pos2genotype_tumor, uncalled_chr_pos_set_tumor, median_depth_tumor) =
+            self.read_in_genotypes(self.snp_file_path_tumor);
+        let (chr_pos2genotype_normal, _, _) = self.read_in_genotypes(self.snp_file_path_normal);
+        //a site absent from the tumor VCF is hom-ref in the tumor only if the tumor has reads there.
+        let is_absent_hom_ref = median_depth_tumor.map_or(false, |depth| depth >= self.min_coverage);
+        if !is_absent_hom_ref {
+            println_stderr!("WARNING: median depth {} of tumor SNPs is below {} or unknown. Sites absent from the \
+                    tumor VCF are not taken as hom-ref.", format_option(median_depth_tumor), self.min_coverage);
+        }
+        let mut no_of_common_sites = 0usize;
+        let mut no_of_concordant = 0usize;
+        //sites homozygous in both samples, and those among them homozygous for different alleles.
+        let mut no_of_hom_hom = 0usize;
+        let mut no_of_hom_opposite = 0usize;
+        //normal hets not uncalled in the tumor, and those absent from the tumor VCF or homozygous in it.
+        let mut no_of_normal_hets = 0usize;
+        let mut no_of_normal_het_tumor_hom = 0usize;
+        for (chr_pos, genotype_normal) in chr_pos2genotype_normal.iter() {
+            //no variant called in the tumor, i.e. hom-ref, unless the tumor has too few reads there.
+            let (genotype_tumor, is_absent) = match chr_pos2genotype_tumor.get(chr_pos) {
+                Some(genotype_tumor) => (*genotype_tumor, false),
+                None if uncalled_chr_pos_set_tumor.contains(chr_pos) => continue,
+                None => (GenotypeClass::HomRef, true),
+            };
+            if !is_absent {
+                no_of_common_sites += 1;
+                if genotype_tumor == *genotype_normal {
+                    no_of_concordant += 1;
+                }
+            }
+            match (*genotype_normal, genotype_tumor) {
+                (GenotypeClass::Het(_, _), GenotypeClass::HomRef) |
+                (GenotypeClass::Het(_, _), GenotypeClass::HomAlt(_)) => {
+                    no_of_normal_hets += 1;
+                    no_of_normal_het_tumor_hom += 1;
+                },
+                (GenotypeClass::Het(_, _), _) => no_of_normal_hets += 1,
+                _ if is_absent && !is_absent_hom_ref => {},
+                (GenotypeClass::HomRef, GenotypeClass::HomRef) => no_of_hom_hom += 1,
+                (GenotypeClass::HomRef, GenotypeClass::HomAlt(_)) |
+                (GenotypeClass::HomAlt(_), GenotypeClass::HomRef) => {
+                    no_of_hom_hom += 1;
+                    no_of_hom_opposite += 1;
+                },
+                (GenotypeClass::HomAlt(allele_normal), GenotypeClass::HomAlt(allele_tumor)) => {
+                    no_of_hom_hom += 1;
+                    if allele_normal != allele_tumor {
+                        no_of_hom_opposite += 1;
+                    }
+                },
+                _ => {},
+            }
+        }
+        let concordance = calc_fraction(no_of_concordant, no_of_common_sites);
+        let hom_opposite_fraction = calc_fraction(no_of_hom_opposite, no_of_hom_hom);
+        let normal_het_tumor_hom_fraction = calc_fraction(no_of_normal_het_tumor_hom, no_of_normal_hets);
+        //None if there are too few homozygous sites to tell.
+        let is_same_individual: Option<bool> = if no_of_hom_hom < MIN_NO_OF_HOM_HOM {
+            None
+        } else {
+            hom_opposite_fraction.map(|fraction| fraction <= self.max_hom_opposite_fraction)
+        };
+        println_stderr!("{} common SNPs. concordance={}, hom_opposite_fraction={} ({} out of {}), \
+                normal_het_tumor_hom_fraction={} ({} out of {}).", no_of_common_sites, format_option(concordance),
+                        format_option(hom_opposite_fraction), no_of_hom_opposite, no_of_hom_hom,
+                        format_option(normal_het_tumor_hom_fraction), no_of_normal_het_tumor_hom, no_of_normal_hets);
+        match is_same_individual {
+            Some(true) => {},
+            Some(false) => println_stderr!("WARNING: hom_opposite_fraction {} > {}. Tumor {:?} and normal {:?} \
+                    may come from different individuals.", format_option(hom_opposite_fraction),
+                                           self.max_hom_opposite_fraction, self.snp_file_path_tumor,
+                                           self.snp_file_path_normal),
+            None => println_stderr!("WARNING: insufficient sites. {} sites homozygous in both < {}. Can not tell \
+                    whether tumor {:?} and normal {:?} come from the same individual.", no_of_hom_hom,
+                                    MIN_NO_OF_HOM_HOM, self.snp_file_path_tumor, self.snp_file_path_normal),
+        }
+
+        let mut output_f = File::create(&self.output_file_path)
+            .expect(&format!("Error in creating output file {:?}", &self.output_file_path));
+        output_f.write_fmt(format_args!("#min_coverage={}, max_hom_opposite_fraction={}, tumor_median_depth={}\n",
+                                        self.min_coverage, self.max_hom_opposite_fraction,
+                                        format_option(median_depth_tumor))).unwrap();
+        output_f.write_fmt(format_args!("#tumor snp:{:?}\n", &self.snp_file_path_tumor)).unwrap();
+        output_f.write_fmt(format_args!("#normal snp:{:?}\n", &self.snp_file_path_normal)).unwrap();
+        output_f.write_fmt(format_args!("no_of_tumor_snps\tno_of_normal_snps\tno_of_common_snps\tconcordance\t\
+                no_of_hom_hom\tno_of_hom_opposite\thom_opposite_fraction\t\
+                no_of_normal_hets\tno_of_normal_het_tumor_hom\tnormal_het_tumor_hom_fraction\tis_same_individual\n"))
+            .unwrap();
+        output_f.write_fmt(format_args!("{}\t{}\t{}\t{}\t\
+                {}\t{}\t{}\t\
+                {}\t{}\t{}\t{}\n",
+                                        chr_pos2genotype_tumor.len(), chr_pos2genotype_normal.len(),
+                                        no_of_common_sites, format_option(concordance),
+                                        no_of_hom_hom, no_of_hom_opposite, format_option(hom_opposite_fraction),
+                                        no_of_normal_hets, no_of_normal_het_tumor_hom,
+                                        format_option(normal_het_tumor_hom_fraction), format_option(is_same_individual))
+        ).unwrap();
+    }
+}
@@ -0,0 +1,416 @@
+use flate2;
+use flate2::Compression;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::{Path};
+
+use contamination;
+use contamination::ContaminationProfile;
+use haplotype_block;
+use het_snp_vcf::{HetSNPVcfRecord, HetSNPVcfWriter};
+use ref_bias::RefBias;
+use roh;
+use tumor_in_normal;
+
+
+//header lines of select_het_snp output that hold "key=value, ..." filter settings, which go into the VCF header
+const SETTING_LINE_PREFIX_ARRAY: [&str; 3] = ["#abp_max_tumor=", "#known_sites=", "#normal_beta_binomial_rho="];
+
+pub struct ConcatHetSNP<'a> {
+    input_file_path_vec: Vec<&'a Path>,
+    output_file_path: &'a Path,
+    contamination_qc_path: Option<&'a Path>,
+    phase_block_input_path_vec: Vec<&'a Path>,
+    phase_block_output_path: Option<&'a Path>,
+    roh_input_path_vec: Vec<&'a Path>,
+    roh_output_path: Option<&'a Path>,
+    vcf_output_path: Option<&'a Path>,
+}
+
+/// Column indices of a select_het_snp output, looked up by name in its column header.
+struct ColumnIndex {
+    chr: usize,
+    pos: usize,
+    tumor_maf_normalized: usize,
+    tumor_maf: usize,
+    tumor_ro: usize,
+    tumor_ao: usize,
+    normal_maf: usize,
+    normal_ro: usize,
+    normal_ao: usize,
+    tumor_baf: usize,
+    tumor_baf_corrected: usize,
+    record_ref_allele: usize,
+    allele_1: usize,
+    allele_2: usize,
+    is_multi_allelic_het: usize,
+}
+
+impl ColumnIndex {
+    fn new(column_header: &str) -> ColumnIndex {
+        let column_name_vec: Vec<&str> = column_header.split('\t').collect();
+        let index_of = |column_name: &str| -> usize {
+            column_name_vec.iter().position(|name| *name == column_name)
+                .expect(&format!("No column {} in the header {}. Is the input from select_het_snp of this version?",
+                                 column_name, column_header))
+        };
+        ColumnIndex {
+            chr: index_of("chr"),
+            pos: index_of("pos"),
+            tumor_maf_normalized: index_of("tumor_maf_normalized"),
+            tumor_maf: index_of("tumor_maf"),
+            tumor_ro: index_of("tumor_ro"),
+            tumor_ao: index_of("tumor_ao"),
+            normal_maf: index_of("normal_maf"),
+            normal_ro: index_of("normal_ro"),
+            normal_ao: index_of("normal_ao"),
+            tumor_baf: index_of("tumor_baf"),
+            tumor_baf_corrected: index_of("tumor_baf_corrected"),
+            record_ref_allele: index_of("record_ref_allele"),
+            allele_1: index_of("allele_1"),
+            allele_2: index_of("allele_2"),
+            is_multi_allelic_het: index_of("is_multi_allelic_het"),
+        }
+    }
+}
+
+/// "key=value" fields of a comment line, e.g. "#tumor_in_normal_fraction=0.01, no_of_loh_blocks=3, ...".
+fn parse_comment_field_vec(line: &str) -> Vec<(String, String)> {
+    let line = if line.starts_with('#') { &line[1..] } else { line };
+    line.split(", ").map(|field| {
+        let mut key_value = field.splitn(2, '=');
+        let key = key_value.next().unwrap().to_string();
+        let value = key_value.next().expect(&format!("Error parsing {} in {}", field, line)).to_string();
+        (key, value)
+    }).collect()
+}
+
+impl<'a> ConcatHetSNP<'a> {
+    pub fn new(input_file_path_vec: &[&'a str], output_file_path: &'a str) -> ConcatHetSNP<'a> {
+        ConcatHetSNP {
+            input_file_path_vec: input_file_path_vec.iter().map(|input_file_path| Path::new(*input_file_path)).collect(),
+            output_file_path: Path::new(output_file_path),
+            contamination_qc_path: None,
+            phase_block_input_path_vec: Vec::new(),
+            phase_block_output_path: None,
+            roh_input_path_vec: Vec::new(),
+            roh_output_path: None,
+            vcf_output_path: None,
+        }
+    }
+
+    /// Re-estimate contamination from the profiles of all shards (select_het_snp --contamination_qc) and write
+    /// it into this QC file, as well as into the output header.
+    pub fn set_contamination_qc(&mut self, contamination_qc_path: &'a str) {
+        self.contamination_qc_path = Some(Path::new(contamination_qc_path));
+    }
+
+    /// Merge --phase_block_output of all shards. Blocks of a phase set split across shards are joined.
+    pub fn set_phase_block(&mut self, phase_block_input_path_vec: &[&'a str], phase_block_output_path: &'a str) {
+        self.phase_block_input_path_vec = phase_block_input_path_vec.iter().map(|path| Path::new(*path)).collect();
+        self.phase_block_output_path = Some(Path::new(phase_block_output_path));
+    }
+
+    /// Find runs of homozygosity of the normal from the genotyped sites of all shards (--roh_output of
+    /// select_het_snp --region) and write them into a BED.
+    pub fn set_roh(&mut self, roh_input_path_vec: &[&'a str], roh_output_path: &'a str) {
+        self.roh_input_path_vec = roh_input_path_vec.iter().map(|path| Path::new(*path)).collect();
+        self.roh_output_path = Some(Path::new(roh_output_path));
+    }
+
+    /// Write the merged hets into a VCF as select_het_snp --vcf_output does.
+    pub fn set_vcf_output(&mut self, vcf_output_path: &'a str) {
+        self.vcf_output_path = Some(Path::new(vcf_output_path));
+    }
+
+    /// Concatenate het SNP outputs of select_het_snp run on disjoint regions (shards), in the given order.
+    /// Comment lines of the form "#<label>: <integer>" are summed over shards.
+    /// The tumor-in-normal fraction is re-computed from the sums of its numerator and denominator over shards.
+    /// LOH blocks of its estimate do not span shards, so it can differ slightly from that of a whole-genome run.
+    /// Contamination is re-estimated from the summed log likelihood profiles of shards.
+    /// The ref bias is re-estimated from the summed per-depth counts of normal hets, as a whole-genome run would,
+    /// and tumor_baf_corrected re-computed except for multi-allelic hets.
+    /// Other comment lines and the column header come from the first shard.
+    pub fn run(&self) {
+        //comment lines in the order of the first shard. Count lines carry a sum.
+        let mut comment_line_vec: Vec<(String, Option<usize>)> = Vec::new();
+        let mut column_header: Option<String> = None;
+        let mut data_line_vec: Vec<String> = Vec::new();
+        let mut no_of_loh_blocks = 0usize;
+        let mut no_of_loh_snps = 0usize;
+        let mut normal_excess_sum = 0f64;
+        let mut tumor_excess_sum = 0f64;
+        //(sample, profile summed over shards)
+        let mut sample_contamination_profile_vec: Vec<(String, ContaminationProfile)> = Vec::new();
+        for (shard_index, input_file_path) in self.input_file_path_vec.iter().enumerate() {
+            println_stderr!("Reading shard {:?} ...", input_file_path);
+            let input_f = File::open(input_file_path)
+                .expect(&format!("Error in opening file {:?}", input_file_path));
+            let reader = BufReader::new(flate2::read::MultiGzDecoder::new(input_f)
+                .expect("Error in opening gzipped file."));
+            let mut no_of_data_lines = 0usize;
+            for line in reader.lines() {
+                let line = line.expect("Error reading line.");
+                if line.starts_with("#tumor_in_normal_fraction=") {
+                    for (key, value) in parse_comment_field_vec(&line) {
+                        match key.as_str() {
+                            "no_of_loh_blocks" => no_of_loh_blocks += value.parse::<usize>().unwrap(),
+                            "no_of_loh_snps" => no_of_loh_snps += value.parse::<usize>().unwrap(),
+                            "normal_excess_sum" => normal_excess_sum += value.parse::<f64>().unwrap(),
+                            "tumor_excess_sum" => tumor_excess_sum += value.parse::<f64>().unwrap(),
+                            _ => {},
+                        }
+                    }
+                } else if line.starts_with("#tumor contamination_profile: ") ||
+                    line.starts_with("#normal contamination_profile: ") {
+                    let sample = line[1..line.find(' ').unwrap()].to_string();
+                    let profile = ContaminationProfile::from_comment_value(&line[line.find(": ").unwrap() + 2..]);
+                    match sample_contamination_profile_vec.iter().position(|&(ref other_sample, _)| *other_sample == sample) {
+                        Some(index) => sample_contamination_profile_vec[index].1.add(&profile),
+                        None => sample_contamination_profile_vec.push((sample, profile)),
+                    }
+                } else if line.starts_with('#') {
+                    let count: Option<usize> = match line.rfind(": ") {
+                        Some(index) => line[index + 2..].parse().ok(),
+                        None => None,
+                    };
+                    match count {
+                        Some(count) => {
+                            let label = &line[..line.rfind(": ").unwrap()];
+                            match comment_line_vec.iter().position(|&(ref comment_line, _)| comment_line == label) {
+                                Some(index) => {
+                                    if let Some(ref mut count_sum) = comment_line_vec[index].1 {
+                                        *count_sum += count;
+                                    }
+                                },
+                                None => comment_line_vec.push((label.to_string(), Some(count))),
+                            }
+                        },
+                        None => {
+                            if shard_index == 0 {
+                                comment_line_vec.push((line, None));
+                            }
+                        },
+                    }
+                } else if column_header.is_none() {
+                    column_header = Some(line);
+                } else if shard_index > 0 && no_of_data_lines == 0 && Some(&line) == column_header.as_ref() {
+                    continue;
+                } else {
+                    data_line_vec.push(line);
+                    no_of_data_lines += 1;
+                }
+            }
+            println_stderr!("{} SNPs.", no_of_data_lines);
+        }
+        let column_header = column_header.expect("No column header in the input files.");
+        let column_index = ColumnIndex::new(&column_header);
+
+        self.reestimate_contamination(&sample_contamination_profile_vec, &mut comment_line_vec);
+        self.reestimate_ref_bias(&column_index, &mut comment_line_vec, &mut data_line_vec);
+        if let Some(no_of_roh) = self.merge_roh() {
+            //where a whole-genome run writes it
+            let index = comment_line_vec.iter()
+                .position(|&(ref comment_line, _)| comment_line == "#normal no_of_allele_imbalanced")
+                .unwrap_or(comment_line_vec.len());
+            comment_line_vec.insert(index, ("#normal no_of_roh".to_string(), Some(no_of_roh)));
+        }
+
+        let output_f = File::create(&self.output_file_path)
+            .expect(&format!("Error in creating output file {:?}", &self.output_file_path));
+        let mut gz_writer = flate2::GzBuilder::new()
+            .filename(self.output_file_path.file_stem().unwrap().to_str().unwrap())
+            .comment("Comment")
+            .write(output_f, Compression::default());
+        gz_writer.write_fmt(format_args!("#concatenated from {} shards\n", self.input_file_path_vec.len())).unwrap();
+        for &(ref comment_line, count_sum) in comment_line_vec.iter() {
+            match count_sum {
+                Some(count_sum) => gz_writer.write_fmt(format_args!("{}: {}\n", comment_line, count_sum)).unwrap(),
+                None => gz_writer.write_fmt(format_args!("{}\n", comment_line)).unwrap(),
+            }
+        }
+        gz_writer.write_fmt(format_args!("{}\n", column_header)).unwrap();
+        for data_line in data_line_vec.iter() {
+            gz_writer.write_fmt(format_args!("{}\n", data_line)).unwrap();
+        }
+        if let Some(tumor_in_normal_fraction) =
+            tumor_in_normal::calc_tumor_in_normal_fraction(normal_excess_sum, tumor_excess_sum) {
+            gz_writer.write_fmt(format_args!("#tumor_in_normal_fraction={}, no_of_loh_blocks={}, no_of_loh_snps={}, \
+                    normal_excess_sum={}, tumor_excess_sum={}\n",
+                                             tumor_in_normal_fraction, no_of_loh_blocks, no_of_loh_snps,
+                                             normal_excess_sum, tumor_excess_sum)).unwrap();
+        }
+        gz_writer.finish()
+            .expect(&format!("ERROR finish() failure for gz_writer of {:?}.", &self.output_file_path));
+        println_stderr!("{} SNPs from {} shards written to {:?}.", data_line_vec.len(), self.input_file_path_vec.len(),
+                        self.output_file_path);
+
+        if let Some(vcf_output_path) = self.vcf_output_path {
+            output_vcf(vcf_output_path, &column_index, &comment_line_vec, &data_line_vec);
+        }
+        if let Some(phase_block_output_path) = self.phase_block_output_path {
+            let mut block_vec: Vec<haplotype_block::HaplotypeBlock> = Vec::new();
+            let mut no_of_phased_snps = 0usize;
+            for phase_block_input_path in self.phase_block_input_path_vec.iter() {
+                let (shard_block_vec, shard_no_of_phased_snps) = haplotype_block::read_blocks(phase_block_input_path);
+                block_vec.extend(shard_block_vec);
+                no_of_phased_snps += shard_no_of_phased_snps;
+            }
+            let block_vec = haplotype_block::merge_blocks(block_vec);
+            haplotype_block::output_blocks(&block_vec, phase_block_output_path, no_of_phased_snps);
+        }
+    }
+
+    /// Replace the per-shard "#<sample> contamination" and "#<sample> contamination_profile" lines by ones of the
+    /// summed profiles.
+    fn reestimate_contamination(&self, sample_contamination_profile_vec: &[(String, ContaminationProfile)],
+                                comment_line_vec: &mut Vec<(String, Option<usize>)>) {
+        if sample_contamination_profile_vec.is_empty() {
+            if self.contamination_qc_path.is_some() {
+                panic!("No contamination_profile line in the input files. \
+                        Run select_het_snp with --contamination_qc to estimate contamination.");
+            }
+            return;
+        }
+        let mut sample_estimate_vec: Vec<(&str, contamination::ContaminationEstimate)> = Vec::new();
+        for &(ref sample, ref profile) in sample_contamination_profile_vec.iter() {
+            let estimate = profile.estimate();
+            println_stderr!("Contamination of {}: {} re-estimated over all shards.", sample,
+                            contamination::format_estimate(&estimate));
+            //in place of the first shard's line, which precedes its profile line
+            let contamination_prefix = format!("#{} contamination: ", sample);
+            let index = comment_line_vec.iter()
+                .position(|&(ref comment_line, _)| comment_line.starts_with(&contamination_prefix))
+                .expect(&format!("No {}line in the first shard.", contamination_prefix));
+            comment_line_vec[index].0 = format!("{}{}", contamination_prefix, contamination::format_estimate(&estimate));
+            comment_line_vec.insert(index + 1, (format!("#{} contamination_profile: {}", sample,
+                                                        profile.to_comment_value()), None));
+            sample_estimate_vec.push((sample.as_str(), estimate));
+        }
+        if let Some(contamination_qc_path) = self.contamination_qc_path {
+            let estimate_of = |sample: &str| sample_estimate_vec.iter().find(|&&(other_sample, _)| other_sample == sample)
+                .map(|&(_, ref estimate)| estimate)
+                .expect(&format!("No {} contamination_profile line in the input files.", sample));
+            contamination::output_qc(contamination_qc_path, estimate_of("tumor"), estimate_of("normal"));
+        }
+    }
+
+    /// Re-estimate the ref bias from "#normal no_of_hets of depth D" and "#normal ref_obs of depth D" summed over
+    /// shards and re-compute tumor_baf_corrected with it.
+    fn reestimate_ref_bias(&self, column_index: &ColumnIndex, comment_line_vec: &mut Vec<(String, Option<usize>)>,
+                           data_line_vec: &mut Vec<String>) {
+        //depth => (number of hets, total ref count)
+        let mut ref_bias_depth2count: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+        for &(ref comment_line, count_sum) in comment_line_vec.iter() {
+            if let Some(count_sum) = count_sum {
+                if comment_line.starts_with("#normal no_of_hets of depth ") {
+                    let depth: usize = comment_line["#normal no_of_hets of depth ".len()..].parse().unwrap();
+                    ref_bias_depth2count.entry(depth).or_insert((0, 0)).0 += count_sum;
+                } else if comment_line.starts_with("#normal ref_obs of depth ") {
+                    let depth: usize = comment_line["#normal ref_obs of depth ".len()..].parse().unwrap();
+                    ref_bias_depth2count.entry(depth).or_insert((0, 0)).1 += count_sum;
+                }
+            }
+        }
+        if ref_bias_depth2count.is_empty() {
+            return;
+        }
+        let ref_bias = RefBias::estimate_from_depth_histogram(&ref_bias_depth2count);
+        println_stderr!("Ref bias re-estimated in {} depth bins from {} normal hets.", ref_bias.bin_max_depth_vec.len(),
+                        ref_bias.no_of_snps_vec.iter().sum::<usize>());
+        //in place of the first shard's lines
+        let index = comment_line_vec.iter()
+            .position(|&(ref comment_line, _)| comment_line.starts_with("#normal_ref_fraction depth<="))
+            .unwrap_or(comment_line_vec.len());
+        comment_line_vec.retain(|&(ref comment_line, _)| !comment_line.starts_with("#normal_ref_fraction depth<="));
+        for (i, bin_max_depth) in ref_bias.bin_max_depth_vec.iter().enumerate().rev() {
+            comment_line_vec.insert(index, (format!("#normal_ref_fraction depth<={}: {} ({} hets)", bin_max_depth,
+                                                    ref_bias.ref_fraction_vec[i], ref_bias.no_of_snps_vec[i]), None));
+        }
+        for data_line in data_line_vec.iter_mut() {
+            let mut field_vec: Vec<String> = data_line.split('\t').map(|field| field.to_string()).collect();
+            //written with tumor_baf_corrected = tumor_baf, as their first allele is not the reference.
+            if field_vec[column_index.is_multi_allelic_het] == "true" {
+                continue;
+            }
+            let normal_depth: usize = field_vec[column_index.normal_ro].parse::<usize>().unwrap() +
+                field_vec[column_index.normal_ao].parse::<usize>().unwrap();
+            let tumor_baf: f64 = field_vec[column_index.tumor_baf].parse().unwrap();
+            field_vec[column_index.tumor_baf_corrected] =
+                (1.0 - ref_bias.correct_ref_fraction(1.0 - tumor_baf, normal_depth)).to_string();
+            *data_line = field_vec.join("\t");
+        }
+    }
+
+    /// Find runs of homozygosity from the genotyped sites of all shards and write them. Return their number.
+    fn merge_roh(&self) -> Option<usize> {
+        let roh_output_path = match self.roh_output_path {
+            Some(roh_output_path) => roh_output_path,
+            None => return None,
+        };
+        let mut chr_site_vec: Vec<(String, Vec<(u64, bool)>)> = Vec::new();
+        let mut min_roh_len: Option<u64> = None;
+        for roh_input_path in self.roh_input_path_vec.iter() {
+            let shard_min_roh_len = roh::read_genotyped_sites(roh_input_path, &mut chr_site_vec);
+            if min_roh_len.map_or(false, |min_roh_len| min_roh_len != shard_min_roh_len) {
+                panic!("min_roh_len of {:?} is {}, different from that of previous shards, {}.", roh_input_path,
+                       shard_min_roh_len, min_roh_len.unwrap());
+            }
+            min_roh_len = Some(shard_min_roh_len);
+        }
+        let min_roh_len = min_roh_len.unwrap_or(0);
+        let roh_segment_vec = roh::find_roh(&chr_site_vec, min_roh_len);
+        roh::output_roh_bed(&roh_segment_vec, roh_output_path, min_roh_len);
+        Some(roh_segment_vec.len())
+    }
+}
+
+/// Write the merged hets into a VCF. Filter settings come from the header lines of the first shard.
+fn output_vcf(vcf_output_path: &Path, column_index: &ColumnIndex, comment_line_vec: &[(String, Option<usize>)],
+              data_line_vec: &[String]) {
+    let mut setting_vec: Vec<(String, String)> = Vec::new();
+    for &(ref comment_line, _) in comment_line_vec.iter() {
+        if SETTING_LINE_PREFIX_ARRAY.iter().any(|prefix| comment_line.starts_with(prefix)) {
+            setting_vec.extend(parse_comment_field_vec(comment_line));
+        }
+    }
+    for &(ref comment_line, _) in comment_line_vec.iter() {
+        if comment_line.starts_with("#tumor snp:") {
+            setting_vec.push(("snp_file_path_tumor".to_string(), comment_line["#tumor snp:".len()..].to_string()));
+        } else if comment_line.starts_with("#normal snp:") {
+            setting_vec.push(("snp_file_path_normal".to_string(), comment_line["#normal snp:".len()..].to_string()));
+        }
+    }
+    let field_vec_vec: Vec<Vec<&str>> = data_line_vec.iter().map(|data_line| data_line.split('\t').collect()).collect();
+    let mut chr_vec: Vec<String> = Vec::new();
+    for field_vec in field_vec_vec.iter() {
+        if chr_vec.last().map(|chr| chr.as_str()) != Some(field_vec[column_index.chr]) {
+            chr_vec.push(field_vec[column_index.chr].to_string());
+        }
+    }
+    let mut vcf_writer = HetSNPVcfWriter::new(vcf_output_path, &chr_vec, &setting_vec);
+    for field_vec in field_vec_vec.iter() {
+        let parse_error = |column_name: &str| format!("Error parsing {} in {}", column_name, field_vec.join("\t"));
+        vcf_writer.write(&HetSNPVcfRecord {
+            chr: field_vec[column_index.chr],
+            pos: field_vec[column_index.pos].parse().expect(&parse_error("pos")),
+            record_ref_allele: field_vec[column_index.record_ref_allele].as_bytes(),
+            allele_1: field_vec[column_index.allele_1].as_bytes(),
+            allele_2: field_vec[column_index.allele_2].as_bytes(),
+            tumor_ro: field_vec[column_index.tumor_ro].parse().expect(&parse_error("tumor_ro")),
+            tumor_ao: field_vec[column_index.tumor_ao].parse().expect(&parse_error("tumor_ao")),
+            normal_ro: field_vec[column_index.normal_ro].parse().expect(&parse_error("normal_ro")),
+            normal_ao: field_vec[column_index.normal_ao].parse().expect(&parse_error("normal_ao")),
+            tumor_maf: field_vec[column_index.tumor_maf].parse().expect(&parse_error("tumor_maf")),
+            normal_maf: field_vec[column_index.normal_maf].parse().expect(&parse_error("normal_maf")),
+            tumor_maf_normalized: field_vec[column_index.tumor_maf_normalized].parse()
+                .expect(&parse_error("tumor_maf_normalized")),
+            tumor_baf: field_vec[column_index.tumor_baf].parse().expect(&parse_error("tumor_baf")),
+            tumor_baf_corrected: field_vec[column_index.tumor_baf_corrected].parse()
+                .expect(&parse_error("tumor_baf_corrected")),
+        });
+    }
+    vcf_writer.finish();
+}
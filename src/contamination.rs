@@ -0,0 +1,372 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::f64;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path};
+
+use known_sites::KnownSites;
+
+
+//population AF is binned in steps of this, so that sites of the same depth, alt reads and AF bin are scored once.
+const AF_STEP: f64 = 0.001;
+//sites whose rarer allele has a population AF below this estimate the sequencing error rate, not contamination.
+const ERROR_SITE_MAX_AF: f64 = 0.01;
+//at such a site, reads of the rare allele above this fraction of the depth come from a real het, not errors.
+const ERROR_SITE_MAX_RARE_FRACTION: f64 = 0.2;
+//the log likelihood is tabulated at these contamination levels and error rates.
+pub const CONTAMINATION_MAX: f64 = 0.5;
+pub const CONTAMINATION_STEP: f64 = 0.005;
+pub const ERROR_RATE_MAX: f64 = 0.01;
+pub const ERROR_RATE_STEP: f64 = 0.002;
+//half the 0.95 quantile of chi-square(1), the drop of log likelihood at the bounds of the 95% confidence interval.
+const CI_LOG_LIKELIHOOD_DROP: f64 = 1.92;
+
+/// A biallelic SNP genotyped in one sample, whatever the genotype is. Reads of an allele the sample does not carry
+/// come from sequencing errors or foreign DNA.
+pub struct ContaminationSite {
+    pub chr: String,
+    pub pos: u64,
+    pub ref_allele: Vec<u8>,
+    pub alt_allele: Vec<u8>,
+    pub no_of_alt_obs: usize,
+    pub depth: usize,
+}
+
+pub struct ContaminationEstimate {
+    //maximum likelihood contamination and its 95% confidence interval. None without sites.
+    pub contamination: Option<f64>,
+    pub lower: Option<f64>,
+    pub upper: Option<f64>,
+    pub no_of_sites: usize,
+    pub error_rate: f64,
+    //whether error_rate comes from sites of rare alleles (true) or is fitted along with contamination (false).
+    pub is_error_rate_from_rare_sites: bool,
+}
+
+fn no_of_contamination_levels() -> usize {
+    (CONTAMINATION_MAX / CONTAMINATION_STEP).round() as usize + 1
+}
+
+fn no_of_error_rates() -> usize {
+    (ERROR_RATE_MAX / ERROR_RATE_STEP).round() as usize + 1
+}
+
+/// x*ln(y), 0 if x is 0 even if y is 0.
+fn x_ln_y(x: f64, y: f64) -> f64 {
+    if x == 0.0 { 0.0 } else { x * y.ln() }
+}
+
+/// ln P(alt_obs | depth) of a site whose sample and contaminant genotypes are unknown and in Hardy-Weinberg
+/// equilibrium at population_af. The binomial coefficient, the same at every contamination level, is left out.
+fn calc_site_log_likelihood(depth: usize, no_of_alt_obs: usize, population_af: f64, contamination: f64,
+                            error_rate: f64) -> f64 {
+    let af = population_af.max(AF_STEP).min(1.0 - AF_STEP);
+    let genotype_prior_array = [(1.0 - af) * (1.0 - af), 2.0 * af * (1.0 - af), af * af];
+    let mut ln_term_vec: Vec<f64> = Vec::with_capacity(9);
+    for sample_alt_count in 0..3 {
+        for contaminant_alt_count in 0..3 {
+            let alt_fraction = (1.0 - contamination) * sample_alt_count as f64 / 2.0 +
+                contamination * contaminant_alt_count as f64 / 2.0;
+            let observed_alt_fraction = alt_fraction * (1.0 - error_rate) + (1.0 - alt_fraction) * error_rate;
+            ln_term_vec.push(genotype_prior_array[sample_alt_count].ln() +
+                genotype_prior_array[contaminant_alt_count].ln() +
+                x_ln_y(no_of_alt_obs as f64, observed_alt_fraction) +
+                x_ln_y((depth - no_of_alt_obs) as f64, 1.0 - observed_alt_fraction));
+        }
+    }
+    let max_ln_term = ln_term_vec.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if max_ln_term == f64::NEG_INFINITY {
+        return max_ln_term;
+    }
+    max_ln_term + ln_term_vec.iter().map(|ln_term| (ln_term - max_ln_term).exp()).sum::<f64>().ln()
+}
+
+/// Log likelihood of contamination levels at every error rate, summed over sites, and the read counts of
+/// rare-allele sites that estimate the error rate. All are sums over sites.
+pub struct ContaminationProfile {
+    pub no_of_sites: usize,
+    pub no_of_error_obs: usize,
+    pub error_depth: usize,
+    //[error rate index][contamination level index]
+    pub log_likelihood_grid: Vec<Vec<f64>>,
+}
+
+impl ContaminationProfile {
+    /// From the number of sites of each (depth, alt reads, population AF bin).
+    pub fn from_site_count(depth_alt_af_bin2count: &HashMap<(usize, usize, usize), usize>) -> ContaminationProfile {
+        let mut profile = ContaminationProfile {
+            no_of_sites: 0,
+            no_of_error_obs: 0,
+            error_depth: 0,
+            log_likelihood_grid: vec![vec![0f64; no_of_contamination_levels()]; no_of_error_rates()],
+        };
+        for (&(depth, no_of_alt_obs, af_bin), &count) in depth_alt_af_bin2count.iter() {
+            let population_af = af_bin as f64 * AF_STEP;
+            if population_af < ERROR_SITE_MAX_AF || population_af > 1.0 - ERROR_SITE_MAX_AF {
+                let no_of_rare_obs = if population_af < 0.5 { no_of_alt_obs } else { depth - no_of_alt_obs };
+                if no_of_rare_obs as f64 <= ERROR_SITE_MAX_RARE_FRACTION * depth as f64 {
+                    profile.no_of_error_obs += count * no_of_rare_obs;
+                    profile.error_depth += count * depth;
+                }
+                continue;
+            }
+            profile.no_of_sites += count;
+            for (error_index, log_likelihood_vec) in profile.log_likelihood_grid.iter_mut().enumerate() {
+                for (contamination_index, log_likelihood) in log_likelihood_vec.iter_mut().enumerate() {
+                    *log_likelihood += count as f64 * calc_site_log_likelihood(
+                        depth, no_of_alt_obs, population_af, contamination_index as f64 * CONTAMINATION_STEP,
+                        error_index as f64 * ERROR_RATE_STEP);
+                }
+            }
+        }
+        profile
+    }
+
+    /// Add the sums of another profile, e.g. of another shard of the genome.
+    pub fn add(&mut self, other: &ContaminationProfile) {
+        self.no_of_sites += other.no_of_sites;
+        self.no_of_error_obs += other.no_of_error_obs;
+        self.error_depth += other.error_depth;
+        for (log_likelihood_vec, other_log_likelihood_vec) in self.log_likelihood_grid.iter_mut()
+            .zip(other.log_likelihood_grid.iter()) {
+            for (log_likelihood, other_log_likelihood) in log_likelihood_vec.iter_mut()
+                .zip(other_log_likelihood_vec.iter()) {
+                *log_likelihood += *other_log_likelihood;
+            }
+        }
+    }
+
+    /// "no_of_sites=N, no_of_error_obs=N, error_depth=N, log_likelihood=..." for a comment line, with
+    /// log likelihoods comma-separated within an error rate and error rates separated by ';'.
+    pub fn to_comment_value(&self) -> String {
+        let log_likelihood_string = self.log_likelihood_grid.iter()
+            .map(|log_likelihood_vec| log_likelihood_vec.iter().map(|log_likelihood| log_likelihood.to_string())
+                .collect::<Vec<String>>().join(","))
+            .collect::<Vec<String>>().join(";");
+        format!("no_of_sites={}, no_of_error_obs={}, error_depth={}, log_likelihood={}", self.no_of_sites,
+                self.no_of_error_obs, self.error_depth, log_likelihood_string)
+    }
+
+    /// Inverse of to_comment_value().
+    pub fn from_comment_value(comment_value: &str) -> ContaminationProfile {
+        let value_vec: Vec<&str> = comment_value.split(", ")
+            .map(|field| field.splitn(2, '=').nth(1)
+                .expect(&format!("Error parsing contamination profile {}", comment_value)))
+            .collect();
+        let log_likelihood_grid: Vec<Vec<f64>> = value_vec[3].split(';')
+            .map(|log_likelihood_string| log_likelihood_string.split(',')
+                .map(|log_likelihood| log_likelihood.parse().unwrap()).collect())
+            .collect();
+        if log_likelihood_grid.len() != no_of_error_rates() ||
+            log_likelihood_grid.iter().any(|log_likelihood_vec| log_likelihood_vec.len() != no_of_contamination_levels()) {
+            panic!("Contamination profile is not of {} error rates x {} contamination levels: {}",
+                   no_of_error_rates(), no_of_contamination_levels(), comment_value);
+        }
+        ContaminationProfile {
+            no_of_sites: value_vec[0].parse().unwrap(),
+            no_of_error_obs: value_vec[1].parse().unwrap(),
+            error_depth: value_vec[2].parse().unwrap(),
+            log_likelihood_grid,
+        }
+    }
+
+    /// Maximum likelihood contamination at the error rate of rare-allele sites, or at the best error rate on
+    /// the grid if there are no such sites. The log likelihood is interpolated linearly between error rates.
+    pub fn estimate(&self) -> ContaminationEstimate {
+        let (error_rate, is_error_rate_from_rare_sites) = if self.error_depth > 0 {
+            ((self.no_of_error_obs as f64 / self.error_depth as f64).min(ERROR_RATE_MAX), true)
+        } else {
+            let best_error_index = (0..no_of_error_rates()).max_by(|&i, &j| {
+                let max_i = self.log_likelihood_grid[i].iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                let max_j = self.log_likelihood_grid[j].iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                max_i.partial_cmp(&max_j).unwrap()
+            }).unwrap();
+            (best_error_index as f64 * ERROR_RATE_STEP, false)
+        };
+        if self.no_of_sites == 0 {
+            return ContaminationEstimate {
+                contamination: None,
+                lower: None,
+                upper: None,
+                no_of_sites: 0,
+                error_rate,
+                is_error_rate_from_rare_sites,
+            };
+        }
+        let lower_error_index = cmp::min((error_rate / ERROR_RATE_STEP).floor() as usize, no_of_error_rates() - 2);
+        let weight = error_rate / ERROR_RATE_STEP - lower_error_index as f64;
+        let log_likelihood_vec: Vec<f64> = self.log_likelihood_grid[lower_error_index].iter()
+            .zip(self.log_likelihood_grid[lower_error_index + 1].iter())
+            .map(|(lower, upper)| (1.0 - weight) * lower + weight * upper)
+            .collect();
+        let best_index = (0..log_likelihood_vec.len())
+            .max_by(|&i, &j| log_likelihood_vec[i].partial_cmp(&log_likelihood_vec[j]).unwrap()).unwrap();
+        //vertex of the parabola through the best level and its neighbors
+        let mut contamination = best_index as f64 * CONTAMINATION_STEP;
+        if best_index > 0 && best_index + 1 < log_likelihood_vec.len() {
+            let (left, middle, right) = (log_likelihood_vec[best_index - 1], log_likelihood_vec[best_index],
+                                         log_likelihood_vec[best_index + 1]);
+            let curvature = left - 2.0 * middle + right;
+            if curvature < 0.0 {
+                contamination += 0.5 * (left - right) / curvature * CONTAMINATION_STEP;
+            }
+        }
+        let threshold = log_likelihood_vec[best_index] - CI_LOG_LIKELIHOOD_DROP;
+        //the level, interpolated between grid points, where the log likelihood drops to threshold
+        let find_bound = |index_vec: Vec<usize>| -> f64 {
+            let mut previous_index = best_index;
+            for index in index_vec {
+                if log_likelihood_vec[index] < threshold {
+                    let fraction = (log_likelihood_vec[previous_index] - threshold) /
+                        (log_likelihood_vec[previous_index] - log_likelihood_vec[index]);
+                    return (previous_index as f64 + fraction * (index as f64 - previous_index as f64))
+                        * CONTAMINATION_STEP;
+                }
+                previous_index = index;
+            }
+            previous_index as f64 * CONTAMINATION_STEP
+        };
+        let lower = find_bound((0..best_index).rev().collect());
+        let upper = find_bound(((best_index + 1)..log_likelihood_vec.len()).collect());
+        ContaminationEstimate {
+            contamination: Some(contamination),
+            lower: Some(lower),
+            upper: Some(upper),
+            no_of_sites: self.no_of_sites,
+            error_rate,
+            is_error_rate_from_rare_sites,
+        }
+    }
+}
+
+/// Contamination profile of one sample from its genotyped SNPs at known sites with population AF.
+/// Following VerifyBamID, both the sample and the contaminant genotypes are marginalized over their
+/// Hardy-Weinberg priors, so sites are not selected by the genotype call, which contamination itself changes.
+/// Hom-ref sites count only if the VCF reports them (i.e. freebayes --report-monomorphic).
+pub fn calc_profile(site_vec: &[ContaminationSite], known_sites: &KnownSites) -> ContaminationProfile {
+    let mut depth_alt_af_bin2count: HashMap<(usize, usize, usize), usize> = HashMap::new();
+    for site in site_vec.iter() {
+        let population_af = match known_sites.get(&site.chr, site.pos) {
+            Some(known_site) => {
+                if !known_site.is_snp() || !known_site.matches_alleles(&site.ref_allele, &site.alt_allele) {
+                    continue;
+                }
+                match known_site.population_af {
+                    Some(af) => af as f64,
+                    None => continue,
+                }
+            },
+            None => continue,
+        };
+        let af_bin = (population_af / AF_STEP).round() as usize;
+        *depth_alt_af_bin2count.entry((site.depth, site.no_of_alt_obs, af_bin)).or_insert(0) += 1;
+    }
+    ContaminationProfile::from_site_count(&depth_alt_af_bin2count)
+}
+
+/// None as NA, like other outputs.
+pub fn format_option(value: Option<f64>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "NA".to_string(),
+    }
+}
+
+/// "X (95% CI L-U, N sites, error rate E)" of the output header.
+pub fn format_estimate(estimate: &ContaminationEstimate) -> String {
+    format!("{} (95% CI {}-{}, {} sites, error rate {})", format_option(estimate.contamination),
+            format_option(estimate.lower), format_option(estimate.upper), estimate.no_of_sites, estimate.error_rate)
+}
+
+pub fn output_qc(output_file_path: &Path, estimate_tumor: &ContaminationEstimate,
+                 estimate_normal: &ContaminationEstimate) {
+    let mut output_f = File::create(&output_file_path)
+        .expect(&format!("Error in creating output file {:?}", &output_file_path));
+    output_f.write_fmt(format_args!("sample\tcontamination\tlower_95\tupper_95\tno_of_sites\terror_rate\t\
+        error_rate_source\n")).unwrap();
+    for &(sample, estimate) in [("tumor", estimate_tumor), ("normal", estimate_normal)].iter() {
+        output_f.write_fmt(format_args!("{}\t{}\t{}\t{}\t{}\t{}\t{}\n", sample, format_option(estimate.contamination),
+                                        format_option(estimate.lower), format_option(estimate.upper),
+                                        estimate.no_of_sites, estimate.error_rate,
+                                        if estimate.is_error_rate_from_rare_sites { "rare_sites" } else { "fitted" }))
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A linear congruential generator of uniforms in (0, 1), to simulate reads without a rand dependency.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next_uniform(&mut self) -> f64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((self.0 >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+        }
+
+        fn next_binomial(&mut self, n: usize, p: f64) -> usize {
+            (0..n).filter(|_| self.next_uniform() < p).count()
+        }
+
+        fn next_genotype(&mut self, af: f64) -> usize {
+            self.next_binomial(2, af)
+        }
+    }
+
+    /// Sites of depth 30 at common AFs, plus one in ten at a rare AF, with reads of both individuals mixed
+    /// and then flipped at error_rate.
+    fn simulate_site_count(contamination: f64, error_rate: f64, no_of_sites: usize)
+                           -> HashMap<(usize, usize, usize), usize> {
+        let mut lcg = Lcg(7);
+        let depth = 30;
+        let mut depth_alt_af_bin2count: HashMap<(usize, usize, usize), usize> = HashMap::new();
+        for i in 0..no_of_sites {
+            let af_bin = if i % 10 == 0 { 2 } else { 50 + 50 * (i % 19) };
+            let af = af_bin as f64 * AF_STEP;
+            let alt_fraction = (1.0 - contamination) * lcg.next_genotype(af) as f64 / 2.0 +
+                contamination * lcg.next_genotype(af) as f64 / 2.0;
+            let observed_alt_fraction = alt_fraction * (1.0 - error_rate) + (1.0 - alt_fraction) * error_rate;
+            let no_of_alt_obs = lcg.next_binomial(depth, observed_alt_fraction);
+            *depth_alt_af_bin2count.entry((depth, no_of_alt_obs, af_bin)).or_insert(0) += 1;
+        }
+        depth_alt_af_bin2count
+    }
+
+    #[test]
+    fn clean_sample_has_no_error_floor() {
+        let estimate = ContaminationProfile::from_site_count(&simulate_site_count(0.0, 0.005, 20000)).estimate();
+        assert!(estimate.is_error_rate_from_rare_sites);
+        assert!((estimate.error_rate - 0.005).abs() < 0.002, "error rate {}", estimate.error_rate);
+        assert!(estimate.contamination.unwrap() < 0.005, "contamination {:?}", estimate.contamination);
+        assert_eq!(estimate.lower, Some(0.0));
+    }
+
+    #[test]
+    fn high_contamination_is_not_biased_down() {
+        let estimate = ContaminationProfile::from_site_count(&simulate_site_count(0.2, 0.005, 20000)).estimate();
+        let contamination = estimate.contamination.unwrap();
+        assert!((contamination - 0.2).abs() < 0.01, "contamination {}", contamination);
+        assert!(estimate.lower.unwrap() < 0.2 && estimate.upper.unwrap() > 0.2);
+    }
+
+    #[test]
+    fn no_sites_gives_no_estimate() {
+        let estimate = ContaminationProfile::from_site_count(&HashMap::new()).estimate();
+        assert_eq!(estimate.no_of_sites, 0);
+        assert!(estimate.contamination.is_none() && estimate.lower.is_none() && estimate.upper.is_none());
+    }
+
+    #[test]
+    fn profiles_of_shards_add_up_through_comment_lines() {
+        let profile = ContaminationProfile::from_site_count(&simulate_site_count(0.05, 0.005, 2000));
+        let mut merged = ContaminationProfile::from_comment_value(&profile.to_comment_value());
+        merged.add(&profile);
+        assert_eq!(merged.no_of_sites, 2 * profile.no_of_sites);
+        assert_eq!(merged.error_depth, 2 * profile.error_depth);
+        assert_eq!(merged.log_likelihood_grid[1][3], 2.0 * profile.log_likelihood_grid[1][3]);
+        assert_eq!(merged.estimate().contamination, profile.estimate().contamination);
+    }
+}
@@ -0,0 +1,79 @@
+use rust_htslib::bcf::record::{Genotype, GenotypeAllele};
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GenotypeClass {
+    Missing,
+    HomRef,
+    // allele index of the homozygous non-reference call
+    HomAlt(u32),
+    // allele indices in the order of the GT field, i.e. 1|0 => Het(1, 0)
+    Het(u32, u32),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct OneGenotype {
+    pub class: GenotypeClass,
+    pub is_phased: bool,
+}
+
+impl OneGenotype {
+    pub fn is_het(&self) -> bool {
+        match self.class {
+            GenotypeClass::Het(_, _) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_hom(&self) -> bool {
+        match self.class {
+            GenotypeClass::HomRef | GenotypeClass::HomAlt(_) => true,
+            _ => false,
+        }
+    }
+
+    /// A het between two non-reference alleles, i.e. 1/2.
+    pub fn is_multi_allelic_het(&self) -> bool {
+        match self.class {
+            GenotypeClass::Het(allele_1, allele_2) => allele_1 > 0 && allele_2 > 0,
+            _ => false,
+        }
+    }
+}
+
+/// Classify one sample's genotype from its parsed allele indices rather than its string form,
+/// so that 0/1, 1/0, 0|1, 1|0 and 1/2 are all recognized as hets.
+/// Haploid calls are homozygous. The phase marker is carried by the second allele in VCF.
+pub fn classify_genotype(genotype: &Genotype) -> OneGenotype {
+    let mut allele_index_vec: Vec<u32> = Vec::new();
+    let mut is_phased = false;
+    for (i, allele) in genotype.iter().enumerate() {
+        match *allele {
+            GenotypeAllele::Unphased(allele_index) => allele_index_vec.push(allele_index as u32),
+            GenotypeAllele::Phased(allele_index) => {
+                allele_index_vec.push(allele_index as u32);
+                if i > 0 {
+                    is_phased = true;
+                }
+            },
+            GenotypeAllele::UnphasedMissing | GenotypeAllele::PhasedMissing => {
+                return OneGenotype { class: GenotypeClass::Missing, is_phased: false };
+            },
+        }
+    }
+    if allele_index_vec.is_empty() {
+        return OneGenotype { class: GenotypeClass::Missing, is_phased: false };
+    }
+    let first_allele_index = allele_index_vec[0];
+    let class = match allele_index_vec.iter().find(|allele_index| **allele_index != first_allele_index) {
+        Some(other_allele_index) => GenotypeClass::Het(first_allele_index, *other_allele_index),
+        None => {
+            if first_allele_index == 0 {
+                GenotypeClass::HomRef
+            } else {
+                GenotypeClass::HomAlt(first_allele_index)
+            }
+        },
+    };
+    OneGenotype { class, is_phased }
+}
@@ -0,0 +1,220 @@
+use flate2;
+use flate2::Compression;
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::{Path};
+
+
+/// One het SNP in tumor and normal whose normal genotype is phased.
+pub struct PhasedSNP {
+    pub chr: String,
+    pub pos: u64,
+    //phased hets without PS are put into one chromosome-wide block, phase_set=0.
+    pub phase_set: i32,
+    pub hap1_is_ref: bool,
+    pub tumor_ref_obs: usize,
+    pub tumor_alt_obs: usize,
+    pub normal_ref_obs: usize,
+    pub normal_alt_obs: usize,
+}
+
+impl PhasedSNP {
+    pub fn new(chr: String,
+               pos: u64,
+               phase_set: i32,
+               hap1_is_ref: bool,
+               tumor_ref_obs: usize,
+               tumor_alt_obs: usize,
+               normal_ref_obs: usize,
+               normal_alt_obs: usize,) -> PhasedSNP {
+        PhasedSNP {
+            chr,
+            pos,
+            phase_set,
+            hap1_is_ref,
+            tumor_ref_obs,
+            tumor_alt_obs,
+            normal_ref_obs,
+            normal_alt_obs,
+        }
+    }
+}
+
+/// Allele counts summed per haplotype across one phase set.
+pub struct HaplotypeBlock {
+    pub chr: String,
+    pub phase_set: i32,
+    pub start: u64,
+    pub end: u64,
+    pub no_of_snps: usize,
+    pub tumor_hap1_obs: usize,
+    pub tumor_hap2_obs: usize,
+    pub normal_hap1_obs: usize,
+    pub normal_hap2_obs: usize,
+}
+
+impl HaplotypeBlock {
+    fn new(chr: String, phase_set: i32, pos: u64) -> HaplotypeBlock {
+        HaplotypeBlock {
+            chr,
+            phase_set,
+            start: pos,
+            end: pos,
+            no_of_snps: 0,
+            tumor_hap1_obs: 0,
+            tumor_hap2_obs: 0,
+            normal_hap1_obs: 0,
+            normal_hap2_obs: 0,
+        }
+    }
+
+    fn add(&mut self, phased_snp: &PhasedSNP) {
+        self.start = cmp::min(self.start, phased_snp.pos);
+        self.end = cmp::max(self.end, phased_snp.pos);
+        self.no_of_snps += 1;
+        if phased_snp.hap1_is_ref {
+            self.tumor_hap1_obs += phased_snp.tumor_ref_obs;
+            self.tumor_hap2_obs += phased_snp.tumor_alt_obs;
+            self.normal_hap1_obs += phased_snp.normal_ref_obs;
+            self.normal_hap2_obs += phased_snp.normal_alt_obs;
+        } else {
+            self.tumor_hap1_obs += phased_snp.tumor_alt_obs;
+            self.tumor_hap2_obs += phased_snp.tumor_ref_obs;
+            self.normal_hap1_obs += phased_snp.normal_alt_obs;
+            self.normal_hap2_obs += phased_snp.normal_ref_obs;
+        }
+    }
+
+    pub fn tumor_depth(&self) -> usize {
+        self.tumor_hap1_obs + self.tumor_hap2_obs
+    }
+
+    /// The major-haplotype fraction in the tumor, analogous to tumor_maf of a single SNP.
+    pub fn tumor_baf(&self) -> f32 {
+        cmp::max(self.tumor_hap1_obs, self.tumor_hap2_obs) as f32 / self.tumor_depth() as f32
+    }
+
+    pub fn normal_baf(&self) -> f32 {
+        cmp::max(self.normal_hap1_obs, self.normal_hap2_obs) as f32 /
+            (self.normal_hap1_obs + self.normal_hap2_obs) as f32
+    }
+
+    /// Tumor haplotype counts divided by normal ones before taking the major fraction, as tumor_maf_normalized.
+    pub fn tumor_baf_normalized(&self) -> f32 {
+        let ratio_hap1 = self.tumor_hap1_obs as f32 / self.normal_hap1_obs as f32;
+        let ratio_hap2 = self.tumor_hap2_obs as f32 / self.normal_hap2_obs as f32;
+        if ratio_hap1 > ratio_hap2 {
+            ratio_hap1 / (ratio_hap1 + ratio_hap2)
+        } else {
+            ratio_hap2 / (ratio_hap1 + ratio_hap2)
+        }
+    }
+}
+
+/// Group phased SNPs by (chromosome, phase set). Blocks are returned in the order of their first SNP.
+pub fn aggregate_blocks(phased_snp_vec: &[PhasedSNP]) -> Vec<HaplotypeBlock> {
+    let mut block_vec: Vec<HaplotypeBlock> = Vec::new();
+    let mut chr_ps2block_index: HashMap<(String, i32), usize> = HashMap::new();
+    for phased_snp in phased_snp_vec.iter() {
+        let block_key = (phased_snp.chr.clone(), phased_snp.phase_set);
+        let block_index = *chr_ps2block_index.entry(block_key).or_insert_with(|| {
+            block_vec.push(HaplotypeBlock::new(phased_snp.chr.clone(), phased_snp.phase_set, phased_snp.pos));
+            block_vec.len() - 1
+        });
+        block_vec[block_index].add(phased_snp);
+    }
+    block_vec
+}
+
+/// Merge blocks of the same (chromosome, phase set), e.g. of a phase set split across shards, by summing their
+/// counts. Blocks are returned in the order of their first occurrence.
+pub fn merge_blocks(block_vec: Vec<HaplotypeBlock>) -> Vec<HaplotypeBlock> {
+    let mut merged_block_vec: Vec<HaplotypeBlock> = Vec::new();
+    let mut chr_ps2block_index: HashMap<(String, i32), usize> = HashMap::new();
+    for block in block_vec.into_iter() {
+        let block_key = (block.chr.clone(), block.phase_set);
+        if let Some(&block_index) = chr_ps2block_index.get(&block_key) {
+            let merged_block = &mut merged_block_vec[block_index];
+            merged_block.start = cmp::min(merged_block.start, block.start);
+            merged_block.end = cmp::max(merged_block.end, block.end);
+            merged_block.no_of_snps += block.no_of_snps;
+            merged_block.tumor_hap1_obs += block.tumor_hap1_obs;
+            merged_block.tumor_hap2_obs += block.tumor_hap2_obs;
+            merged_block.normal_hap1_obs += block.normal_hap1_obs;
+            merged_block.normal_hap2_obs += block.normal_hap2_obs;
+            continue;
+        }
+        chr_ps2block_index.insert(block_key, merged_block_vec.len());
+        merged_block_vec.push(block);
+    }
+    merged_block_vec
+}
+
+/// Read blocks and the number of phased SNPs from a file of output_blocks().
+pub fn read_blocks(input_file_path: &Path) -> (Vec<HaplotypeBlock>, usize) {
+    println_stderr!("Reading haplotype blocks from {:?} ...", input_file_path);
+    let input_f = File::open(&input_file_path)
+        .expect(&format!("Error in opening file {:?}", &input_file_path));
+    let reader = BufReader::new(flate2::read::MultiGzDecoder::new(input_f)
+        .expect("Error in opening gzipped file."));
+    let mut block_vec: Vec<HaplotypeBlock> = Vec::new();
+    let mut no_of_phased_snps = 0usize;
+    for line in reader.lines() {
+        let line = line.expect("Error reading line.");
+        if line.starts_with("#no_of_phased_snps: ") {
+            no_of_phased_snps = line["#no_of_phased_snps: ".len()..].parse()
+                .expect(&format!("Error parsing {}", line));
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') || line.starts_with("chr\t") {
+            continue;
+        }
+        let field_vec: Vec<&str> = line.split('\t').collect();
+        let parse_field = |index: usize| -> usize {
+            field_vec[index].parse().expect(&format!("Error parsing column {} in {}", index + 1, line))
+        };
+        block_vec.push(HaplotypeBlock {
+            chr: field_vec[0].to_string(),
+            phase_set: field_vec[3].parse().expect(&format!("Error parsing phase_set in {}", line)),
+            start: parse_field(1) as u64,
+            end: parse_field(2) as u64,
+            no_of_snps: parse_field(4),
+            tumor_hap1_obs: parse_field(8),
+            tumor_hap2_obs: parse_field(9),
+            normal_hap1_obs: parse_field(11),
+            normal_hap2_obs: parse_field(12),
+        });
+    }
+    (block_vec, no_of_phased_snps)
+}
+
+pub fn output_blocks(block_vec: &[HaplotypeBlock], output_file_path: &Path, no_of_phased_snps: usize) {
+    println_stderr!("Outputting {} haplotype blocks to {:?} ...", block_vec.len(), output_file_path);
+    let output_f = File::create(&output_file_path)
+        .expect(&format!("Error in creating output file {:?}", &output_file_path));
+    let mut gz_writer = flate2::GzBuilder::new()
+        .filename(output_file_path.file_stem().unwrap().to_str().unwrap())
+        .comment("Comment")
+        .write(output_f, Compression::default());
+    gz_writer.write_fmt(format_args!("#no_of_phased_snps: {}\n", no_of_phased_snps)).unwrap();
+    gz_writer.write_fmt(format_args!("#no_of_blocks: {}\n", block_vec.len())).unwrap();
+    gz_writer.write_fmt(format_args!("chr\tstart\tend\tphase_set\tno_of_snps\t\
+            tumor_baf_normalized\ttumor_depth\ttumor_baf\ttumor_hap1\ttumor_hap2\t\
+            normal_baf\tnormal_hap1\tnormal_hap2\n")).unwrap();
+    for block in block_vec.iter() {
+        gz_writer.write_fmt(format_args!("{}\t{}\t{}\t{}\t{}\t\
+                {}\t{}\t{}\t{}\t{}\t\
+                {}\t{}\t{}\n",
+                                         block.chr, block.start, block.end, block.phase_set, block.no_of_snps,
+                                         block.tumor_baf_normalized(), block.tumor_depth(), block.tumor_baf(),
+                                         block.tumor_hap1_obs, block.tumor_hap2_obs,
+                                         block.normal_baf(), block.normal_hap1_obs, block.normal_hap2_obs)
+        ).unwrap();
+    }
+    gz_writer.finish()
+        .expect(&format!("ERROR finish() failure for gz_writer of {:?}.", &output_file_path));
+    println_stderr!("Output done.");
+}
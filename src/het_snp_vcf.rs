@@ -0,0 +1,108 @@
+use rust_htslib::bcf;
+use rust_htslib::bcf::record::GenotypeAllele;
+use rust_htslib::htslib;
+use std::ffi::CString;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+
+/// One selected het with its tumor and normal allele counts, as in a row of het_snp.tsv.gz.
+pub struct HetSNPVcfRecord<'a> {
+    pub chr: &'a str,
+    pub pos: u64,
+    //REF of the input record, then the two alleles of the het. The latter's first is REF unless multi-allelic.
+    pub record_ref_allele: &'a [u8],
+    pub allele_1: &'a [u8],
+    pub allele_2: &'a [u8],
+    pub tumor_ro: usize,
+    pub tumor_ao: usize,
+    pub normal_ro: usize,
+    pub normal_ao: usize,
+    pub tumor_maf: f32,
+    pub normal_maf: f32,
+    pub tumor_maf_normalized: f32,
+    pub tumor_baf: f64,
+    pub tumor_baf_corrected: f64,
+}
+
+/// A bgzipped VCF of selected hets with samples TUMOR and NORMAL. It is tabix-indexed by finish().
+pub struct HetSNPVcfWriter {
+    writer: bcf::Writer,
+    output_file_path: PathBuf,
+}
+
+impl HetSNPVcfWriter {
+    /// chr_vec lists contigs in the order records will be written.
+    /// setting_vec holds (key, value) of filter settings, written as ##key=value header lines.
+    pub fn new(output_file_path: &Path, chr_vec: &[String], setting_vec: &[(String, String)]) -> HetSNPVcfWriter {
+        let mut header = bcf::Header::new();
+        header.push_record(b"##source=Accurity select_het_snp");
+        for &(ref key, ref value) in setting_vec.iter() {
+            header.push_record(format!("##{}={}", key, value).as_bytes());
+        }
+        for chr in chr_vec.iter() {
+            header.push_record(format!("##contig=<ID={}>", chr).as_bytes());
+        }
+        header.push_record(b"##INFO=<ID=TMAFN,Number=1,Type=Float,Description=\"Major fraction of tumor allele counts each divided by its normal count\">");
+        header.push_record(b"##INFO=<ID=TBAF,Number=1,Type=Float,Description=\"Tumor fraction of the second allele of the het\">");
+        header.push_record(b"##INFO=<ID=TBAFC,Number=1,Type=Float,Description=\"TBAF corrected for the depth-binned ref-mapping bias of normal hets\">");
+        header.push_record(b"##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">");
+        header.push_record(b"##FORMAT=<ID=DP,Number=1,Type=Integer,Description=\"Read depth of the two alleles of the het\">");
+        header.push_record(b"##FORMAT=<ID=RO,Number=1,Type=Integer,Description=\"Count of the first allele of the het\">");
+        header.push_record(b"##FORMAT=<ID=AO,Number=1,Type=Integer,Description=\"Count of the second allele of the het\">");
+        header.push_record(b"##FORMAT=<ID=MAF,Number=1,Type=Float,Description=\"Major allele fraction\">");
+        header.push_sample(b"TUMOR");
+        header.push_sample(b"NORMAL");
+        let writer = bcf::Writer::from_path(output_file_path, &header, false, true)
+            .ok().expect(&format!("Error in creating VCF output file {:?}", output_file_path));
+        HetSNPVcfWriter {
+            writer,
+            output_file_path: output_file_path.to_path_buf(),
+        }
+    }
+
+    pub fn write(&mut self, het_snp: &HetSNPVcfRecord) {
+        let rid = self.writer.header().name2rid(het_snp.chr.as_bytes())
+            .ok().expect("Error finding contig in the VCF header.");
+        let mut record = self.writer.empty_record();
+        record.set_rid(&Some(rid));
+        record.set_pos(het_snp.pos as i32);
+        //a het between two alternative alleles is 1/2. Otherwise 0/1.
+        let is_multi_allelic_het = het_snp.allele_1 != het_snp.record_ref_allele;
+        let genotype_vec = if is_multi_allelic_het {
+            record.set_alleles(&[het_snp.record_ref_allele, het_snp.allele_1, het_snp.allele_2])
+                .ok().expect("Error setting alleles.");
+            [GenotypeAllele::Unphased(1), GenotypeAllele::Unphased(2),
+                GenotypeAllele::Unphased(1), GenotypeAllele::Unphased(2)]
+        } else {
+            record.set_alleles(&[het_snp.allele_1, het_snp.allele_2]).ok().expect("Error setting alleles.");
+            [GenotypeAllele::Unphased(0), GenotypeAllele::Unphased(1),
+                GenotypeAllele::Unphased(0), GenotypeAllele::Unphased(1)]
+        };
+        record.push_info_float(b"TMAFN", &[het_snp.tumor_maf_normalized]).ok().expect("Error writing TMAFN.");
+        record.push_info_float(b"TBAF", &[het_snp.tumor_baf as f32]).ok().expect("Error writing TBAF.");
+        record.push_info_float(b"TBAFC", &[het_snp.tumor_baf_corrected as f32]).ok().expect("Error writing TBAFC.");
+        record.push_genotypes(&genotype_vec).ok().expect("Error writing GT.");
+        record.push_format_integer(b"DP", &[(het_snp.tumor_ro + het_snp.tumor_ao) as i32,
+            (het_snp.normal_ro + het_snp.normal_ao) as i32]).ok().expect("Error writing DP.");
+        record.push_format_integer(b"RO", &[het_snp.tumor_ro as i32, het_snp.normal_ro as i32])
+            .ok().expect("Error writing RO.");
+        record.push_format_integer(b"AO", &[het_snp.tumor_ao as i32, het_snp.normal_ao as i32])
+            .ok().expect("Error writing AO.");
+        record.push_format_float(b"MAF", &[het_snp.tumor_maf, het_snp.normal_maf])
+            .ok().expect("Error writing MAF.");
+        self.writer.write(&record).ok().expect("Error writing VCF record.");
+    }
+
+    /// Close the VCF and build its tabix index (.tbi).
+    pub fn finish(self) {
+        let output_file_path = self.output_file_path.clone();
+        //the writer flushes and closes the BGZF file when dropped.
+        drop(self.writer);
+        let c_path = CString::new(output_file_path.to_str().unwrap()).unwrap();
+        let return_code = unsafe { htslib::tbx_index_build(c_path.as_ptr(), 0, &htslib::tbx_conf_vcf) };
+        if return_code != 0 {
+            println_stderr!("WARNING: failed to build tabix index for {:?}.", output_file_path);
+        }
+    }
+}
@@ -0,0 +1,810 @@
+use flate2;
+use std::collections::{HashMap, HashSet};
+use std::f64;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::{Path};
+
+use autocor;
+use autocor::CandidatePeriod;
+use call;
+use call::INTEGER_FIT_TOLERANCE;
+use maf_model::{calc_folded_maf, clamp_purity, expected_maf, expected_ratio, CopyState};
+use peaks;
+use roh;
+use prob::ln_normal_pdf;
+use segment::calc_robust_mean_stddev;
+use sex;
+use wgd;
+use wgd::WgdStatus;
+
+
+//segments above this coverage ratio are ignored, as in infer.cpp.
+pub const MAX_RATIO: f64 = 3.0;
+pub const MIN_PLOIDY: f64 = 1.0;
+pub const MAX_PLOIDY: f64 = 4.0;
+const MIN_PURITY: f64 = 0.05;
+const PURITY_STEP: f64 = 0.01;
+const PLOIDY_STEP: f64 = 0.02;
+//a segment needs more SNPs than this for its MAF to be used, as in findSNPsWithinSegment().
+const MIN_NO_OF_SNPS_PER_SEGMENT: usize = 10;
+const SNP_ROBUST_PERCENT_TO_EXCLUDE: usize = 30;
+//the robust MAF stddev of a segment is divided by this, as _snp_maf_stddev_divider in infer.cpp.
+const SNP_MAF_STDDEV_DIVIDER: f64 = 20.0;
+//floors of the segment stddevs in the likelihood, shared by infer, call and subclone.
+const RATIO_STDDEV_MIN: f64 = 0.01;
+const MAF_STDDEV_MIN: f64 = 0.01;
+//weight of the uniform (outlier) component in the per-segment likelihood, e.g. for subclonal segments.
+const OUTLIER_FRACTION: f64 = 0.05;
+//expected folded MAFs are tabulated at this step within [0.5, 1].
+const MAF_TABLE_STEP: f64 = 0.001;
+
+/// One segment of the segment file (e.g. all_segments.tsv.gz), with the SNPs inside.
+pub struct InferSegment {
+    pub chr: String,
+    pub start: u64,
+    pub end: u64,
+    pub ratio: f64,
+    //robust stddev of window ratios, divided by segment_stddev_divider.
+    pub stddev: f64,
+    pub no_of_windows: usize,
+    pub no_of_snps: usize,
+    //robust mean/stddev of tumor_maf_normalized. stddev is divided by SNP_MAF_STDDEV_DIVIDER.
+    pub maf_mean: f64,
+    pub maf_stddev: f64,
+    pub snp_coverage_mean: f64,
+}
+
+/// Read segments on autosomes with ratio <= max_ratio. Also return the number of all segments in the file.
+pub fn read_segment_file(segment_file_path: &Path, segment_stddev_divider: f64,
+                         max_ratio: f64) -> (Vec<InferSegment>, usize) {
+    println_stderr!("Reading segments from {:?} ...", segment_file_path);
+    let input_f = File::open(segment_file_path)
+        .expect(&format!("Error in opening file {:?}", segment_file_path));
+    let reader = BufReader::new(flate2::read::MultiGzDecoder::new(input_f)
+        .expect("Error in opening gzipped file."));
+    let mut segment_vec: Vec<InferSegment> = Vec::new();
+    let mut no_of_segments = 0usize;
+    for line in reader.lines() {
+        let line = line.expect("Error reading line.");
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        no_of_segments += 1;
+        let field_vec: Vec<&str> = line.split('\t').collect();
+        let ratio: f64 = field_vec[3].parse().unwrap();
+        let stddev: f64 = field_vec[4].parse::<f64>().unwrap() / segment_stddev_divider;
+        //too much variation, as in getSegmentDataFromFile().
+        if ratio > 0.1 && stddev > ratio {
+            continue;
+        }
+        if ratio > max_ratio || stddev <= 1e-12 || !sex::is_autosome(field_vec[0].as_bytes()) {
+            continue;
+        }
+        segment_vec.push(InferSegment {
+            chr: field_vec[0].to_string(),
+            start: field_vec[1].parse().unwrap(),
+            end: field_vec[2].parse().unwrap(),
+            ratio,
+            stddev,
+            no_of_windows: field_vec[5].parse().unwrap(),
+            no_of_snps: 0,
+            maf_mean: 0.0,
+            maf_stddev: 0.0,
+            snp_coverage_mean: 0.0,
+        });
+    }
+    println_stderr!("{} segments, {} used.", no_of_segments, segment_vec.len());
+    (segment_vec, no_of_segments)
+}
+
+/// Purity and ploidy of the best solution in infer.out.tsv, i.e. the first two columns of its second line.
+pub fn read_purity_ploidy(infer_out_file_path: &Path) -> (f64, f64) {
+    let input_f = File::open(infer_out_file_path)
+        .expect(&format!("Error in opening file {:?}", infer_out_file_path));
+    let line = BufReader::new(input_f).lines().nth(1)
+        .expect(&format!("No solution line in {:?}", infer_out_file_path))
+        .expect("Error reading line.");
+    let field_vec: Vec<&str> = line.split('\t').collect();
+    (field_vec[0].parse().expect(&format!("Purity {} is not a number.", field_vec[0])),
+     field_vec[1].parse().expect(&format!("Ploidy {} is not a number.", field_vec[1])))
+}
+
+/// Het SNPs of select_het_snp, plus the estimates in its comment lines.
+pub struct HetSNPData {
+    //chr -> sorted (0-based position, tumor_maf_normalized, tumor_depth)
+    pub chr2snp_vec: HashMap<String, Vec<(u64, f64, usize)>>,
+    pub no_of_snps: usize,
+    pub tumor_in_normal_fraction: Option<f64>,
+    //only reported. The MAF likelihood uses the spread of MAFs within a segment, which includes this
+    //overdispersion, rather than a beta-binomial of rho.
+    pub normal_beta_binomial_rho: Option<f64>,
+}
+
+/// Value of "<key>=<value>" at the start of a comment line, e.g. "#tumor_in_normal_fraction=0.01, ...".
+fn parse_comment_value(line: &str, key: &str) -> Option<f64> {
+    if !line.starts_with(key) {
+        return None;
+    }
+    line[key.len()..].split(',').next().unwrap().parse().ok()
+}
+
+/// Read het SNPs with tumor depth >= snp_coverage_min.
+pub fn read_het_snp_file(het_snp_file_path: &Path, snp_coverage_min: usize) -> HetSNPData {
+    println_stderr!("Reading SNPs from {:?} ...", het_snp_file_path);
+    let input_f = File::open(het_snp_file_path)
+        .expect(&format!("Error in opening file {:?}", het_snp_file_path));
+    let reader = BufReader::new(flate2::read::MultiGzDecoder::new(input_f)
+        .expect("Error in opening gzipped file."));
+    let mut het_snp_data = HetSNPData {
+        chr2snp_vec: HashMap::new(),
+        no_of_snps: 0,
+        tumor_in_normal_fraction: None,
+        normal_beta_binomial_rho: None,
+    };
+    for line in reader.lines() {
+        let line = line.expect("Error reading line.");
+        if line.starts_with('#') {
+            if let Some(value) = parse_comment_value(&line, "#tumor_in_normal_fraction=") {
+                het_snp_data.tumor_in_normal_fraction = Some(value);
+            }
+            if let Some(value) = parse_comment_value(&line, "#normal_beta_binomial_rho=") {
+                het_snp_data.normal_beta_binomial_rho = Some(value);
+            }
+            continue;
+        }
+        if line.starts_with("chr\t") || line.is_empty() {
+            continue;
+        }
+        let field_vec: Vec<&str> = line.split('\t').collect();
+        let depth: usize = field_vec[3].parse().unwrap();
+        if depth < snp_coverage_min {
+            continue;
+        }
+        het_snp_data.chr2snp_vec.entry(field_vec[0].to_string()).or_insert_with(Vec::new)
+            .push((field_vec[1].parse().unwrap(), field_vec[2].parse().unwrap(), depth));
+        het_snp_data.no_of_snps += 1;
+    }
+    for snp_vec in het_snp_data.chr2snp_vec.values_mut() {
+        snp_vec.sort_by_key(|snp| snp.0);
+    }
+    println_stderr!("{} SNPs on {} chromosomes.", het_snp_data.no_of_snps, het_snp_data.chr2snp_vec.len());
+    het_snp_data
+}
+
+/// Summarize SNPs within each segment. Segment start/end are 1-based window starts, as in the segment file.
+/// Return the number of SNPs used.
+pub fn assign_snps_to_segments(segment_vec: &mut [InferSegment], het_snp_data: &HetSNPData) -> usize {
+    let mut no_of_snps_used = 0usize;
+    for segment in segment_vec.iter_mut() {
+        let snp_vec = match het_snp_data.chr2snp_vec.get(&segment.chr) {
+            Some(snp_vec) => snp_vec,
+            None => continue,
+        };
+        //index of the first SNP with 1-based position >= segment.start
+        let first_index = match snp_vec.binary_search_by_key(&segment.start, |snp| snp.0 + 1) {
+            Ok(index) => index,
+            Err(index) => index,
+        };
+        let mut maf_vec: Vec<f64> = Vec::new();
+        let mut depth_vec: Vec<f64> = Vec::new();
+        for &(pos, maf, depth) in snp_vec[first_index..].iter() {
+            if pos + 1 > segment.end {
+                break;
+            }
+            maf_vec.push(maf);
+            depth_vec.push(depth as f64);
+        }
+        if maf_vec.len() <= MIN_NO_OF_SNPS_PER_SEGMENT {
+            continue;
+        }
+        let (maf_mean, maf_stddev) = calc_robust_mean_stddev(&maf_vec, SNP_ROBUST_PERCENT_TO_EXCLUDE);
+        let (snp_coverage_mean, _) = calc_robust_mean_stddev(&depth_vec, SNP_ROBUST_PERCENT_TO_EXCLUDE);
+        segment.no_of_snps = maf_vec.len();
+        segment.maf_mean = maf_mean;
+        segment.maf_stddev = maf_stddev / SNP_MAF_STDDEV_DIVIDER;
+        segment.snp_coverage_mean = snp_coverage_mean;
+        no_of_snps_used += maf_vec.len();
+    }
+    no_of_snps_used
+}
+
+/// Expected folded MAFs at the SNP depths of all segments, tabulated within [0.5, 1].
+struct FoldedMafTable {
+    depth2folded_maf_vec: HashMap<usize, Vec<f64>>,
+}
+
+impl FoldedMafTable {
+    fn new(segment_vec: &[InferSegment]) -> FoldedMafTable {
+        let no_of_steps = (0.5 / MAF_TABLE_STEP).round() as usize;
+        let mut depth2folded_maf_vec: HashMap<usize, Vec<f64>> = HashMap::new();
+        for segment in segment_vec.iter().filter(|segment| segment.no_of_snps > 0) {
+            let depth = segment.snp_coverage_mean.round() as usize;
+            depth2folded_maf_vec.entry(depth).or_insert_with(|| (0..(no_of_steps + 1))
+                .map(|i| calc_folded_maf(0.5 + i as f64 * MAF_TABLE_STEP, depth)).collect());
+        }
+        FoldedMafTable { depth2folded_maf_vec }
+    }
+
+    fn get(&self, maf: f64, depth: f64) -> f64 {
+        let index = ((maf - 0.5) / MAF_TABLE_STEP).round() as usize;
+        self.depth2folded_maf_vec[&(depth.round() as usize)][index]
+    }
+}
+
+/// ln of a normal density mixed with a uniform density over a range of width uniform_width.
+fn ln_robust_normal_pdf(x: f64, mean: f64, stddev: f64, uniform_width: f64) -> f64 {
+    let z = (x - mean) / stddev;
+    ((1.0 - OUTLIER_FRACTION) * (-0.5 * z * z).exp() / (stddev * (2.0 * f64::consts::PI).sqrt()) +
+        OUTLIER_FRACTION / uniform_width).ln()
+}
+
+/// Log-likelihood of the coverage ratio of a segment whose expected ratio is ratio.
+pub fn calc_ratio_log_likelihood(segment: &InferSegment, ratio: f64) -> f64 {
+    ln_robust_normal_pdf(segment.ratio, ratio, segment.stddev.max(RATIO_STDDEV_MIN), MAX_RATIO)
+}
+
+/// Log-likelihood of the SNP MAF of a segment whose expected (folded) MAF is maf. The segment needs SNPs.
+pub fn calc_maf_log_likelihood(segment: &InferSegment, maf: f64) -> f64 {
+    ln_robust_normal_pdf(segment.maf_mean, maf, segment.maf_stddev.max(MAF_STDDEV_MIN), 0.5)
+}
+
+/// One purity/ploidy solution and its log-likelihood.
+#[derive(Clone, Debug)]
+pub struct Solution {
+    pub purity: f64,
+    pub ploidy: f64,
+    pub log_likelihood: f64,
+    //coverage ratio component, with its penalty for the number of copy number levels
+    pub ratio_log_likelihood: f64,
+    //SNP MAF component, with its penalty for the number of (copy number, major copy number) states
+    pub snp_log_likelihood: f64,
+    pub no_of_copy_levels: usize,
+    pub no_of_maf_states: usize,
+    //ln density of the Gaussian priors on purity and ploidy. 0 without priors.
+    pub log_prior: f64,
+    //fraction of windows in segments whose float copy number is within INTEGER_FIT_TOLERANCE of an integer
+    pub integer_fit_fraction: f64,
+}
+
+impl Solution {
+    /// The objective of the search, log-likelihood plus log-prior.
+    pub fn log_posterior(&self) -> f64 {
+        self.log_likelihood + self.log_prior
+    }
+
+    /// Coverage ratio of copy number 2. Q in infer.cpp is 1000 times this.
+    pub fn ratio_of_cp_2(&self) -> f64 {
+        expected_ratio(self.purity, self.ploidy, 2)
+    }
+
+    /// Coverage ratio difference between adjacent copy numbers. period_int in infer.cpp is 1000 times this.
+    pub fn period(&self) -> f64 {
+        self.purity / (2.0 * (1.0 - self.purity) + self.ploidy * self.purity)
+    }
+}
+
+/// Copy number with the nearest expected ratio, within copy numbers whose expected ratio <= MAX_RATIO.
+fn nearest_copy_number(ratio: f64, ratio_of_cp_0: f64, period: f64, max_cn: usize) -> usize {
+    let cn = ((ratio - ratio_of_cp_0) / period).round();
+    if cn < 0.0 { 0 } else if cn > max_cn as f64 { max_cn } else { cn as usize }
+}
+
+/// Scores purity/ploidy solutions against segments. Each segment is weighted by its number of windows,
+/// scaled so that weights sum to the number of segments, which is the sample size of the BIC-like penalties.
+struct Scorer<'a> {
+    segment_vec: &'a [InferSegment],
+    weight_vec: Vec<f64>,
+    total_no_of_windows: usize,
+    no_of_snp_segments: usize,
+    folded_maf_table: FoldedMafTable,
+}
+
+impl<'a> Scorer<'a> {
+    fn new(segment_vec: &'a [InferSegment]) -> Scorer<'a> {
+        let total_no_of_windows = segment_vec.iter().fold(0, |sum, segment| sum + segment.no_of_windows);
+        Scorer {
+            segment_vec,
+            weight_vec: segment_vec.iter()
+                .map(|segment| segment.no_of_windows as f64 * segment_vec.len() as f64 / total_no_of_windows as f64)
+                .collect(),
+            total_no_of_windows,
+            no_of_snp_segments: segment_vec.iter().filter(|segment| segment.no_of_snps > 0).count(),
+            folded_maf_table: FoldedMafTable::new(segment_vec),
+        }
+    }
+
+    fn score(&self, purity: f64, ploidy: f64) -> Solution {
+        let ratio_of_cp_0 = expected_ratio(purity, ploidy, 0);
+        let period = expected_ratio(purity, ploidy, 1) - ratio_of_cp_0;
+        let max_cn = ((MAX_RATIO - ratio_of_cp_0) / period).floor() as usize;
+        let mut ratio_log_likelihood = 0f64;
+        let mut snp_log_likelihood = 0f64;
+        let mut copy_number_set: HashSet<usize> = HashSet::new();
+        let mut maf_state_set: HashSet<(usize, usize)> = HashSet::new();
+        let mut no_of_integer_fit_windows = 0usize;
+        for (segment, weight) in self.segment_vec.iter().zip(self.weight_vec.iter()) {
+            let cn = nearest_copy_number(segment.ratio, ratio_of_cp_0, period, max_cn);
+            copy_number_set.insert(cn);
+            if ((segment.ratio - ratio_of_cp_0) / period - cn as f64).abs() <= INTEGER_FIT_TOLERANCE {
+                no_of_integer_fit_windows += segment.no_of_windows;
+            }
+            ratio_log_likelihood += weight * calc_ratio_log_likelihood(segment, ratio_of_cp_0 + cn as f64 * period);
+            if segment.no_of_snps == 0 || cn == 0 {
+                continue;
+            }
+            //the major copy number that fits the MAF best
+            let mut best_major_cn_ln_pdf: Option<(usize, f64)> = None;
+            for major_cn in ((cn + 1) / 2)..(cn + 1) {
+                let maf = self.folded_maf_table.get(expected_maf(purity, cn, major_cn), segment.snp_coverage_mean);
+                let ln_pdf = calc_maf_log_likelihood(segment, maf);
+                if best_major_cn_ln_pdf.map_or(true, |(_, best_ln_pdf)| ln_pdf > best_ln_pdf) {
+                    best_major_cn_ln_pdf = Some((major_cn, ln_pdf));
+                }
+            }
+            let (major_cn, ln_pdf) = best_major_cn_ln_pdf.unwrap();
+            maf_state_set.insert((cn, major_cn));
+            snp_log_likelihood += weight * ln_pdf;
+        }
+        //only copy numbers that some segment is assigned to are parameters, as for MAF states.
+        ratio_log_likelihood -= 0.5 * copy_number_set.len() as f64 * (self.segment_vec.len() as f64).ln();
+        if self.no_of_snp_segments > 0 {
+            snp_log_likelihood -= 0.5 * maf_state_set.len() as f64 * (self.no_of_snp_segments as f64).ln();
+        }
+        Solution {
+            purity,
+            ploidy,
+            log_likelihood: ratio_log_likelihood + snp_log_likelihood,
+            ratio_log_likelihood,
+            snp_log_likelihood,
+            no_of_copy_levels: copy_number_set.len(),
+            no_of_maf_states: maf_state_set.len(),
+            log_prior: 0.0,
+            integer_fit_fraction: no_of_integer_fit_windows as f64 / self.total_no_of_windows as f64,
+        }
+    }
+}
+
+/// A user constraint on purity or ploidy, e.g. purity from pathology or ploidy from the DNA index of flow cytometry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Constraint {
+    Free,
+    Fixed(f64),
+    //min, max
+    Range(f64, f64),
+    //mean, stddev of a Gaussian prior
+    Gaussian(f64, f64),
+}
+
+impl Constraint {
+    /// Constraint from the values of --*_fixed, --*_range ("MIN,MAX") and --*_prior ("MEAN,STDDEV").
+    /// At most one is given.
+    pub fn from_args(name: &str, fixed: Option<&str>, range: Option<&str>, prior: Option<&str>) -> Constraint {
+        let parse_pair = |value: &str| -> (f64, f64) {
+            let field_vec: Vec<f64> = value.split(',')
+                .map(|field| field.trim().parse().expect(&format!("{} {} is not a number.", name, field)))
+                .collect();
+            if field_vec.len() != 2 {
+                panic!("Expect two comma-separated numbers for {}, got {}.", name, value);
+            }
+            (field_vec[0], field_vec[1])
+        };
+        match (fixed, range, prior) {
+            (None, None, None) => Constraint::Free,
+            (Some(value), None, None) => Constraint::Fixed(value.parse()
+                .expect(&format!("{} {} is not a number.", name, value))),
+            (None, Some(value), None) => {
+                let (min, max) = parse_pair(value);
+                if min > max {
+                    panic!("{} range {} has min above max.", name, value);
+                }
+                Constraint::Range(min, max)
+            },
+            (None, None, Some(value)) => {
+                let (mean, stddev) = parse_pair(value);
+                if stddev <= 0.0 {
+                    panic!("{} prior stddev {} is not positive.", name, stddev);
+                }
+                Constraint::Gaussian(mean, stddev)
+            },
+            _ => panic!("Only one of fixed, range and prior can be given for {}.", name),
+        }
+    }
+
+    /// Values on the grid min..=max at step that satisfy the constraint.
+    fn grid_value_vec(&self, min: f64, max: f64, step: f64) -> Vec<f64> {
+        let no_of_values = ((max - min) / step).round() as usize + 1;
+        let value_vec: Vec<f64> = (0..no_of_values).map(|index| round_to_grid(min + index as f64 * step)).collect();
+        match *self {
+            Constraint::Fixed(value) => vec![value],
+            Constraint::Range(range_min, range_max) => value_vec.into_iter()
+                .filter(|value| *value >= range_min && *value <= range_max).collect(),
+            Constraint::Free | Constraint::Gaussian(_, _) => value_vec,
+        }
+    }
+
+    /// Panic if a range or prior mean is outside [min, max], or a range holds no value of the grid at step.
+    /// Fixed values are checked by the callers, as they need not be on the grid.
+    fn check_grid(&self, name: &str, min: f64, max: f64, step: f64) {
+        match *self {
+            Constraint::Range(range_min, range_max) => {
+                if range_min < min || range_max > max {
+                    panic!("{} range {},{} is not within [{}, {}].", name, range_min, range_max, min, max);
+                }
+                if self.grid_value_vec(min, max, step).is_empty() {
+                    panic!("{} range {},{} holds no value of the search grid, which is at a step of {}.",
+                           name, range_min, range_max, step);
+                }
+            },
+            Constraint::Gaussian(mean, _) => {
+                if mean < min || mean > max {
+                    panic!("{} prior mean {} is not within [{}, {}].", name, mean, min, max);
+                }
+            },
+            Constraint::Free | Constraint::Fixed(_) => {},
+        }
+    }
+
+    fn ln_prior(&self, value: f64) -> f64 {
+        match *self {
+            Constraint::Gaussian(mean, stddev) => ln_normal_pdf(value, mean, stddev),
+            _ => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Constraint::Free => write!(f, "free"),
+            Constraint::Fixed(value) => write!(f, "fixed={}", value),
+            Constraint::Range(min, max) => write!(f, "range=[{},{}]", min, max),
+            Constraint::Gaussian(mean, stddev) => write!(f, "prior=N({},{})", mean, stddev),
+        }
+    }
+}
+
+/// The solution with the highest log-posterior.
+fn find_best_solution(solution_vec: &[Solution]) -> Solution {
+    solution_vec.iter()
+        .fold(None, |best: Option<&Solution>, solution| match best {
+            Some(best) if best.log_posterior() >= solution.log_posterior() => Some(best),
+            _ => Some(solution),
+        }).unwrap().clone()
+}
+
+/// Local maxima of the log-posterior on the purity x ploidy grid, best first, at most no_of_optima.
+/// solution_vec is purity-major with no_of_ploidies solutions per purity. Of equal neighbors, only the first counts.
+fn find_local_optima(solution_vec: &[Solution], no_of_ploidies: usize, no_of_optima: usize) -> Vec<&Solution> {
+    let no_of_purities = solution_vec.len() / no_of_ploidies;
+    let mut optimum_vec: Vec<&Solution> = Vec::new();
+    for purity_index in 0..no_of_purities {
+        for ploidy_index in 0..no_of_ploidies {
+            let index = purity_index * no_of_ploidies + ploidy_index;
+            let log_posterior = solution_vec[index].log_posterior();
+            let mut is_optimum = true;
+            for neighbor_purity_index in purity_index.saturating_sub(1)..(purity_index + 2).min(no_of_purities) {
+                for neighbor_ploidy_index in ploidy_index.saturating_sub(1)..(ploidy_index + 2).min(no_of_ploidies) {
+                    let neighbor_index = neighbor_purity_index * no_of_ploidies + neighbor_ploidy_index;
+                    let neighbor_log_posterior = solution_vec[neighbor_index].log_posterior();
+                    if (neighbor_index < index && neighbor_log_posterior >= log_posterior) ||
+                        (neighbor_index > index && neighbor_log_posterior > log_posterior) {
+                        is_optimum = false;
+                    }
+                }
+            }
+            if is_optimum {
+                optimum_vec.push(&solution_vec[index]);
+            }
+        }
+    }
+    optimum_vec.sort_by(|a, b| b.log_posterior().partial_cmp(&a.log_posterior()).unwrap());
+    optimum_vec.truncate(no_of_optima);
+    optimum_vec
+}
+
+/// Drop the floating-point residue of grid arithmetic, e.g. 0.6000000000000001.
+fn round_to_grid(value: f64) -> f64 {
+    (value * 1e6).round() / 1e6
+}
+
+/// Infer tumor purity and ploidy from segments of coverage ratios and het SNP MAFs, by a grid search over
+/// purity and ploidy. Each solution places copy number levels periodically on the ratio axis and
+/// expected MAFs on the MAF axis. Purity and ploidy can be fixed, bounded or given Gaussian priors, in which
+/// case the constrained best solution is reported besides the unconstrained one.
+/// Writes infer.out.tsv, infer.out.details.tsv, infer.out.optima.tsv (top local optima), infer.out.grid.tsv
+/// (the log-likelihood surface), auto.tsv and peak_bounds.tsv into output_dir.
+pub struct Infer<'a> {
+    segment_file_path: &'a Path,
+    het_snp_file_path: &'a Path,
+    output_dir: &'a Path,
+    segment_stddev_divider: f64,
+    snp_coverage_min: usize,
+    debug: i32,
+    purity_constraint: Constraint,
+    ploidy_constraint: Constraint,
+    no_of_optima: usize,
+    roh_file_path: Option<&'a Path>,
+}
+
+impl<'a> Infer<'a> {
+    pub fn new(segment_file_path: &'a str,
+               het_snp_file_path: &'a str,
+               output_dir: &'a str,
+               segment_stddev_divider: f64,
+               snp_coverage_min: usize,
+               debug: i32,
+    ) -> Infer<'a> {
+        if segment_stddev_divider <= 0.0 {
+            panic!("segment_stddev_divider {} less than or equal to 0.", segment_stddev_divider);
+        }
+        Infer {
+            segment_file_path: Path::new(segment_file_path),
+            het_snp_file_path: Path::new(het_snp_file_path),
+            output_dir: Path::new(output_dir),
+            segment_stddev_divider,
+            snp_coverage_min,
+            debug,
+            purity_constraint: Constraint::Free,
+            ploidy_constraint: Constraint::Free,
+            no_of_optima: 5,
+            roh_file_path: None,
+        }
+    }
+
+    /// The number of local optima to output in infer.out.optima.tsv.
+    pub fn set_no_of_optima(&mut self, no_of_optima: usize) {
+        self.no_of_optima = no_of_optima;
+    }
+
+    /// Runs of homozygosity of the normal (select_het_snp --roh_output). Segments mostly within them are left out
+    /// of whole-genome-doubling detection, as call does, so germline ROH does not count as tumor LOH.
+    pub fn set_roh_file(&mut self, roh_file_path: &'a str) {
+        self.roh_file_path = Some(Path::new(roh_file_path));
+    }
+
+    pub fn set_purity_constraint(&mut self, purity_constraint: Constraint) {
+        if let Constraint::Fixed(purity) = purity_constraint {
+            if purity <= 0.0 || purity > 1.0 {
+                panic!("Fixed purity {} is not within (0, 1].", purity);
+            }
+        }
+        purity_constraint.check_grid("purity", MIN_PURITY, 1.0, PURITY_STEP);
+        self.purity_constraint = purity_constraint;
+    }
+
+    pub fn set_ploidy_constraint(&mut self, ploidy_constraint: Constraint) {
+        if let Constraint::Fixed(ploidy) = ploidy_constraint {
+            if ploidy <= 0.0 {
+                panic!("Fixed ploidy {} is not positive.", ploidy);
+            }
+        }
+        ploidy_constraint.check_grid("ploidy", MIN_PLOIDY, MAX_PLOIDY, PLOIDY_STEP);
+        self.ploidy_constraint = ploidy_constraint;
+    }
+
+    fn is_constrained(&self) -> bool {
+        self.purity_constraint != Constraint::Free || self.ploidy_constraint != Constraint::Free
+    }
+
+    /// All solutions on the purity x ploidy grid that satisfy the constraints, purity in the outer loop.
+    /// Gaussian priors set log_prior of each solution.
+    /// Also return the number of ploidies per purity.
+    fn search_grid(&self, scorer: &Scorer, purity_constraint: Constraint,
+                   ploidy_constraint: Constraint) -> (Vec<Solution>, usize) {
+        let purity_vec = purity_constraint.grid_value_vec(MIN_PURITY, 1.0, PURITY_STEP);
+        let ploidy_vec = ploidy_constraint.grid_value_vec(MIN_PLOIDY, MAX_PLOIDY, PLOIDY_STEP);
+        let mut solution_vec: Vec<Solution> = Vec::with_capacity(purity_vec.len() * ploidy_vec.len());
+        for purity in purity_vec.iter() {
+            let purity = clamp_purity(*purity);
+            for ploidy in ploidy_vec.iter() {
+                let mut solution = scorer.score(purity, *ploidy);
+                solution.log_prior = purity_constraint.ln_prior(purity) + ploidy_constraint.ln_prior(*ploidy);
+                solution_vec.push(solution);
+            }
+        }
+        (solution_vec, ploidy_vec.len())
+    }
+
+    /// best_solution is the constrained one if there are constraints.
+    fn output_infer_out(&self, best_solution: &Solution, unconstrained_best_solution: &Solution,
+                        no_of_segments: usize, no_of_segments_used: usize, het_snp_data: &HetSNPData,
+                        no_of_snps_used: usize, candidate_period_vec: &[CandidatePeriod], wgd_status: &WgdStatus) {
+        let output_file_path = self.output_dir.join("infer.out.tsv");
+        println_stderr!("Outputting the best solution to {:?} ...", output_file_path);
+        let mut output_f = File::create(&output_file_path)
+            .expect(&format!("Error in creating output file {:?}", &output_file_path));
+        output_f.write_fmt(format_args!("purity\tploidy\trc_ratio_of_cp_2\tperiod\t\
+            segment_stddev_divider\tsnp_maf_stddev_divider\tsnp_coverage_min\n")).unwrap();
+        output_f.write_fmt(format_args!("{:.5}\t{:.5}\t{:.5}\t{:.5}\t{}\t{}\t{}\n", best_solution.purity,
+                                        best_solution.ploidy, best_solution.ratio_of_cp_2(), best_solution.period(),
+                                        self.segment_stddev_divider, SNP_MAF_STDDEV_DIVIDER,
+                                        self.snp_coverage_min)).unwrap();
+        output_f.write_fmt(format_args!("logL\tlogL_ratio\tlogL_snp\tno_of_copy_levels\tno_of_maf_states\n")).unwrap();
+        output_f.write_fmt(format_args!("{}\t{}\t{}\t{}\t{}\n", best_solution.log_likelihood,
+                                        best_solution.ratio_log_likelihood, best_solution.snp_log_likelihood,
+                                        best_solution.no_of_copy_levels, best_solution.no_of_maf_states)).unwrap();
+        output_f.write_fmt(format_args!("no_of_segments\tno_of_segments_used\tno_of_snps\tno_of_snps_used\n")).unwrap();
+        output_f.write_fmt(format_args!("{}\t{}\t{}\t{}\n", no_of_segments, no_of_segments_used,
+                                        het_snp_data.no_of_snps, no_of_snps_used)).unwrap();
+        output_f.write_fmt(format_args!("tumor_in_normal_fraction\n")).unwrap();
+        match het_snp_data.tumor_in_normal_fraction {
+            Some(tumor_in_normal_fraction) => output_f.write_fmt(format_args!("{}\n", tumor_in_normal_fraction)).unwrap(),
+            None => output_f.write_fmt(format_args!("NA\n")).unwrap(),
+        }
+        //echoed from select_het_snp for QC. It does not enter the likelihood.
+        output_f.write_fmt(format_args!("normal_beta_binomial_rho\n")).unwrap();
+        match het_snp_data.normal_beta_binomial_rho {
+            Some(rho) => output_f.write_fmt(format_args!("{}\n", rho)).unwrap(),
+            None => output_f.write_fmt(format_args!("NA\n")).unwrap(),
+        }
+        //the best autocorrelation period and whether the period of the best solution falls within its bounds
+        output_f.write_fmt(format_args!("period_from_autocor\tlower_bound\tupper_bound\tsolution_within_bounds\n")).unwrap();
+        match candidate_period_vec.first() {
+            Some(candidate_period) => {
+                let resolution = autocor::RESOLUTION as f64;
+                let period_of_solution = best_solution.period() * resolution;
+                output_f.write_fmt(format_args!("{}\t{}\t{}\t{}\n", candidate_period.period as f64 / resolution,
+                                                candidate_period.lower_bound as f64 / resolution,
+                                                candidate_period.upper_bound as f64 / resolution,
+                                                period_of_solution >= candidate_period.lower_bound as f64 &&
+                                                    period_of_solution <= candidate_period.upper_bound as f64)).unwrap();
+            },
+            None => output_f.write_fmt(format_args!("NA\tNA\tNA\tNA\n")).unwrap(),
+        }
+        output_f.write_fmt(format_args!("fraction_major_cn_ge_2\tfraction_major_cn_ge_4\tloh_fraction\t\
+            wgd_ploidy_threshold\twgd\tno_of_doublings\n")).unwrap();
+        output_f.write_fmt(format_args!("{:.4}\t{:.4}\t{:.4}\t{:.4}\t{}\t{}\n", wgd_status.fraction_major_cn_ge_2,
+                                        wgd_status.fraction_major_cn_ge_4, wgd_status.loh_fraction,
+                                        wgd::wgd_ploidy_threshold(wgd_status.loh_fraction), wgd_status.is_wgd,
+                                        wgd_status.no_of_doublings)).unwrap();
+        output_f.write_fmt(format_args!("solution\tpurity\tploidy\tlogL\tlog_prior\tpurity_constraint\t\
+            ploidy_constraint\n")).unwrap();
+        output_f.write_fmt(format_args!("unconstrained\t{:.5}\t{:.5}\t{}\t{}\tfree\tfree\n",
+                                        unconstrained_best_solution.purity, unconstrained_best_solution.ploidy,
+                                        unconstrained_best_solution.log_likelihood,
+                                        unconstrained_best_solution.log_prior)).unwrap();
+        if self.is_constrained() {
+            output_f.write_fmt(format_args!("constrained\t{:.5}\t{:.5}\t{}\t{}\t{}\t{}\n", best_solution.purity,
+                                            best_solution.ploidy, best_solution.log_likelihood,
+                                            best_solution.log_prior, self.purity_constraint,
+                                            self.ploidy_constraint)).unwrap();
+        }
+    }
+
+    /// The best solution at each purity (profile likelihood), with its log-posterior difference from the overall best.
+    fn output_infer_out_details(&self, solution_vec: &[Solution], best_solution: &Solution) {
+        let output_file_path = self.output_dir.join("infer.out.details.tsv");
+        let mut output_f = File::create(&output_file_path)
+            .expect(&format!("Error in creating output file {:?}", &output_file_path));
+        output_f.write_fmt(format_args!("purity\tploidy\tlogL\tmaxlogL-logL\tlogL_ratio\tlogL_snp\t\
+            rc_ratio_of_cp_2\tperiod\tno_of_copy_levels\tno_of_maf_states\tlog_prior\n")).unwrap();
+        let mut purity_best_vec: Vec<&Solution> = Vec::new();
+        for solution in solution_vec.iter() {
+            let is_new_purity = purity_best_vec.last().map_or(true, |last| last.purity != solution.purity);
+            if is_new_purity {
+                purity_best_vec.push(solution);
+            } else if solution.log_posterior() > purity_best_vec.last().unwrap().log_posterior() {
+                *purity_best_vec.last_mut().unwrap() = solution;
+            }
+        }
+        for solution in purity_best_vec {
+            output_f.write_fmt(format_args!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n", solution.purity,
+                                            solution.ploidy, solution.log_likelihood,
+                                            best_solution.log_posterior() - solution.log_posterior(),
+                                            solution.ratio_log_likelihood, solution.snp_log_likelihood,
+                                            solution.ratio_of_cp_2(), solution.period(),
+                                            solution.no_of_copy_levels, solution.no_of_maf_states,
+                                            solution.log_prior)).unwrap();
+        }
+    }
+
+    /// Local optima of the search, best first, so that an alternative (e.g. the whole-genome-doubled twin)
+    /// can be picked deliberately and given to call.
+    fn output_optima(&self, optimum_vec: &[&Solution]) {
+        let output_file_path = self.output_dir.join("infer.out.optima.tsv");
+        println_stderr!("Outputting {} local optima to {:?} ...", optimum_vec.len(), output_file_path);
+        let mut output_f = File::create(&output_file_path)
+            .expect(&format!("Error in creating output file {:?}", &output_file_path));
+        output_f.write_fmt(format_args!("rank\tpurity\tploidy\tlogL\tlogL_ratio\tlogL_snp\tlog_prior\t\
+            maxlogL-logL\tinteger_fit_fraction\trc_ratio_of_cp_2\tperiod\n")).unwrap();
+        for (rank, solution) in optimum_vec.iter().enumerate() {
+            output_f.write_fmt(format_args!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.4}\t{:.5}\t{:.5}\n", rank + 1,
+                                            solution.purity, solution.ploidy, solution.log_likelihood,
+                                            solution.ratio_log_likelihood, solution.snp_log_likelihood,
+                                            solution.log_prior,
+                                            optimum_vec[0].log_posterior() - solution.log_posterior(),
+                                            solution.integer_fit_fraction, solution.ratio_of_cp_2(),
+                                            solution.period())).unwrap();
+        }
+    }
+
+    /// The log-likelihood surface, one line per purity x ploidy grid point.
+    fn output_grid(&self, solution_vec: &[Solution]) {
+        let output_file_path = self.output_dir.join("infer.out.grid.tsv");
+        let mut output_f = File::create(&output_file_path)
+            .expect(&format!("Error in creating output file {:?}", &output_file_path));
+        output_f.write_fmt(format_args!("purity\tploidy\tlogL\tlogL_ratio\tlogL_snp\tlog_prior\t\
+            integer_fit_fraction\n")).unwrap();
+        for solution in solution_vec.iter() {
+            output_f.write_fmt(format_args!("{}\t{}\t{}\t{}\t{}\t{}\t{:.4}\n", solution.purity, solution.ploidy,
+                                            solution.log_likelihood, solution.ratio_log_likelihood,
+                                            solution.snp_log_likelihood, solution.log_prior,
+                                            solution.integer_fit_fraction)).unwrap();
+        }
+    }
+
+    pub fn run(&self) {
+        let (mut segment_vec, no_of_segments) = read_segment_file(self.segment_file_path, self.segment_stddev_divider,
+                                                                    MAX_RATIO);
+        if segment_vec.is_empty() {
+            panic!("No usable segments in {:?}.", self.segment_file_path);
+        }
+        let het_snp_data = read_het_snp_file(self.het_snp_file_path, self.snp_coverage_min);
+        let no_of_snps_used = assign_snps_to_segments(&mut segment_vec, &het_snp_data);
+        println_stderr!("{} SNPs in {} segments used.", no_of_snps_used,
+                        segment_vec.iter().filter(|segment| segment.no_of_snps > 0).count());
+        fs::create_dir_all(self.output_dir)
+            .expect(&format!("Error in creating output folder {:?}", self.output_dir));
+
+        let cor_vec = autocor::calc_autocor(&autocor::build_ratio_histogram(&segment_vec));
+        autocor::output_autocor(&cor_vec, &self.output_dir.join("auto.tsv"));
+        let candidate_period_vec = autocor::rank_candidate_periods(&cor_vec);
+        for candidate_period in candidate_period_vec.iter().take(3) {
+            println_stderr!("Candidate period from autocorrelation: {} [{}, {}], rise {}.", candidate_period.period,
+                            candidate_period.lower_bound, candidate_period.upper_bound, candidate_period.rise);
+        }
+
+        let peak_vec = peaks::PeakFinder::new(0.0, peaks::PEAK_HEIGHT_MIN).find_peaks(&segment_vec);
+        println_stderr!("{} peaks in the segment ratio density.", peak_vec.len());
+        peaks::output_peak_bounds(&peak_vec, &self.output_dir.join("peak_bounds.tsv"));
+
+        let scorer = Scorer::new(&segment_vec);
+        let (unconstrained_solution_vec, unconstrained_no_of_ploidies) =
+            self.search_grid(&scorer, Constraint::Free, Constraint::Free);
+        let unconstrained_best_solution = find_best_solution(&unconstrained_solution_vec);
+        println_stderr!("Best solution: purity={}, ploidy={}, logL={} (ratio {}, SNP {}).",
+                        unconstrained_best_solution.purity, unconstrained_best_solution.ploidy,
+                        unconstrained_best_solution.log_likelihood, unconstrained_best_solution.ratio_log_likelihood,
+                        unconstrained_best_solution.snp_log_likelihood);
+        let (solution_vec, no_of_ploidies) = if self.is_constrained() {
+            self.search_grid(&scorer, self.purity_constraint, self.ploidy_constraint)
+        } else {
+            (unconstrained_solution_vec, unconstrained_no_of_ploidies)
+        };
+        let best_solution = find_best_solution(&solution_vec);
+        if self.is_constrained() {
+            println_stderr!("Best solution with purity {} and ploidy {}: purity={}, ploidy={}, logL={}, log_prior={}.",
+                            self.purity_constraint, self.ploidy_constraint, best_solution.purity,
+                            best_solution.ploidy, best_solution.log_likelihood, best_solution.log_prior);
+        }
+        if self.debug > 0 {
+            println_stderr!("{} solutions searched.", solution_vec.len());
+        }
+        //segments without SNPs would be called LOH for lack of evidence, and those within germline ROH for lack
+        //of hets. Neither counts towards WGD, as in call.
+        let roh_segment_vec = self.roh_file_path.map(|roh_file_path| roh::read_roh_bed(roh_file_path));
+        let weight_copy_state_vec: Vec<(f64, CopyState)> = segment_vec.iter()
+            .filter(|segment| segment.no_of_snps > 0)
+            .filter(|segment| match roh_segment_vec {
+                Some(ref roh_segment_vec) => roh::calc_roh_fraction(roh_segment_vec, &segment.chr, segment.start - 1,
+                                                                    segment.end) <= call::GERMLINE_ROH_FRACTION_MAX,
+                None => true,
+            })
+            .map(|segment| (segment.no_of_windows as f64,
+                            call::call_segment(segment, best_solution.purity, best_solution.ploidy).copy_state))
+            .collect();
+        let wgd_status = wgd::detect_wgd(&weight_copy_state_vec, best_solution.ploidy);
+        println_stderr!("Whole-genome doubling: {}, {} doublings. {} of the genome has major copy number >= 2.",
+                        wgd_status.is_wgd, wgd_status.no_of_doublings, wgd_status.fraction_major_cn_ge_2);
+        self.output_infer_out(&best_solution, &unconstrained_best_solution, no_of_segments, segment_vec.len(),
+                              &het_snp_data, no_of_snps_used, &candidate_period_vec, &wgd_status);
+        self.output_infer_out_details(&solution_vec, &best_solution);
+        self.output_optima(&find_local_optima(&solution_vec, no_of_ploidies, self.no_of_optima));
+        self.output_grid(&solution_vec);
+        println_stderr!("Output done.");
+    }
+}
@@ -0,0 +1,150 @@
+use flate2;
+use rust_htslib::bcf;
+use rust_htslib::bcf::Read as bcfRead;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::{Path};
+
+
+pub struct KnownSite {
+    pub ref_allele: Vec<u8>,
+    pub alt_allele_vec: Vec<Vec<u8>>,
+    //None if the panel is a BED file or the VCF record has no AF tag.
+    pub population_af: Option<f32>,
+}
+
+impl KnownSite {
+    fn new(ref_allele: Vec<u8>, alt_allele_vec: Vec<Vec<u8>>, population_af: Option<f32>) -> KnownSite {
+        KnownSite {
+            ref_allele,
+            alt_allele_vec,
+            population_af,
+        }
+    }
+
+    /// A population SNP is a bi-allelic single-nucleotide record. BED sites carry no alleles and always pass.
+    pub fn is_snp(&self) -> bool {
+        if self.ref_allele.is_empty() {
+            return true;
+        }
+        self.ref_allele.len() == 1 && self.alt_allele_vec.len() == 1 && self.alt_allele_vec[0].len() == 1
+    }
+
+    /// Whether the ref/alt alleles of a called SNP agree with the panel. BED sites always match.
+    pub fn matches_alleles(&self, ref_allele: &[u8], alt_allele: &[u8]) -> bool {
+        if self.ref_allele.is_empty() {
+            return true;
+        }
+        self.ref_allele.as_slice() == ref_allele &&
+            self.alt_allele_vec.iter().any(|alt| alt.as_slice() == alt_allele)
+    }
+}
+
+/// A panel of known SNP sites (i.e. 1000 Genomes), keyed by (chromosome name, 0-based position).
+/// Only sites listed in `wanted_chr_pos_set` are kept in memory.
+pub struct KnownSites {
+    pub chr_pos2site: HashMap<(String, u64), KnownSite>,
+    pub has_population_af: bool,
+}
+
+impl KnownSites {
+    pub fn from_path(known_sites_path: &Path, wanted_chr_pos_set: &HashSet<(String, u64)>) -> KnownSites {
+        let path_str = known_sites_path.to_str().unwrap();
+        let mut known_sites = KnownSites {
+            chr_pos2site: HashMap::new(),
+            has_population_af: false,
+        };
+        if path_str.ends_with(".bed") || path_str.ends_with(".bed.gz") {
+            known_sites.read_in_bed(known_sites_path, wanted_chr_pos_set);
+        } else {
+            known_sites.read_in_vcf(known_sites_path, wanted_chr_pos_set);
+        }
+        println_stderr!("{} known sites out of {} queried sites.", known_sites.chr_pos2site.len(),
+                        wanted_chr_pos_set.len());
+        known_sites
+    }
+
+    pub fn get(&self, chr: &str, pos: u64) -> Option<&KnownSite> {
+        self.chr_pos2site.get(&(chr.to_string(), pos))
+    }
+
+    fn read_in_vcf(&mut self, known_sites_path: &Path, wanted_chr_pos_set: &HashSet<(String, u64)>) {
+        println_stderr!("Reading known sites from indexed VCF {:?} ...", known_sites_path);
+        //fetch only the span of wanted sites on each chromosome, through the VCF index.
+        let mut chr2span: HashMap<&str, (u64, u64)> = HashMap::new();
+        for &(ref chr, pos) in wanted_chr_pos_set.iter() {
+            let span = chr2span.entry(chr.as_str()).or_insert((pos, pos));
+            if pos < span.0 {
+                span.0 = pos;
+            }
+            if pos > span.1 {
+                span.1 = pos;
+            }
+        }
+        let mut vcf = bcf::IndexedReader::from_path(&known_sites_path)
+            .ok().expect("Error opening indexed known-sites file. Is it bgzipped and indexed?");
+        let vcf_header = vcf.header().clone();
+        let mut chr_vec: Vec<&str> = chr2span.keys().map(|chr| *chr).collect();
+        chr_vec.sort();
+        for chr in chr_vec {
+            let span = chr2span[chr];
+            let rid = match vcf_header.name2rid(chr.as_bytes()) {
+                Ok(rid) => rid,
+                Err(_) => {
+                    println_stderr!("WARNING: chromosome {} is not in the known-sites file.", chr);
+                    continue;
+                }
+            };
+            vcf.fetch(rid, span.0 as u32, span.1 as u32 + 1).ok().expect("Error fetching known-sites region.");
+            for rec in vcf.records() {
+                let mut record = rec.ok().expect("Error reading known-sites record.");
+                let key = (chr.to_string(), record.pos() as u64);
+                if !wanted_chr_pos_set.contains(&key) {
+                    continue;
+                }
+                let population_af = match record.info(b"AF").float() {
+                    Ok(Some(af_vec)) => Some(af_vec[0]),
+                    _ => None,
+                };
+                if population_af.is_some() {
+                    self.has_population_af = true;
+                }
+                let alleles = record.alleles();
+                let ref_allele = alleles[0].to_vec();
+                let alt_allele_vec = alleles[1..].iter().map(|allele| allele.to_vec()).collect();
+                self.chr_pos2site.insert(key, KnownSite::new(ref_allele, alt_allele_vec, population_af));
+            }
+        }
+    }
+
+    fn read_in_bed(&mut self, known_sites_path: &Path, wanted_chr_pos_set: &HashSet<(String, u64)>) {
+        println_stderr!("Reading known sites from BED {:?} ...", known_sites_path);
+        let input_f = File::open(&known_sites_path)
+            .expect(&format!("Error in opening known-sites file {:?}", &known_sites_path));
+        let reader: Box<BufRead> = if known_sites_path.to_str().unwrap().ends_with(".gz") {
+            Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(input_f)
+                .expect("Error in opening gzipped known-sites file.")))
+        } else {
+            Box::new(BufReader::new(input_f))
+        };
+        for line in reader.lines() {
+            let line = line.expect("Error reading known-sites line.");
+            if line.starts_with('#') || line.starts_with("track") || line.starts_with("browser") {
+                continue;
+            }
+            let mut field_iter = line.split('\t');
+            let chr = field_iter.next().unwrap();
+            let start: u64 = field_iter.next().unwrap().parse().unwrap();
+            let end: u64 = field_iter.next().unwrap().parse().unwrap();
+            //BED start is 0-based, same as VCF record.pos(). One BED interval could cover several sites.
+            for pos in start..end {
+                let key = (chr.to_string(), pos);
+                if wanted_chr_pos_set.contains(&key) {
+                    self.chr_pos2site.insert(key, KnownSite::new(vec![], vec![], None));
+                }
+            }
+        }
+    }
+}
@@ -56,12 +56,58 @@ pub fn calc_median_i32(numbers: &mut Vec<i32>) -> i32 {
 
 }
 
+pub mod allele_count;
+
+pub mod autocor;
+
+pub mod call;
+
+pub mod check_pair;
+
+pub mod concat;
+
+pub mod contamination;
+
+pub mod genotype;
+
+pub mod haplotype_block;
+
+pub mod het_snp_vcf;
+
+pub mod infer;
+
+pub mod known_sites;
+
+pub mod maf_model;
+
+pub mod merge_features;
+
 pub mod select_het_snp;
 
 pub mod normalize;
 
+pub mod peaks;
+
+pub mod prob;
+
+pub mod ref_bias;
+
 pub mod recall_precision;
 
+pub mod region;
+
+pub mod roh;
+
+pub mod segment;
+
+pub mod sex;
+
+pub mod subclone;
+
+pub mod tumor_in_normal;
+
+pub mod wgd;
+
 pub fn gc_index(input_filename: &str, output_dir: &str) {
     print_stderr!("Opening file {} ...", input_filename);
     let reader = fasta::Reader::from_file(input_filename).unwrap();
@@ -0,0 +1,197 @@
+use prob::{ln_choose, ln_gamma};
+
+
+//purity=1 leaves no normal cells, where MAF of copy number 0 is undefined. Purity stops just short.
+const MAX_PURITY: f64 = 0.999;
+
+/// Total and minor (the less frequent allele) copy number of a tumor segment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CopyState {
+    pub total_cn: usize,
+    pub minor_cn: usize,
+}
+
+impl CopyState {
+    pub fn new(total_cn: usize, minor_cn: usize) -> CopyState {
+        if 2 * minor_cn > total_cn {
+            panic!("minor copy number {} exceeds half of total copy number {}.", minor_cn, total_cn);
+        }
+        CopyState { total_cn, minor_cn }
+    }
+
+    pub fn major_cn(&self) -> usize {
+        self.total_cn - self.minor_cn
+    }
+
+    /// Expected coverage ratio and expected tumor_maf_normalized of het SNPs with depth depth.
+    /// depth 0 returns the unfolded MAF.
+    pub fn expected_ratio_maf(&self, purity: f64, ploidy: f64, depth: usize) -> (f64, f64) {
+        let maf = expected_maf(purity, self.total_cn, self.major_cn());
+        (expected_ratio(purity, ploidy, self.total_cn),
+         if depth > 0 { calc_folded_maf(maf, depth) } else { maf })
+    }
+}
+
+/// Purity capped at MAX_PURITY, for infer, call and subclone alike.
+pub fn clamp_purity(purity: f64) -> f64 {
+    purity.min(MAX_PURITY)
+}
+
+/// Expected tumor/normal coverage ratio of a segment at total copy number cn. The normal is diploid.
+pub fn expected_ratio(purity: f64, ploidy: f64, cn: usize) -> f64 {
+    (2.0 * (1.0 - purity) + cn as f64 * purity) / (2.0 * (1.0 - purity) + ploidy * purity)
+}
+
+/// Expected fraction of the major allele of a het SNP at total copy number cn and major copy number major_cn,
+/// i.e. maf_expected of infer.cpp. Undefined for cn=0 at purity 1.
+pub fn expected_maf(purity: f64, cn: usize, major_cn: usize) -> f64 {
+    (1.0 - purity + major_cn as f64 * purity) / (2.0 - 2.0 * purity + cn as f64 * purity)
+}
+
+/// E[max(k, n-k)/n] of k ~ Binomial(n=depth, maf), i.e. the MAF observed after taking the larger allele,
+/// as intersect_snp() of select_het_snp does. Larger than maf at low depth, even when maf is 0.5.
+pub fn calc_folded_maf(maf: f64, depth: usize) -> f64 {
+    if depth == 0 || maf <= 0.0 || maf >= 1.0 {
+        return maf.max(1.0 - maf);
+    }
+    let mut folded_maf = 0f64;
+    for k in 0..(depth + 1) {
+        let ln_pmf = ln_choose(depth, k) + k as f64 * maf.ln() + (depth - k) as f64 * (1.0 - maf).ln();
+        folded_maf += ln_pmf.exp() * (if 2 * k >= depth { k } else { depth - k }) as f64 / depth as f64;
+    }
+    folded_maf
+}
+
+/// calc_folded_maf() averaged over SNP depths >= depth_min, which follow a Poisson (depth_var <= 1.1*depth_mean)
+/// or negative binomial distribution of mean depth_mean and variance depth_var.
+/// Same as adjust_maf_expect() of infer.cpp, except on the linear rather than log10 scale.
+pub fn calc_folded_maf_over_depth(maf: f64, depth_mean: f64, depth_var: f64, depth_min: usize) -> f64 {
+    let depth_max = (depth_mean * 10.0).ceil() as usize;
+    let mut folded_maf_sum = 0f64;
+    let mut cdf = 0f64;
+    for depth in depth_min.max(1)..depth_max {
+        let k = depth as f64;
+        let ln_pmf = if depth_var <= 1.1 * depth_mean {
+            -depth_mean + k * depth_mean.ln() - ln_gamma(k + 1.0)
+        } else {
+            //p: success probability, r: number of failures, as neg_bi_repara() of prob.cpp
+            let p = 1.0 - depth_mean / depth_var;
+            let r = depth_mean * depth_mean / (depth_var - depth_mean);
+            ln_gamma(k + r) - ln_gamma(k + 1.0) - ln_gamma(r) + r * (1.0 - p).ln() + k * p.ln()
+        };
+        let pmf = ln_pmf.exp();
+        folded_maf_sum += pmf * calc_folded_maf(maf, depth);
+        cdf += pmf;
+    }
+    if cdf > 0.0 { folded_maf_sum / cdf } else { maf.max(1.0 - maf) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn ratio_of_pure_tumor_is_cn_over_ploidy() {
+        assert_close(expected_ratio(1.0, 2.0, 2), 1.0);
+        assert_close(expected_ratio(1.0, 2.0, 4), 2.0);
+        assert_close(expected_ratio(1.0, 3.0, 1), 1.0 / 3.0);
+    }
+
+    #[test]
+    fn ratio_of_normal_is_one() {
+        for cn in 0..6 {
+            assert_close(expected_ratio(0.0, 3.2, cn), 1.0);
+        }
+    }
+
+    #[test]
+    fn ratio_of_ploidy_cn_is_one() {
+        assert_close(expected_ratio(0.37, 3.0, 3), 1.0);
+        assert_close(expected_ratio(0.8, 2.0, 2), 1.0);
+    }
+
+    #[test]
+    fn ratio_levels_are_periodic() {
+        let (purity, ploidy) = (0.6, 2.4);
+        let period = purity / (2.0 * (1.0 - purity) + ploidy * purity);
+        for cn in 0..6 {
+            assert_close(expected_ratio(purity, ploidy, cn + 1) - expected_ratio(purity, ploidy, cn), period);
+        }
+    }
+
+    #[test]
+    fn maf_of_balanced_state_is_half() {
+        for purity in [0.1, 0.5, 0.9, 1.0].iter() {
+            assert_close(expected_maf(*purity, 2, 1), 0.5);
+            assert_close(expected_maf(*purity, 4, 2), 0.5);
+        }
+    }
+
+    #[test]
+    fn maf_of_loh() {
+        assert_close(expected_maf(1.0, 1, 1), 1.0);
+        assert_close(expected_maf(1.0, 2, 2), 1.0);
+        //one normal copy of each allele plus one tumor copy of the major allele, each cell half the sample
+        assert_close(expected_maf(0.5, 1, 1), 2.0 / 3.0);
+        assert_close(expected_maf(0.0, 1, 1), 0.5);
+    }
+
+    #[test]
+    fn maf_of_gain() {
+        assert_close(expected_maf(1.0, 3, 2), 2.0 / 3.0);
+        assert_close(expected_maf(0.5, 3, 2), 3.0 / 5.0);
+    }
+
+    #[test]
+    fn folded_maf_at_low_depth() {
+        assert_close(calc_folded_maf(0.5, 1), 1.0);
+        //k=0,1,2 with probability 1/4, 1/2, 1/4
+        assert_close(calc_folded_maf(0.5, 2), 0.75);
+        assert_close(calc_folded_maf(0.5, 3), 0.75);
+        assert_close(calc_folded_maf(1.0, 7), 1.0);
+        assert_close(calc_folded_maf(0.2, 0), 0.8);
+    }
+
+    #[test]
+    fn folded_maf_is_symmetric() {
+        assert_close(calc_folded_maf(0.3, 25), calc_folded_maf(0.7, 25));
+    }
+
+    #[test]
+    fn folded_maf_converges_at_high_depth() {
+        assert!((calc_folded_maf(0.8, 1000) - 0.8).abs() < 1e-3);
+        assert!(calc_folded_maf(0.5, 1000) > 0.5);
+        assert!(calc_folded_maf(0.5, 1000) < 0.52);
+    }
+
+    #[test]
+    fn folded_maf_over_depth_is_between_depths() {
+        let folded_maf = calc_folded_maf_over_depth(0.5, 30.0, 30.0, 2);
+        assert!(folded_maf > calc_folded_maf(0.5, 60));
+        assert!(folded_maf < calc_folded_maf(0.5, 10));
+        let folded_maf = calc_folded_maf_over_depth(0.5, 30.0, 90.0, 2);
+        assert!(folded_maf > calc_folded_maf(0.5, 100));
+        assert!(folded_maf < calc_folded_maf(0.5, 2));
+    }
+
+    #[test]
+    fn copy_state() {
+        let copy_state = CopyState::new(3, 1);
+        assert_eq!(copy_state.major_cn(), 2);
+        let (ratio, maf) = copy_state.expected_ratio_maf(1.0, 2.0, 0);
+        assert_close(ratio, 1.5);
+        assert_close(maf, 2.0 / 3.0);
+        let (_, folded_maf) = copy_state.expected_ratio_maf(1.0, 2.0, 30);
+        assert!(folded_maf > maf);
+    }
+
+    #[test]
+    #[should_panic]
+    fn copy_state_minor_over_half() {
+        CopyState::new(2, 2);
+    }
+}
@@ -188,6 +188,199 @@ fn main() {
                 .required(true)
                 .takes_value(true)
             )
+            .arg(Arg::with_name("known_sites")
+                .long("known_sites")
+                .value_name("KNOWN SITES FILE")
+                .help("A bgzipped and indexed VCF/BED of population SNPs (i.e. 1000 Genomes). \
+                    If given, only hets at these sites are kept.")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("min_population_af")
+                .long("min_population_af")
+                .value_name("MINIMUM POPULATION AF")
+                .help("Known sites whose population minor allele frequency (AF tag in the VCF) is below this are ignored. \
+                    A panel without AF is an error unless this is 0. Default is 0.")
+                .requires("known_sites")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("multi_allelic_het")
+                .long("multi_allelic_het")
+                .help("Also accept hets between two alternative alleles (i.e. 1/2) in multi-allelic records. \
+                    By default, multi-allelic records are skipped.")
+            )
+            .arg(Arg::with_name("phase_block_output")
+                .long("phase_block_output")
+                .value_name("OUTPUT FILE")
+                .help("If given, phased hets are grouped by the phase set (PS) of the normal and \
+                    a haplotype-block-level BAF table is written into this file.")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("phased_normal_vcf")
+                .long("phased_normal_vcf")
+                .value_name("PHASED NORMAL VCF")
+                .help("A phased normal VCF (GT with |, and PS) from an external phasing run. \
+                    Its phase replaces that of the normal SNP file.")
+                .requires("phase_block_output")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("region")
+                .long("region")
+                .value_name("REGION")
+                .help("Only read this region, chr, chr:start or chr:start-end (1-based, inclusive), of the SNP files. \
+                    They have to be bgzipped and indexed. Shards run this way can be merged by the concat subcommand. \
+                    Requires --sex male or female, and --beta_binomial_rho with --beta_binomial_pvalue_min.")
+                .conflicts_with("regions_file")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("regions_file")
+                .long("regions_file")
+                .value_name("BED FILE")
+                .help("Only read regions of this BED file of the SNP files. They have to be bgzipped and indexed. \
+                    Same requirements as --region.")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("sex")
+                .long("sex")
+                .value_name("SEX")
+                .help("Sex of the individual. Hets on chrX outside the pseudo-autosomal regions are dropped for males. \
+                    auto infers it from the het ratio of chrX calls in the normal. If there are no non-PAR chrX or no \
+                    autosome calls, sex is unknown and chrX hets are kept.")
+                .possible_values(&["male", "female", "auto"])
+                .default_value("auto")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("genome_build")
+                .long("genome_build")
+                .value_name("GENOME BUILD")
+                .help("Genome build of the SNP files, for the coordinates of the chrX pseudo-autosomal regions.")
+                .possible_values(&["hg19", "GRCh37", "hg38", "GRCh38"])
+                .default_value("hg19")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("roh_output")
+                .long("roh_output")
+                .value_name("OUTPUT BED")
+                .help("If given, runs of homozygosity in the normal (germline UPD, consanguinity) are written into this BED. \
+                    Give it to call by --roh_file so that they are not taken as tumor LOH. \
+                    With --region or --regions_file, genotyped sites of the normal are written instead (gzipped), \
+                    from which concat --roh_input finds runs across shards.")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("min_roh_len")
+                .long("min_roh_len")
+                .value_name("MINIMUM ROH LENGTH")
+                .help("Runs of homozygosity shorter than this (bp) are ignored.")
+                .default_value("1000000")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("vcf_output")
+                .long("vcf_output")
+                .value_name("OUTPUT VCF")
+                .help("If given, selected hets are also written into this bgzipped, tabix-indexed VCF (.vcf.gz), \
+                    with tumor and normal RO/AO/MAF as FORMAT fields and filter settings as header lines.")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("contamination_qc")
+                .long("contamination_qc")
+                .value_name("QC FILE")
+                .help("If given, foreign-DNA contamination of tumor and normal is estimated by maximum likelihood from \
+                    allele reads of genotyped SNPs, given population AF of known_sites (VCF with the AF tag), \
+                    after subtracting the sequencing error rate of sites of rare alleles. \
+                    Estimates and their 95% confidence intervals go into this file and the output header.")
+                .requires("known_sites")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("beta_binomial_pvalue_min")
+                .long("beta_binomial_pvalue_min")
+                .value_name("MINIMUM P-VALUE")
+                .help("If given, abp_max_normal is not used. Instead, a beta-binomial overdispersion is fitted to \
+                    allele counts of normal hets genome-wide and hets whose allele balance has a p-value below this \
+                    (under a 50/50 germline het) are dropped. The fitted overdispersion goes to the output header.")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("beta_binomial_rho")
+                .long("beta_binomial_rho")
+                .value_name("RHO")
+                .help("Use this overdispersion in the beta-binomial test instead of fitting it. Required with \
+                    --region or --regions_file, i.e. the rho of a whole-genome run, so that all shards share it.")
+                .requires("beta_binomial_pvalue_min")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("debug")
+                .short("d")
+                .long("debug")
+                .help("Debug mode. NOT used. no difference.")
+                .takes_value(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("allele_count")
+            .about("Count alleles of tumor and normal bams at known SNP sites and select normal hets")
+            .version("32acfd1e-debug")
+            .author("www.yfish.org")
+            .arg(Arg::with_name("tumor_bam")
+                .short("t")
+                .long("tumor_bam")
+                .value_name("TUMOR BAM FILE")
+                .help("The indexed tumor bam file.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("normal_bam")
+                .short("n")
+                .long("normal_bam")
+                .value_name("NORMAL BAM FILE")
+                .help("The indexed normal bam file.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("known_sites")
+                .short("k")
+                .long("known_sites")
+                .value_name("KNOWN SITES FILE")
+                .help("A VCF/BCF of population SNPs (i.e. 1000 Genomes). Only bi-allelic SNPs are counted.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("min_base_quality")
+                .short("q")
+                .long("min_base_quality")
+                .value_name("MINIMUM BASE QUALITY")
+                .help("Bases with quality below this are ignored.")
+                .default_value("20")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("min_mapping_quality")
+                .short("Q")
+                .long("min_mapping_quality")
+                .value_name("MINIMUM MAPPING QUALITY")
+                .help("Reads with mapping quality below this are ignored.")
+                .default_value("30")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("max_coverage")
+                .short("x")
+                .long("max_coverage")
+                .value_name("MAXIMUM COVERAGE")
+                .help("Coverage above this value is ignored.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("min_coverage")
+                .short("m")
+                .long("min_coverage")
+                .value_name("MINIMUM COVERAGE")
+                .help("Coverage below this value is ignored.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("output_file_path")
+                .short("o")
+                .long("output_file_path")
+                .value_name("OUTPUT FILE")
+                .help("The output file, in the same format as that of select_het_snp.")
+                .required(true)
+                .takes_value(true)
+            )
             .arg(Arg::with_name("debug")
                 .short("d")
                 .long("debug")
@@ -195,18 +388,502 @@ fn main() {
                 .takes_value(true)
             )
         )
+        .subcommand(SubCommand::with_name("check_pair")
+            .about("Check that tumor and normal come from the same individual by genotype concordance")
+            .version("32acfd1e-debug")
+            .author("www.yfish.org")
+            .arg(Arg::with_name("snp_file_path_tumor")
+                .short("t")
+                .long("snp_file_path_tumor")
+                .value_name("TUMOR SNP FILE")
+                .help("The tumor SNP VCF file, as given to select_het_snp.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("snp_file_path_normal")
+                .short("n")
+                .long("snp_file_path_normal")
+                .value_name("NORMAL SNP FILE")
+                .help("The normal SNP VCF file, as given to select_het_snp.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("min_coverage")
+                .short("m")
+                .long("min_coverage")
+                .value_name("MINIMUM COVERAGE")
+                .help("SNPs with FORMAT/DP below this are ignored.")
+                .default_value("10")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("max_hom_opposite_fraction")
+                .long("max_hom_opposite_fraction")
+                .value_name("MAXIMUM FRACTION")
+                .help("Warn if the fraction of sites homozygous in both tumor and normal that are homozygous for \
+                    different alleles is above this. It is near 0 for the same individual, whatever the tumor LOH, \
+                    and about 0.1 for unrelated ones.")
+                .default_value("0.05")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("output_file_path")
+                .short("o")
+                .long("output_file_path")
+                .value_name("OUTPUT FILE")
+                .help("The output file to contain the concordance report")
+                .required(true)
+                .takes_value(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("concat")
+            .about("Concatenate het SNP outputs of select_het_snp run on separate regions")
+            .version("32acfd1e-debug")
+            .author("www.yfish.org")
+            .arg(Arg::with_name("input_file_path")
+                .short("i")
+                .long("input_file_path")
+                .value_name("INPUT FILES")
+                .help("Outputs of select_het_snp --region/--regions_file on disjoint regions, in genomic order.")
+                .required(true)
+                .multiple(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("output_file_path")
+                .short("o")
+                .long("output_file_path")
+                .value_name("OUTPUT FILE")
+                .help("The merged output file")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("contamination_qc")
+                .long("contamination_qc")
+                .value_name("QC FILE")
+                .help("If given, contamination is re-estimated from the profiles of all shards \
+                    (select_het_snp --contamination_qc) and written into this file.")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("phase_block_input")
+                .long("phase_block_input")
+                .value_name("INPUT FILES")
+                .help("--phase_block_output of select_het_snp of the shards.")
+                .requires("phase_block_output")
+                .multiple(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("phase_block_output")
+                .long("phase_block_output")
+                .value_name("OUTPUT FILE")
+                .help("The merged haplotype blocks. Blocks of a phase set split across shards are joined.")
+                .requires("phase_block_input")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("roh_input")
+                .long("roh_input")
+                .value_name("INPUT FILES")
+                .help("--roh_output of select_het_snp of the shards, i.e. genotyped sites of the normal.")
+                .requires("roh_output")
+                .multiple(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("roh_output")
+                .long("roh_output")
+                .value_name("OUTPUT BED")
+                .help("Runs of homozygosity in the normal found over the sites of all shards, for call --roh_file.")
+                .requires("roh_input")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("vcf_output")
+                .long("vcf_output")
+                .value_name("OUTPUT VCF")
+                .help("If given, the merged hets are also written into this bgzipped, tabix-indexed VCF (.vcf.gz), \
+                    as select_het_snp --vcf_output does.")
+                .takes_value(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("merge_features")
+            .about("Join coverage ratios of normalize and het SNPs of select_het_snp into one tabix-indexed file of \
+                    windows (coverage ratio, no. of SNPs, median BAF) and SNPs (BAF, coverage ratio of the window).")
+            .version("32acfd1e-debug")
+            .author("www.yfish.org")
+            .arg(Arg::with_name("het_snp_file_path")
+                .short("s")
+                .long("het_snp_file_path")
+                .value_name("HET SNP FILE")
+                .help("The output of select_het_snp.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("ratio_folder")
+                .short("r")
+                .long("ratio_folder")
+                .value_name("RATIO FOLDER")
+                .help("The output folder of normalize.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("window_size")
+                .short("w")
+                .long("window_size")
+                .value_name("WINDOW SIZE")
+                .help("The window size given to normalize.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("output_file_path")
+                .short("o")
+                .long("output_file_path")
+                .value_name("OUTPUT FILE")
+                .help("The bgzipped output file. A tabix index (.tbi) is written next to it.")
+                .required(true)
+                .takes_value(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("segment")
+            .about("Segment coverage ratios from normalize, or jointly with BAFs from merge_features, into \
+                    piecewise-constant segments (binary segmentation and backward elimination, as in GADA). \
+                    Output is compatible with all_segments.tsv.gz.")
+            .version("32acfd1e-debug")
+            .author("www.yfish.org")
+            .arg(Arg::with_name("ratio_folder")
+                .short("r")
+                .long("ratio_folder")
+                .value_name("RATIO FOLDER")
+                .help("The output folder of normalize.")
+                .required_unless("merged_features_file_path")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("window_size")
+                .short("w")
+                .long("window_size")
+                .value_name("WINDOW SIZE")
+                .help("The window size given to normalize.")
+                .required_unless("merged_features_file_path")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("merged_features_file_path")
+                .short("m")
+                .long("merged_features_file_path")
+                .value_name("MERGED FEATURES FILE")
+                .help("The output of merge_features. If given, segment coverage ratios and median mirrored BAFs of windows \
+                       jointly, keeping breakpoints supported by either, and add the mean mirrored BAF and the number of \
+                       het SNPs of each segment to the output.")
+                .conflicts_with_all(&["ratio_folder", "window_size"])
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("min_segment_len")
+                .short("M")
+                .long("min_segment_len")
+                .value_name("MINIMUM SEGMENT LENGTH")
+                .help("Minimum number of windows in a segment.")
+                .default_value("50")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("t_score_threshold")
+                .short("T")
+                .long("t_score_threshold")
+                .value_name("T SCORE THRESHOLD")
+                .help("A breakpoint is kept only if the t-score of the mean ratio (or, with --merged_features_file_path, mirrored BAF) difference of its two sides is at least this.")
+                .default_value("20")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("output_file_path")
+                .short("o")
+                .long("output_file_path")
+                .value_name("OUTPUT FILE")
+                .help("The gzipped segment file, i.e. all_segments.tsv.gz")
+                .required(true)
+                .takes_value(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("peaks")
+            .about("Find copy number peaks in the kernel density of segment coverage ratios. \
+                    Output peak_bounds.tsv, peaks.tsv and ratio_density.tsv.")
+            .version("32acfd1e-debug")
+            .author("www.yfish.org")
+            .arg(Arg::with_name("segment_file_path")
+                .short("s")
+                .long("segment_file_path")
+                .value_name("SEGMENT FILE")
+                .help("The gzipped segment file of coverage ratios, i.e. all_segments.tsv.gz of GADA or the output of segment.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("output_dir")
+                .short("o")
+                .long("output_dir")
+                .value_name("OUTPUT FOLDER")
+                .help("The output folder. Created if not existent.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("segment_stddev_divider")
+                .long("segment_stddev_divider")
+                .value_name("SEGMENT STDDEV DIVIDER")
+                .help("The stddev of each segment is divided by this, as in the C++ infer.")
+                .default_value("20")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("bandwidth")
+                .short("b")
+                .long("bandwidth")
+                .value_name("BANDWIDTH")
+                .help("The kernel bandwidth in coverage ratio. 0 uses the stddev of each segment.")
+                .default_value("0")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("min_peak_height")
+                .long("min_peak_height")
+                .value_name("MIN PEAK HEIGHT")
+                .help("Peaks lower than this (in windows per 0.001 of coverage ratio) are ignored.")
+                .default_value("100")
+                .takes_value(true)
+            )
+        )
         .subcommand(SubCommand::with_name("infer")
             .about("infers tumor purity, ploidy from tumor-normal WGS data")
             .version("32acfd1e-debug")
             .author("www.yfish.org")
-            .arg(Arg::with_name("config")
-                .short("c")
-                .long("config")
-                .value_name("FILE")
-                .help("Sets a custom config file")
+            .arg(Arg::with_name("segment_file_path")
+                .short("s")
+                .long("segment_file_path")
+                .value_name("SEGMENT FILE")
+                .help("The gzipped segment file of coverage ratios, i.e. all_segments.tsv.gz of GADA or the output of segment.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("het_snp_file_path")
+                .short("n")
+                .long("het_snp_file_path")
+                .value_name("HET SNP FILE")
+                .help("The gzipped het SNP output of select_het_snp, i.e. het_snp.tsv.gz")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("output_dir")
+                .short("o")
+                .long("output_dir")
+                .value_name("OUTPUT FOLDER")
+                .help("The folder to hold infer.out.tsv and infer.out.details.tsv. Created if not existent.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("segment_stddev_divider")
+                .long("segment_stddev_divider")
+                .value_name("SEGMENT STDDEV DIVIDER")
+                .help("The stddev of each segment is divided by this, as in the C++ infer.")
+                .default_value("20")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("snp_coverage_min")
+                .long("snp_coverage_min")
+                .value_name("SNP COVERAGE MIN")
+                .help("Het SNPs with tumor depth below this are ignored.")
+                .default_value("2")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("purity_fixed")
+                .long("purity_fixed")
+                .value_name("PURITY")
+                .help("Fix purity at this value, e.g. from pathology.")
+                .conflicts_with_all(&["purity_range", "purity_prior"])
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("purity_range")
+                .long("purity_range")
+                .value_name("MIN,MAX")
+                .help("Restrict purity to this range, within [0.05, 1] and holding at least one multiple of 0.01.")
+                .conflicts_with("purity_prior")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("purity_prior")
+                .long("purity_prior")
+                .value_name("MEAN,STDDEV")
+                .help("A Gaussian prior on purity, added to the log-likelihood. The mean is within [0.05, 1].")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("ploidy_fixed")
+                .long("ploidy_fixed")
+                .value_name("PLOIDY")
+                .help("Fix ploidy at this value, e.g. from the DNA index of flow cytometry.")
+                .conflicts_with_all(&["ploidy_range", "ploidy_prior"])
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("ploidy_range")
+                .long("ploidy_range")
+                .value_name("MIN,MAX")
+                .help("Restrict ploidy to this range, within [1, 4] and holding at least one multiple of 0.02.")
+                .conflicts_with("ploidy_prior")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("ploidy_prior")
+                .long("ploidy_prior")
+                .value_name("MEAN,STDDEV")
+                .help("A Gaussian prior on ploidy, added to the log-likelihood. The mean is within [1, 4].")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("no_of_optima")
+                .short("N")
+                .long("no_of_optima")
+                .value_name("NUMBER OF OPTIMA")
+                .help("The number of best local optima of purity and ploidy to output in infer.out.optima.tsv.")
+                .default_value("5")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("roh_file")
+                .long("roh_file")
+                .value_name("ROH BED")
+                .help("Runs of homozygosity of the normal, i.e. --roh_output of select_het_snp. Segments mostly within \
+                    them are left out of whole-genome-doubling detection.")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("debug")
+                .short("d")
+                .long("debug")
+                .help("Debug level. 0 is no debug output.")
+                .takes_value(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("call")
+            .about("Call absolute allele-specific copy numbers of segments given purity and ploidy, \
+                    which are read from infer.out.tsv or given by --purity and --ploidy.")
+            .version("32acfd1e-debug")
+            .author("www.yfish.org")
+            .arg(Arg::with_name("segment_file_path")
+                .short("s")
+                .long("segment_file_path")
+                .value_name("SEGMENT FILE")
+                .help("The gzipped segment file of coverage ratios, i.e. all_segments.tsv.gz of GADA or the output of segment.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("het_snp_file_path")
+                .short("n")
+                .long("het_snp_file_path")
+                .value_name("HET SNP FILE")
+                .help("The gzipped het SNP output of select_het_snp, i.e. het_snp.tsv.gz")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("infer_out_file_path")
+                .short("i")
+                .long("infer_out_file_path")
+                .value_name("INFER OUTPUT FILE")
+                .help("infer.out.tsv of infer, to get purity and ploidy from. Required unless --purity and --ploidy are given.")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("purity")
+                .short("p")
+                .long("purity")
+                .value_name("PURITY")
+                .help("Tumor purity. Overrides infer.out.tsv.")
+                .requires("ploidy")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("ploidy")
+                .short("P")
+                .long("ploidy")
+                .value_name("PLOIDY")
+                .help("Tumor ploidy. Overrides infer.out.tsv.")
+                .requires("purity")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("output_file_path")
+                .short("o")
+                .long("output_file_path")
+                .value_name("OUTPUT FILE")
+                .help("The output file of per-segment copy numbers, i.e. cnv.output.tsv")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("segment_stddev_divider")
+                .long("segment_stddev_divider")
+                .value_name("SEGMENT STDDEV DIVIDER")
+                .help("The stddev of each segment is divided by this, as in the C++ infer.")
+                .default_value("20")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("snp_coverage_min")
+                .long("snp_coverage_min")
+                .value_name("SNP COVERAGE MIN")
+                .help("Het SNPs with tumor depth below this are ignored.")
+                .default_value("2")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("roh_file")
+                .long("roh_file")
+                .value_name("ROH BED")
+                .help("Runs of homozygosity of the normal, i.e. --roh_output of select_het_snp. Segments mostly within \
+                    them have no hets to tell LOH apart, so their allele-specific copy numbers are NA and they are \
+                    left out of whole-genome-doubling detection.")
+                .takes_value(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("subclone")
+            .about("Detect subclonal copy number segments, each a mixture of two adjacent copy states, given purity \
+                    and ploidy, which are read from infer.out.tsv or given by --purity and --ploidy.")
+            .version("32acfd1e-debug")
+            .author("www.yfish.org")
+            .arg(Arg::with_name("segment_file_path")
+                .short("s")
+                .long("segment_file_path")
+                .value_name("SEGMENT FILE")
+                .help("The gzipped segment file of coverage ratios, i.e. all_segments.tsv.gz of GADA or the output of segment.")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("het_snp_file_path")
+                .short("n")
+                .long("het_snp_file_path")
+                .value_name("HET SNP FILE")
+                .help("The gzipped het SNP output of select_het_snp, i.e. het_snp.tsv.gz")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("infer_out_file_path")
+                .short("i")
+                .long("infer_out_file_path")
+                .value_name("INFER OUTPUT FILE")
+                .help("infer.out.tsv of infer, to get purity and ploidy from. Required unless --purity and --ploidy are given.")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("purity")
+                .short("p")
+                .long("purity")
+                .value_name("PURITY")
+                .help("Tumor purity. Overrides infer.out.tsv.")
+                .requires("ploidy")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("ploidy")
+                .short("P")
+                .long("ploidy")
+                .value_name("PLOIDY")
+                .help("Tumor ploidy. Overrides infer.out.tsv.")
+                .requires("purity")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("output_dir")
+                .short("o")
+                .long("output_dir")
+                .value_name("OUTPUT FOLDER")
+                .help("The folder to hold subclone.segments.tsv and subclone.populations.tsv. Created if not existent.")
                 .required(true)
                 .takes_value(true)
             )
+            .arg(Arg::with_name("segment_stddev_divider")
+                .long("segment_stddev_divider")
+                .value_name("SEGMENT STDDEV DIVIDER")
+                .help("The stddev of each segment is divided by this, as in the C++ infer.")
+                .default_value("20")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("snp_coverage_min")
+                .long("snp_coverage_min")
+                .value_name("SNP COVERAGE MIN")
+                .help("Het SNPs with tumor depth below this are ignored.")
+                .default_value("2")
+                .takes_value(true)
+            )
         )
         .subcommand(SubCommand::with_name("recall_precision")
             .about("calculate recall and precision from truth result and predicted result")
@@ -287,9 +964,184 @@ fn main() {
                                 snp_file_path_tumor, snp_file_path_normal, sap_max, srp_max, min_coverage, max_coverage,
                                 abp_max_normal, abp_max_tumor, debug, output_file_path);
 
-        let ins = accurity::select_het_snp::SelectHetSNP::new(snp_file_path_tumor, snp_file_path_normal, output_file_path,
+        let mut ins = accurity::select_het_snp::SelectHetSNP::new(snp_file_path_tumor, snp_file_path_normal, output_file_path,
                                                               abp_max_tumor, abp_max_normal, srp_max, sap_max,
                                                               min_coverage, max_coverage, debug);
+        if let Some(known_sites_path) = matches.value_of("known_sites") {
+            let min_population_af: f32 = matches.value_of("min_population_af").unwrap_or("0").parse().unwrap();
+            ins.set_known_sites(known_sites_path, min_population_af);
+        }
+        ins.set_multi_allelic_het(matches.is_present("multi_allelic_het"));
+        if let Some(phase_block_output_path) = matches.value_of("phase_block_output") {
+            ins.set_phase_block_output(phase_block_output_path, matches.value_of("phased_normal_vcf"));
+        }
+        if let Some(region_str) = matches.value_of("region") {
+            ins.set_regions(&[accurity::region::Region::parse(region_str)]);
+        }
+        if let Some(regions_file_path) = matches.value_of("regions_file") {
+            ins.set_regions(&accurity::region::read_regions_file(std::path::Path::new(regions_file_path)));
+        }
+        ins.set_sex(accurity::sex::parse_sex_option(matches.value_of("sex").unwrap()));
+        ins.set_genome_build(accurity::sex::GenomeBuild::parse(matches.value_of("genome_build").unwrap()));
+        if let Some(roh_output_path) = matches.value_of("roh_output") {
+            let min_roh_len: u64 = matches.value_of("min_roh_len").unwrap().parse().unwrap();
+            ins.set_roh_output(roh_output_path, min_roh_len);
+        }
+        if let Some(vcf_output_path) = matches.value_of("vcf_output") {
+            ins.set_vcf_output(vcf_output_path);
+        }
+        if let Some(contamination_qc_path) = matches.value_of("contamination_qc") {
+            ins.set_contamination_qc(contamination_qc_path);
+        }
+        if let Some(pvalue_min) = matches.value_of("beta_binomial_pvalue_min") {
+            ins.set_beta_binomial_filter(pvalue_min.parse().unwrap());
+        }
+        if let Some(rho) = matches.value_of("beta_binomial_rho") {
+            ins.set_beta_binomial_rho(rho.parse().unwrap());
+        }
+        ins.run();
+    } else if let Some(matches) = matches.subcommand_matches("allele_count") {
+        let tumor_bam_path = matches.value_of("tumor_bam").unwrap();
+        let normal_bam_path = matches.value_of("normal_bam").unwrap();
+        let known_sites_path = matches.value_of("known_sites").unwrap();
+        let output_file_path = matches.value_of("output_file_path").unwrap();
+        let min_base_quality: u8 = matches.value_of("min_base_quality").unwrap().parse().unwrap();
+        let min_mapping_quality: u8 = matches.value_of("min_mapping_quality").unwrap().parse().unwrap();
+        let min_coverage: usize = matches.value_of("min_coverage").unwrap().parse().unwrap();
+        let max_coverage: usize = matches.value_of("max_coverage").unwrap().parse().unwrap();
+        let debug: i32 = matches.value_of("debug").unwrap_or("0").parse().unwrap();
+
+        let ins = accurity::allele_count::AlleleCount::new(tumor_bam_path, normal_bam_path, known_sites_path,
+                                                           output_file_path, min_base_quality, min_mapping_quality,
+                                                           min_coverage, max_coverage, debug);
+        ins.run();
+    } else if let Some(matches) = matches.subcommand_matches("concat") {
+        let input_file_path_vec: Vec<&str> = matches.values_of("input_file_path").unwrap().collect();
+        let output_file_path = matches.value_of("output_file_path").unwrap();
+        let mut ins = accurity::concat::ConcatHetSNP::new(&input_file_path_vec, output_file_path);
+        if let Some(contamination_qc_path) = matches.value_of("contamination_qc") {
+            ins.set_contamination_qc(contamination_qc_path);
+        }
+        if let Some(phase_block_output_path) = matches.value_of("phase_block_output") {
+            let phase_block_input_path_vec: Vec<&str> = matches.values_of("phase_block_input").unwrap().collect();
+            ins.set_phase_block(&phase_block_input_path_vec, phase_block_output_path);
+        }
+        if let Some(roh_output_path) = matches.value_of("roh_output") {
+            let roh_input_path_vec: Vec<&str> = matches.values_of("roh_input").unwrap().collect();
+            ins.set_roh(&roh_input_path_vec, roh_output_path);
+        }
+        if let Some(vcf_output_path) = matches.value_of("vcf_output") {
+            ins.set_vcf_output(vcf_output_path);
+        }
+        ins.run();
+    } else if let Some(matches) = matches.subcommand_matches("check_pair") {
+        let snp_file_path_tumor = matches.value_of("snp_file_path_tumor").unwrap();
+        let snp_file_path_normal = matches.value_of("snp_file_path_normal").unwrap();
+        let output_file_path = matches.value_of("output_file_path").unwrap();
+        let min_coverage: usize = matches.value_of("min_coverage").unwrap().parse().unwrap();
+        let max_hom_opposite_fraction: f32 = matches.value_of("max_hom_opposite_fraction").unwrap().parse().unwrap();
+
+        let ins = accurity::check_pair::CheckPair::new(snp_file_path_tumor, snp_file_path_normal, output_file_path,
+                                                       min_coverage, max_hom_opposite_fraction);
+        ins.run();
+    } else if let Some(matches) = matches.subcommand_matches("merge_features") {
+        let het_snp_file_path = matches.value_of("het_snp_file_path").unwrap();
+        let ratio_folder = matches.value_of("ratio_folder").unwrap();
+        let window_size: u64 = matches.value_of("window_size").unwrap().parse().unwrap();
+        let output_file_path = matches.value_of("output_file_path").unwrap();
+
+        let ins = accurity::merge_features::MergeFeatures::new(het_snp_file_path, ratio_folder, window_size,
+                                                               output_file_path);
+        ins.run();
+    } else if let Some(matches) = matches.subcommand_matches("segment") {
+        let output_file_path = matches.value_of("output_file_path").unwrap();
+        let min_segment_len: usize = matches.value_of("min_segment_len").unwrap().parse().unwrap();
+        let t_score_threshold: f64 = matches.value_of("t_score_threshold").unwrap().parse().unwrap();
+
+        if let Some(merged_features_file_path) = matches.value_of("merged_features_file_path") {
+            let ins = accurity::segment::JointSegment::new(merged_features_file_path, output_file_path,
+                                                           min_segment_len, t_score_threshold);
+            ins.run();
+        } else {
+            let ratio_folder = matches.value_of("ratio_folder").unwrap();
+            let window_size: u64 = matches.value_of("window_size").unwrap().parse().unwrap();
+
+            let ins = accurity::segment::Segment::new(ratio_folder, window_size, output_file_path,
+                                                      min_segment_len, t_score_threshold);
+            ins.run();
+        }
+    } else if let Some(matches) = matches.subcommand_matches("peaks") {
+        let segment_file_path = matches.value_of("segment_file_path").unwrap();
+        let output_dir = matches.value_of("output_dir").unwrap();
+        let segment_stddev_divider: f64 = matches.value_of("segment_stddev_divider").unwrap().parse().unwrap();
+        let bandwidth: f64 = matches.value_of("bandwidth").unwrap().parse().unwrap();
+        let min_peak_height: f64 = matches.value_of("min_peak_height").unwrap().parse().unwrap();
+
+        let ins = accurity::peaks::Peaks::new(segment_file_path, output_dir, segment_stddev_divider, bandwidth,
+                                              min_peak_height);
+        ins.run();
+    } else if let Some(matches) = matches.subcommand_matches("infer") {
+        let segment_file_path = matches.value_of("segment_file_path").unwrap();
+        let het_snp_file_path = matches.value_of("het_snp_file_path").unwrap();
+        let output_dir = matches.value_of("output_dir").unwrap();
+        let segment_stddev_divider: f64 = matches.value_of("segment_stddev_divider").unwrap().parse().unwrap();
+        let snp_coverage_min: usize = matches.value_of("snp_coverage_min").unwrap().parse().unwrap();
+        let debug: i32 = matches.value_of("debug").unwrap_or("0").parse().unwrap();
+
+        let purity_constraint = accurity::infer::Constraint::from_args(
+            "purity", matches.value_of("purity_fixed"), matches.value_of("purity_range"),
+            matches.value_of("purity_prior"));
+        let ploidy_constraint = accurity::infer::Constraint::from_args(
+            "ploidy", matches.value_of("ploidy_fixed"), matches.value_of("ploidy_range"),
+            matches.value_of("ploidy_prior"));
+
+        let mut ins = accurity::infer::Infer::new(segment_file_path, het_snp_file_path, output_dir,
+                                                  segment_stddev_divider, snp_coverage_min, debug);
+        ins.set_purity_constraint(purity_constraint);
+        ins.set_ploidy_constraint(ploidy_constraint);
+        ins.set_no_of_optima(matches.value_of("no_of_optima").unwrap().parse().unwrap());
+        if let Some(roh_file_path) = matches.value_of("roh_file") {
+            ins.set_roh_file(roh_file_path);
+        }
+        ins.run();
+    } else if let Some(matches) = matches.subcommand_matches("call") {
+        let segment_file_path = matches.value_of("segment_file_path").unwrap();
+        let het_snp_file_path = matches.value_of("het_snp_file_path").unwrap();
+        let output_file_path = matches.value_of("output_file_path").unwrap();
+        let segment_stddev_divider: f64 = matches.value_of("segment_stddev_divider").unwrap().parse().unwrap();
+        let snp_coverage_min: usize = matches.value_of("snp_coverage_min").unwrap().parse().unwrap();
+        let (purity, ploidy): (f64, f64) = match (matches.value_of("purity"), matches.value_of("ploidy")) {
+            (Some(purity), Some(ploidy)) => (purity.parse().unwrap(), ploidy.parse().unwrap()),
+            _ => {
+                let infer_out_file_path = matches.value_of("infer_out_file_path")
+                    .expect("Either --infer_out_file_path or --purity and --ploidy is required.");
+                accurity::infer::read_purity_ploidy(std::path::Path::new(infer_out_file_path))
+            },
+        };
+
+        let mut ins = accurity::call::Call::new(segment_file_path, het_snp_file_path, output_file_path, purity, ploidy,
+                                                segment_stddev_divider, snp_coverage_min);
+        if let Some(roh_file_path) = matches.value_of("roh_file") {
+            ins.set_roh_file(roh_file_path);
+        }
+        ins.run();
+    } else if let Some(matches) = matches.subcommand_matches("subclone") {
+        let segment_file_path = matches.value_of("segment_file_path").unwrap();
+        let het_snp_file_path = matches.value_of("het_snp_file_path").unwrap();
+        let output_dir = matches.value_of("output_dir").unwrap();
+        let segment_stddev_divider: f64 = matches.value_of("segment_stddev_divider").unwrap().parse().unwrap();
+        let snp_coverage_min: usize = matches.value_of("snp_coverage_min").unwrap().parse().unwrap();
+        let (purity, ploidy): (f64, f64) = match (matches.value_of("purity"), matches.value_of("ploidy")) {
+            (Some(purity), Some(ploidy)) => (purity.parse().unwrap(), ploidy.parse().unwrap()),
+            _ => {
+                let infer_out_file_path = matches.value_of("infer_out_file_path")
+                    .expect("Either --infer_out_file_path or --purity and --ploidy is required.");
+                accurity::infer::read_purity_ploidy(std::path::Path::new(infer_out_file_path))
+            },
+        };
+
+        let ins = accurity::subclone::Subclone::new(segment_file_path, het_snp_file_path, output_dir, purity, ploidy,
+                                                    segment_stddev_divider, snp_coverage_min);
         ins.run();
     }else if let Some(matches) = matches.subcommand_matches("recall_precision") {
         let truth_result_file_path = matches.value_of("truth_result_file_path").unwrap();
@@ -0,0 +1,195 @@
+use flate2;
+use rust_htslib::htslib;
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::os::raw::c_void;
+use std::path::{Path};
+
+use normalize;
+
+
+/// One het SNP from the select_het_snp output.
+struct HetSNPFeature {
+    pos: u64,
+    tumor_maf_normalized: f32,
+    tumor_depth: usize,
+    tumor_baf_corrected: f64,
+}
+
+/// Write to a BGZF file through htslib, so that it can be tabix-indexed.
+struct BgzfWriter {
+    fp: *mut htslib::BGZF,
+}
+
+impl BgzfWriter {
+    fn new(output_file_path: &Path) -> BgzfWriter {
+        let c_path = CString::new(output_file_path.to_str().unwrap()).unwrap();
+        let c_mode = CString::new("w").unwrap();
+        let fp = unsafe { htslib::bgzf_open(c_path.as_ptr(), c_mode.as_ptr()) };
+        if fp.is_null() {
+            panic!("Error in creating output file {:?}", output_file_path);
+        }
+        BgzfWriter { fp }
+    }
+
+    fn write_str(&mut self, content: &str) {
+        let no_of_bytes = unsafe {
+            htslib::bgzf_write(self.fp, content.as_ptr() as *const c_void, content.len())
+        };
+        if no_of_bytes < 0 || no_of_bytes as usize != content.len() {
+            panic!("Error writing to BGZF file.");
+        }
+    }
+
+    fn close(self) {
+        if unsafe { htslib::bgzf_close(self.fp) } != 0 {
+            panic!("Error closing BGZF file.");
+        }
+    }
+}
+
+/// Join the per-chromosome coverage ratio files of normalize with the het SNPs of select_het_snp into one
+/// bgzipped, tabix-indexed (BED-like) file. Each window row (type W) carries its coverage ratio,
+/// the number of het SNPs in it and their median mirrored BAF, max(b, 1-b) of tumor_baf_corrected.
+/// Each SNP row (type S) carries the coverage ratio of its window and its own BAF.
+pub struct MergeFeatures<'a> {
+    het_snp_file_path: &'a Path,
+    ratio_folder: &'a Path,
+    window_size: u64,
+    output_file_path: &'a Path,
+}
+
+impl<'a> MergeFeatures<'a> {
+    pub fn new(het_snp_file_path: &'a str,
+               ratio_folder: &'a str,
+               window_size: u64,
+               output_file_path: &'a str,
+    ) -> MergeFeatures<'a> {
+        MergeFeatures {
+            het_snp_file_path: Path::new(het_snp_file_path),
+            ratio_folder: Path::new(ratio_folder),
+            window_size,
+            output_file_path: Path::new(output_file_path),
+        }
+    }
+
+    /// Het SNPs grouped by chromosome, in the order of the input file.
+    fn read_het_snp_file(&self) -> Vec<(String, Vec<HetSNPFeature>)> {
+        println_stderr!("Reading het SNPs from {:?} ...", self.het_snp_file_path);
+        let input_f = File::open(self.het_snp_file_path)
+            .expect(&format!("Error in opening file {:?}", self.het_snp_file_path));
+        let reader = BufReader::new(flate2::read::MultiGzDecoder::new(input_f)
+            .expect("Error in opening gzipped file."));
+        let mut chr_snp_vec: Vec<(String, Vec<HetSNPFeature>)> = Vec::new();
+        let mut no_of_snps = 0usize;
+        for line in reader.lines() {
+            let line = line.expect("Error reading line.");
+            if line.starts_with('#') || line.starts_with("chr\t") {
+                continue;
+            }
+            let field_vec: Vec<&str> = line.split('\t').collect();
+            if field_vec.len() < 12 {
+                panic!("Expect 12 columns (select_het_snp output) in {:?}, got: {}", self.het_snp_file_path, line);
+            }
+            let het_snp = HetSNPFeature {
+                pos: field_vec[1].parse().unwrap(),
+                tumor_maf_normalized: field_vec[2].parse().unwrap(),
+                tumor_depth: field_vec[3].parse().unwrap(),
+                tumor_baf_corrected: field_vec[11].parse().unwrap(),
+            };
+            if chr_snp_vec.last().map_or(true, |&(ref chr, _)| chr != field_vec[0]) {
+                chr_snp_vec.push((field_vec[0].to_string(), Vec::new()));
+            }
+            chr_snp_vec.last_mut().unwrap().1.push(het_snp);
+            no_of_snps += 1;
+        }
+        println_stderr!("{} het SNPs on {} chromosomes.", no_of_snps, chr_snp_vec.len());
+        chr_snp_vec
+    }
+
+    pub fn run(&self) {
+        let mut chr_snp_vec = self.read_het_snp_file();
+        let ratio_chr_vec = normalize::find_ratio_chr_vec(self.ratio_folder, self.window_size);
+        println_stderr!("{} coverage ratio files of window size {} in {:?}.", ratio_chr_vec.len(),
+                        self.window_size, self.ratio_folder);
+        //chromosomes without het SNPs follow those with.
+        for chr in ratio_chr_vec.iter() {
+            if !chr_snp_vec.iter().any(|&(ref snp_chr, _)| snp_chr == chr) {
+                chr_snp_vec.push((chr.clone(), Vec::new()));
+            }
+        }
+
+        let mut bgzf_writer = BgzfWriter::new(self.output_file_path);
+        bgzf_writer.write_str(&format!("#het_snp_file={:?}, ratio_folder={:?}, window_size={}\n",
+                                       self.het_snp_file_path, self.ratio_folder, self.window_size));
+        bgzf_writer.write_str("#chr\tstart\tend\ttype\tcoverage_ratio\tno_of_snps\tmedian_mirrored_baf\t\
+            tumor_baf_corrected\ttumor_maf_normalized\ttumor_depth\n");
+        let mut no_of_windows = 0usize;
+        let mut no_of_snps_without_ratio = 0usize;
+        for &(ref chr, ref het_snp_vec) in chr_snp_vec.iter() {
+            let window_index2ratio: BTreeMap<u64, f32> = if ratio_chr_vec.contains(chr) {
+                normalize::read_ratio_file(&self.ratio_folder.join(format!("{}.ratio.w{}.csv.gz", chr, self.window_size)))
+                    .into_iter().map(|(start, coverage_ratio)| ((start - 1) / self.window_size, coverage_ratio)).collect()
+            } else {
+                println_stderr!("WARNING: no coverage ratio file for {}.", chr);
+                BTreeMap::new()
+            };
+            //mirrored BAFs of het SNPs in each window
+            let mut window_index2baf_vec: BTreeMap<u64, Vec<f64>> = BTreeMap::new();
+            for het_snp in het_snp_vec.iter() {
+                let mirrored_baf = het_snp.tumor_baf_corrected.max(1.0 - het_snp.tumor_baf_corrected);
+                window_index2baf_vec.entry(het_snp.pos / self.window_size).or_insert_with(Vec::new).push(mirrored_baf);
+            }
+            //(start, rank, line). A window row precedes SNP rows starting at the same position.
+            let mut row_vec: Vec<(u64, u8, String)> = Vec::new();
+            for (window_index, coverage_ratio) in window_index2ratio.iter() {
+                let start = window_index * self.window_size;
+                let (no_of_snps, median_baf) = match window_index2baf_vec.get_mut(window_index) {
+                    Some(baf_vec) => {
+                        baf_vec.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                        let mid = baf_vec.len() / 2;
+                        let median_baf = if baf_vec.len() % 2 == 0 {
+                            (baf_vec[mid - 1] + baf_vec[mid]) / 2.0
+                        } else {
+                            baf_vec[mid]
+                        };
+                        (baf_vec.len(), median_baf.to_string())
+                    },
+                    None => (0, "NA".to_string()),
+                };
+                row_vec.push((start, 0, format!("{}\t{}\t{}\tW\t{}\t{}\t{}\tNA\tNA\tNA\n", chr, start,
+                                                start + self.window_size, coverage_ratio, no_of_snps, median_baf)));
+            }
+            no_of_windows += window_index2ratio.len();
+            for het_snp in het_snp_vec.iter() {
+                let coverage_ratio = match window_index2ratio.get(&(het_snp.pos / self.window_size)) {
+                    Some(coverage_ratio) => coverage_ratio.to_string(),
+                    None => {
+                        no_of_snps_without_ratio += 1;
+                        "NA".to_string()
+                    },
+                };
+                row_vec.push((het_snp.pos, 1, format!("{}\t{}\t{}\tS\t{}\tNA\tNA\t{}\t{}\t{}\n", chr, het_snp.pos,
+                                                      het_snp.pos + 1, coverage_ratio, het_snp.tumor_baf_corrected,
+                                                      het_snp.tumor_maf_normalized, het_snp.tumor_depth)));
+            }
+            row_vec.sort_by_key(|row| (row.0, row.1));
+            for row in row_vec.iter() {
+                bgzf_writer.write_str(&row.2);
+            }
+        }
+        bgzf_writer.close();
+        println_stderr!("{} windows and {} het SNPs ({} without a coverage ratio) written to {:?}.", no_of_windows,
+                        chr_snp_vec.iter().fold(0, |sum, &(_, ref het_snp_vec)| sum + het_snp_vec.len()),
+                        no_of_snps_without_ratio, self.output_file_path);
+
+        let c_path = CString::new(self.output_file_path.to_str().unwrap()).unwrap();
+        let return_code = unsafe { htslib::tbx_index_build(c_path.as_ptr(), 0, &htslib::tbx_conf_bed) };
+        if return_code != 0 {
+            println_stderr!("WARNING: failed to build tabix index for {:?}.", self.output_file_path);
+        }
+    }
+}
@@ -6,8 +6,11 @@ use rust_htslib::bam::Read as bamRead;
 use std::cmp;
 use std::collections::HashMap;
 use std::io::prelude::*;
+use std::io::BufReader;
+use std::fs;
 use std::fs::File;
 use std::path::{Path};
+use std::u32;
 
 
 //from lib.rs
@@ -16,6 +19,43 @@ use calc_median_i32;
 
 
 
+/// Chromosomes with an output file of Normalize of this window size, "<chr>.ratio.w<window_size>.csv.gz".
+/// Sorted as chr1, chr2, ..., chr22, then others alphabetically.
+pub fn find_ratio_chr_vec(ratio_folder: &Path, window_size: u64) -> Vec<String> {
+    let suffix = format!(".ratio.w{}.csv.gz", window_size);
+    let mut chr_vec: Vec<String> = Vec::new();
+    for entry in fs::read_dir(ratio_folder)
+        .expect(&format!("Error in reading folder {:?}", ratio_folder)) {
+        let file_name = entry.expect("Error reading folder entry.").file_name();
+        let file_name = file_name.to_string_lossy();
+        if file_name.ends_with(&suffix) {
+            chr_vec.push(file_name[..file_name.len() - suffix.len()].to_string());
+        }
+    }
+    chr_vec.sort_by_key(|chr| (chr.trim_left_matches("chr").parse::<u32>().unwrap_or(u32::MAX), chr.clone()));
+    chr_vec
+}
+
+/// Read an output file of Normalize into (1-based window start, coverage ratio).
+pub fn read_ratio_file(ratio_file_path: &Path) -> Vec<(u64, f32)> {
+    let input_f = File::open(ratio_file_path)
+        .expect(&format!("Error in opening file {:?}", ratio_file_path));
+    let reader = BufReader::new(flate2::read::MultiGzDecoder::new(input_f)
+        .expect("Error in opening gzipped file."));
+    let mut start_ratio_vec: Vec<(u64, f32)> = Vec::new();
+    for line in reader.lines() {
+        let line = line.expect("Error reading line.");
+        if line.starts_with('#') || line.starts_with("start,") {
+            continue;
+        }
+        let mut field_iter = line.split(',');
+        let start: u64 = field_iter.next().unwrap().parse().unwrap();
+        let coverage_ratio: f32 = field_iter.next().unwrap().parse().unwrap();
+        start_ratio_vec.push((start, coverage_ratio));
+    }
+    start_ratio_vec
+}
+
 struct OneChrData<'a>{
     chr: &'a str,
     chr_len: usize,
@@ -0,0 +1,179 @@
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path};
+
+use autocor::{kernel_smoothing, RESOLUTION};
+use infer;
+use infer::InferSegment;
+
+
+//a peak bound stops at this fraction of the peak height, i.e. the normal density two stddevs away. DEV2 of read_para.h.
+const DEV2: f64 = 0.135;
+//a peak is at most this wide on either side, in 1/RESOLUTION. kPeakHalfWidthMax of read_para.h.
+const PEAK_HALF_WIDTH_MAX: usize = 200;
+//a peak must be the highest within this many bins on either side.
+const PEAK_SCOPE: usize = 5;
+//default minimum peak height. kPeakHeightMin of read_para.h.
+pub const PEAK_HEIGHT_MIN: f64 = 1e2;
+
+/// One copy number peak of the segment ratio density.
+#[derive(Clone, Debug)]
+pub struct Peak {
+    //window-weighted mean ratio of segments within the bounds, as in refine_peak_center() of infer.cpp.
+    pub center: f64,
+    pub lower_bound: f64,
+    pub upper_bound: f64,
+    //density at the local maximum
+    pub height: f64,
+    pub no_of_segments: usize,
+    pub no_of_windows: usize,
+}
+
+/// Kernel density of segment coverage ratios and its peaks, following find_peaks() of infer.cpp.
+/// Each segment contributes no_of_windows * N(ratio, bandwidth) over [0, MAX_RATIO] at 1/RESOLUTION.
+/// bandwidth 0 uses the stddev of each segment, as infer.cpp.
+pub struct PeakFinder {
+    bandwidth: f64,
+    min_peak_height: f64,
+}
+
+impl PeakFinder {
+    pub fn new(bandwidth: f64, min_peak_height: f64) -> PeakFinder {
+        if bandwidth < 0.0 {
+            panic!("bandwidth {} is negative.", bandwidth);
+        }
+        PeakFinder {
+            bandwidth,
+            min_peak_height,
+        }
+    }
+
+    pub fn density(&self, segment_vec: &[InferSegment]) -> Vec<f64> {
+        let mut ratio_pdf_vec = vec![0f64; (infer::MAX_RATIO * RESOLUTION as f64) as usize + 1];
+        for segment in segment_vec.iter() {
+            let stddev = if self.bandwidth > 0.0 { self.bandwidth } else { segment.stddev };
+            kernel_smoothing(segment.ratio * RESOLUTION as f64, stddev * RESOLUTION as f64,
+                             segment.no_of_windows as f64, &mut ratio_pdf_vec);
+        }
+        ratio_pdf_vec
+    }
+
+    /// Peaks of the density no lower than min_peak_height, sorted by ratio. A peak extends on either side
+    /// until the density drops to DEV2 of its height, turns up again, or reaches PEAK_HALF_WIDTH_MAX.
+    pub fn find_peaks(&self, segment_vec: &[InferSegment]) -> Vec<Peak> {
+        let ratio_pdf_vec = self.density(segment_vec);
+        let last_index = ratio_pdf_vec.len() - 1;
+        let resolution = RESOLUTION as f64;
+        let mut peak_vec: Vec<Peak> = Vec::new();
+        for i in 0..ratio_pdf_vec.len() {
+            let height = ratio_pdf_vec[i];
+            if height < self.min_peak_height || height <= 0.0 {
+                continue;
+            }
+            let scope_start = if i >= PEAK_SCOPE { i - PEAK_SCOPE } else { 0 };
+            let scope_end = (i + PEAK_SCOPE).min(last_index);
+            if !(ratio_pdf_vec[scope_start..i].iter().all(|density| *density < height) &&
+                ratio_pdf_vec[(i + 1)..(scope_end + 1)].iter().all(|density| *density <= height)) {
+                continue;
+            }
+            let threshold = DEV2 * height;
+            let mut lower_index = i;
+            while lower_index > 0 && i - lower_index < PEAK_HALF_WIDTH_MAX &&
+                ratio_pdf_vec[lower_index - 1] > threshold && ratio_pdf_vec[lower_index - 1] <= ratio_pdf_vec[lower_index] {
+                lower_index -= 1;
+            }
+            let mut upper_index = i;
+            while upper_index < last_index && upper_index - i < PEAK_HALF_WIDTH_MAX &&
+                ratio_pdf_vec[upper_index + 1] > threshold && ratio_pdf_vec[upper_index + 1] <= ratio_pdf_vec[upper_index] {
+                upper_index += 1;
+            }
+            let lower_bound = lower_index as f64 / resolution;
+            let upper_bound = upper_index as f64 / resolution;
+            let mut no_of_segments = 0usize;
+            let mut no_of_windows = 0usize;
+            let mut ratio_sum = 0f64;
+            for segment in segment_vec.iter() {
+                if segment.ratio >= lower_bound && segment.ratio <= upper_bound {
+                    no_of_segments += 1;
+                    no_of_windows += segment.no_of_windows;
+                    ratio_sum += segment.ratio * segment.no_of_windows as f64;
+                }
+            }
+            peak_vec.push(Peak {
+                center: if no_of_windows > 0 { ratio_sum / no_of_windows as f64 } else { i as f64 / resolution },
+                lower_bound,
+                upper_bound,
+                height,
+                no_of_segments,
+                no_of_windows,
+            });
+        }
+        peak_vec
+    }
+}
+
+/// Write peak bounds in the format of peak_bounds.tsv of the C++ infer.
+pub fn output_peak_bounds(peak_vec: &[Peak], output_file_path: &Path) {
+    println_stderr!("Outputting peak bounds to {:?} ...", output_file_path);
+    let mut output_f = File::create(output_file_path)
+        .expect(&format!("Error in creating output file {:?}", output_file_path));
+    output_f.write_fmt(format_args!("lowerBound\tupperBound\n")).unwrap();
+    for peak in peak_vec.iter() {
+        output_f.write_fmt(format_args!("{}\t{}\n", peak.lower_bound, peak.upper_bound)).unwrap();
+    }
+}
+
+/// Find copy number peaks of a segment file. Writes peak_bounds.tsv, peaks.tsv (all peak attributes)
+/// and ratio_density.tsv (the smoothed density) into output_dir.
+pub struct Peaks<'a> {
+    segment_file_path: &'a Path,
+    output_dir: &'a Path,
+    segment_stddev_divider: f64,
+    peak_finder: PeakFinder,
+}
+
+impl<'a> Peaks<'a> {
+    pub fn new(segment_file_path: &'a str,
+               output_dir: &'a str,
+               segment_stddev_divider: f64,
+               bandwidth: f64,
+               min_peak_height: f64,
+    ) -> Peaks<'a> {
+        Peaks {
+            segment_file_path: Path::new(segment_file_path),
+            output_dir: Path::new(output_dir),
+            segment_stddev_divider,
+            peak_finder: PeakFinder::new(bandwidth, min_peak_height),
+        }
+    }
+
+    pub fn run(&self) {
+        let (segment_vec, _) = infer::read_segment_file(self.segment_file_path, self.segment_stddev_divider,
+                                                          infer::MAX_RATIO);
+        fs::create_dir_all(self.output_dir)
+            .expect(&format!("Error in creating output folder {:?}", self.output_dir));
+        let peak_vec = self.peak_finder.find_peaks(&segment_vec);
+        println_stderr!("{} peaks found.", peak_vec.len());
+        output_peak_bounds(&peak_vec, &self.output_dir.join("peak_bounds.tsv"));
+
+        let output_file_path = self.output_dir.join("peaks.tsv");
+        let mut output_f = File::create(&output_file_path)
+            .expect(&format!("Error in creating output file {:?}", &output_file_path));
+        output_f.write_fmt(format_args!("center\tlower_bound\tupper_bound\theight\tno_of_segments\tno_of_windows\n"))
+            .unwrap();
+        for peak in peak_vec.iter() {
+            output_f.write_fmt(format_args!("{}\t{}\t{}\t{}\t{}\t{}\n", peak.center, peak.lower_bound,
+                                            peak.upper_bound, peak.height, peak.no_of_segments,
+                                            peak.no_of_windows)).unwrap();
+        }
+
+        let output_file_path = self.output_dir.join("ratio_density.tsv");
+        let mut output_f = File::create(&output_file_path)
+            .expect(&format!("Error in creating output file {:?}", &output_file_path));
+        output_f.write_fmt(format_args!("read_count_ratio\tdensity\n")).unwrap();
+        for (i, density) in self.peak_finder.density(&segment_vec).iter().enumerate() {
+            output_f.write_fmt(format_args!("{}\t{}\n", i as f64 / RESOLUTION as f64, density)).unwrap();
+        }
+    }
+}
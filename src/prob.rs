@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+
+const LANCZOS_G: f64 = 7.0;
+const LANCZOS_COEFFICIENTS: [f64; 9] = [
+    0.99999999999980993,
+    676.5203681218851,
+    -1259.1392167224028,
+    771.32342877765313,
+    -176.61502916214059,
+    12.507343278686905,
+    -0.13857109526572012,
+    9.9843695780195716e-6,
+    1.5056327351493116e-7,
+];
+
+/// ln(Γ(x)) by the Lanczos approximation. Accurate to ~1e-13, unlike the Stirling approximation in prob.cpp.
+pub fn ln_gamma(x: f64) -> f64 {
+    if x < 0.5 {
+        //reflection formula
+        return (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut a = LANCZOS_COEFFICIENTS[0];
+    let t = x + LANCZOS_G + 0.5;
+    for i in 1..LANCZOS_COEFFICIENTS.len() {
+        a += LANCZOS_COEFFICIENTS[i] / (x + i as f64);
+    }
+    0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + a.ln()
+}
+
+pub fn ln_beta(a: f64, b: f64) -> f64 {
+    ln_gamma(a) + ln_gamma(b) - ln_gamma(a + b)
+}
+
+pub fn ln_choose(n: usize, k: usize) -> f64 {
+    ln_gamma(n as f64 + 1.0) - ln_gamma(k as f64 + 1.0) - ln_gamma((n - k) as f64 + 1.0)
+}
+
+/// ln of the normal density.
+pub fn ln_normal_pdf(x: f64, mean: f64, stddev: f64) -> f64 {
+    let z = (x - mean) / stddev;
+    -0.5 * z * z - stddev.ln() - 0.5 * (2.0 * PI).ln()
+}
+
+/// ln P(K=k) of a beta-binomial with n trials, mean p and overdispersion rho (intra-class correlation).
+/// rho=0 is the plain binomial.
+pub fn beta_binomial_ln_pmf(k: usize, n: usize, p: f64, rho: f64) -> f64 {
+    if rho <= 0.0 {
+        return ln_choose(n, k) + k as f64 * p.ln() + (n - k) as f64 * (1.0 - p).ln();
+    }
+    let alpha = p * (1.0 - rho) / rho;
+    let beta = (1.0 - p) * (1.0 - rho) / rho;
+    ln_choose(n, k) + ln_beta(k as f64 + alpha, (n - k) as f64 + beta) - ln_beta(alpha, beta)
+}
+
+/// Two-sided p-value of observing k out of n under a beta-binomial with p=0.5, i.e. a germline het.
+/// The distribution is symmetric, so it is twice the tail beyond the minor count.
+pub fn beta_binomial_het_pvalue(k: usize, n: usize, rho: f64) -> f64 {
+    let minor_k = if k < n - k { k } else { n - k };
+    let mut tail = 0.0;
+    for i in 0..(minor_k + 1) {
+        tail += beta_binomial_ln_pmf(i, n, 0.5, rho).exp();
+    }
+    (2.0 * tail).min(1.0)
+}
+
+/// Maximum-likelihood overdispersion of a beta-binomial with p=0.5, given (k, n) counts of germline hets.
+/// rho is searched within [0, 0.5] by golden-section search on the summed log-likelihood.
+pub fn fit_beta_binomial_het_rho(k_n_vec: &[(usize, usize)]) -> f64 {
+    //many hets share the same counts. Evaluate each distinct (minor k, n) once.
+    let mut k_n2count: HashMap<(usize, usize), usize> = HashMap::new();
+    for &(k, n) in k_n_vec.iter() {
+        let minor_k = if k < n - k { k } else { n - k };
+        *k_n2count.entry((minor_k, n)).or_insert(0) += 1;
+    }
+    let ln_likelihood = |rho: f64| -> f64 {
+        k_n2count.iter().map(|(&(k, n), &count)| count as f64 * beta_binomial_ln_pmf(k, n, 0.5, rho)).sum()
+    };
+    let golden_ratio = (5f64.sqrt() - 1.0) / 2.0;
+    let mut lower = 0.0;
+    let mut upper = 0.5;
+    let mut x1 = upper - golden_ratio * (upper - lower);
+    let mut x2 = lower + golden_ratio * (upper - lower);
+    let mut f1 = ln_likelihood(x1);
+    let mut f2 = ln_likelihood(x2);
+    while upper - lower > 1e-6 {
+        if f1 > f2 {
+            upper = x2;
+            x2 = x1;
+            f2 = f1;
+            x1 = upper - golden_ratio * (upper - lower);
+            f1 = ln_likelihood(x1);
+        } else {
+            lower = x1;
+            x1 = x2;
+            f1 = f2;
+            x2 = lower + golden_ratio * (upper - lower);
+            f2 = ln_likelihood(x2);
+        }
+    }
+    let rho = (lower + upper) / 2.0;
+    //the binomial end is outside the open search interval
+    if ln_likelihood(0.0) >= ln_likelihood(rho) { 0.0 } else { rho }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() < tolerance, "{} != {}", actual, expected);
+    }
+
+    /// A linear congruential generator of uniforms in (0, 1), to simulate counts without a rand dependency.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next_uniform(&mut self) -> f64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((self.0 >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+        }
+
+        /// Gamma(shape, 1) of an integer shape, as a sum of exponentials.
+        fn next_gamma(&mut self, shape: usize) -> f64 {
+            (0..shape).map(|_| -self.next_uniform().ln()).sum()
+        }
+
+        fn next_binomial(&mut self, n: usize, p: f64) -> usize {
+            (0..n).filter(|_| self.next_uniform() < p).count()
+        }
+    }
+
+    #[test]
+    fn ln_gamma_at_integers_is_ln_factorial() {
+        let mut factorial = 1f64;
+        for n in 1..25 {
+            assert_close(ln_gamma(n as f64), factorial.ln(), 1e-10);
+            factorial *= n as f64;
+        }
+    }
+
+    #[test]
+    fn ln_gamma_at_half_integers() {
+        assert_close(ln_gamma(0.5), PI.sqrt().ln(), 1e-12);
+        assert_close(ln_gamma(1.5), (PI.sqrt() / 2.0).ln(), 1e-12);
+        //reflection branch: Γ(0.25)Γ(0.75) = π√2
+        assert_close(ln_gamma(0.25) + ln_gamma(0.75), (PI * 2f64.sqrt()).ln(), 1e-12);
+    }
+
+    #[test]
+    fn beta_binomial_pmf_sums_to_one() {
+        for rho in [0.0, 0.01, 0.1, 0.4].iter() {
+            let sum: f64 = (0..41).map(|k| beta_binomial_ln_pmf(k, 40, 0.5, *rho).exp()).sum();
+            assert_close(sum, 1.0, 1e-10);
+        }
+    }
+
+    #[test]
+    fn het_pvalue_converges_to_binomial() {
+        //P(K<=5) of Binomial(20, 0.5) = (1+20+190+1140+4845+15504)/2^20
+        let binomial_pvalue = 2.0 * 21700.0 / 1048576.0;
+        assert_close(beta_binomial_het_pvalue(5, 20, 0.0), binomial_pvalue, 1e-12);
+        assert_close(beta_binomial_het_pvalue(15, 20, 0.0), binomial_pvalue, 1e-12);
+        assert_close(beta_binomial_het_pvalue(5, 20, 1e-9), binomial_pvalue, 1e-6);
+        assert_close(beta_binomial_het_pvalue(10, 20, 0.0), 1.0, 1e-12);
+        //overdispersion widens the distribution
+        assert!(beta_binomial_het_pvalue(5, 20, 0.05) > binomial_pvalue);
+    }
+
+    #[test]
+    fn fit_rho_of_binomial_counts_is_near_zero() {
+        let mut lcg = Lcg(7);
+        let k_n_vec: Vec<(usize, usize)> = (0..5000).map(|_| (lcg.next_binomial(50, 0.5), 50)).collect();
+        assert!(fit_beta_binomial_het_rho(&k_n_vec) < 0.005);
+    }
+
+    #[test]
+    fn fit_rho_recovers_simulated_overdispersion() {
+        //alpha = beta = 0.5 * (1 - rho) / rho = 10 at rho = 1/21
+        let rho = 1.0 / 21.0;
+        let mut lcg = Lcg(11);
+        let k_n_vec: Vec<(usize, usize)> = (0..5000).map(|_| {
+            let x = lcg.next_gamma(10);
+            let y = lcg.next_gamma(10);
+            (lcg.next_binomial(50, x / (x + y)), 50)
+        }).collect();
+        assert_close(fit_beta_binomial_het_rho(&k_n_vec), rho, 0.01);
+    }
+}
@@ -0,0 +1,97 @@
+use std::cmp;
+use std::collections::BTreeMap;
+
+
+const NO_OF_DEPTH_BINS: usize = 10;
+
+/// depth => (number of hets, total ref count) of (ref count, depth) of normal hets.
+pub fn calc_depth_histogram(ref_obs_depth_vec: &[(usize, usize)]) -> BTreeMap<usize, (usize, usize)> {
+    let mut depth2count: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+    for &(ref_obs, depth) in ref_obs_depth_vec.iter() {
+        let count = depth2count.entry(depth).or_insert((0, 0));
+        count.0 += 1;
+        count.1 += ref_obs;
+    }
+    depth2count
+}
+
+/// Mean ref-allele fraction at germline hets of the normal, stratified by depth.
+/// Without mapping bias, it is 0.5 in every bin.
+pub struct RefBias {
+    //inclusive upper depth of each bin. The last bin also takes all depths above it.
+    pub bin_max_depth_vec: Vec<usize>,
+    pub ref_fraction_vec: Vec<f64>,
+    pub no_of_snps_vec: Vec<usize>,
+}
+
+impl RefBias {
+    /// Estimate from (ref count, depth) of normal hets. Bins hold roughly equal numbers of hets.
+    pub fn estimate(ref_obs_depth_vec: &[(usize, usize)]) -> RefBias {
+        RefBias::estimate_from_depth_histogram(&calc_depth_histogram(ref_obs_depth_vec))
+    }
+
+    /// Estimate from depth => (number of hets, total ref count), i.e. summed over shards.
+    pub fn estimate_from_depth_histogram(depth2count: &BTreeMap<usize, (usize, usize)>) -> RefBias {
+        //(depth, number of hets, total ref count), by depth
+        let depth_count_vec: Vec<(usize, usize, usize)> = depth2count.iter()
+            .filter(|&(depth, _)| *depth > 0)
+            .map(|(depth, &(no_of_snps, total_ref_obs))| (*depth, no_of_snps, total_ref_obs)).collect();
+        let mut ref_bias = RefBias {
+            bin_max_depth_vec: Vec::new(),
+            ref_fraction_vec: Vec::new(),
+            no_of_snps_vec: Vec::new(),
+        };
+        let total_no_of_snps: usize = depth_count_vec.iter().map(|&(_, no_of_snps, _)| no_of_snps).sum();
+        if total_no_of_snps == 0 {
+            return ref_bias;
+        }
+        let bin_size = cmp::max(total_no_of_snps / NO_OF_DEPTH_BINS, 1);
+        let mut no_of_snps_left = total_no_of_snps;
+        let mut bin_start = 0usize;
+        while bin_start < depth_count_vec.len() {
+            //all hets of one depth go into one bin
+            let mut bin_end = bin_start;
+            let mut no_of_snps_in_bin = 0usize;
+            while bin_end < depth_count_vec.len() && no_of_snps_in_bin < bin_size {
+                no_of_snps_in_bin += depth_count_vec[bin_end].1;
+                bin_end += 1;
+            }
+            //fold a small remainder into the last bin
+            if no_of_snps_left - no_of_snps_in_bin < bin_size / 2 {
+                bin_end = depth_count_vec.len();
+                no_of_snps_in_bin = no_of_snps_left;
+            }
+            let bin = &depth_count_vec[bin_start..bin_end];
+            let total_ref_obs: usize = bin.iter().map(|&(_, _, total_ref_obs)| total_ref_obs).sum();
+            let total_depth: usize = bin.iter().map(|&(depth, no_of_snps, _)| depth * no_of_snps).sum();
+            ref_bias.bin_max_depth_vec.push(depth_count_vec[bin_end - 1].0);
+            ref_bias.ref_fraction_vec.push(total_ref_obs as f64 / total_depth as f64);
+            ref_bias.no_of_snps_vec.push(no_of_snps_in_bin);
+            no_of_snps_left -= no_of_snps_in_bin;
+            bin_start = bin_end;
+        }
+        ref_bias
+    }
+
+    /// The normal ref fraction for a site of this depth. 0.5 (no bias) if nothing was estimated.
+    pub fn ref_fraction(&self, depth: usize) -> f64 {
+        for (i, bin_max_depth) in self.bin_max_depth_vec.iter().enumerate() {
+            if depth <= *bin_max_depth {
+                return self.ref_fraction_vec[i];
+            }
+        }
+        match self.ref_fraction_vec.last() {
+            Some(ref_fraction) => *ref_fraction,
+            None => 0.5,
+        }
+    }
+
+    /// Undo the bias on an observed ref fraction.
+    /// With bias b, a true fraction f is observed as f*b/(f*b + (1-f)*(1-b)). This is its inverse.
+    pub fn correct_ref_fraction(&self, observed_ref_fraction: f64, depth: usize) -> f64 {
+        let b = self.ref_fraction(depth);
+        let numerator = observed_ref_fraction * (1.0 - b);
+        let denominator = numerator + (1.0 - observed_ref_fraction) * b;
+        if denominator > 0.0 { numerator / denominator } else { observed_ref_fraction }
+    }
+}
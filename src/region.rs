@@ -0,0 +1,88 @@
+use std::cmp;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::{Path};
+use std::i32;
+
+
+//end of an open-ended region. htslib takes region ends as i32, so u32::MAX would wrap to -1.
+pub const OPEN_END: u32 = i32::MAX as u32;
+
+/// A genomic interval. start is 0-based inclusive, end is exclusive, as in BED.
+#[derive(Clone, Debug)]
+pub struct Region {
+    pub chr: String,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Region {
+    /// Parse samtools-style "chr", "chr:start" or "chr:start-end" (1-based, inclusive).
+    /// A region without an end extends to OPEN_END.
+    pub fn parse(region_str: &str) -> Region {
+        let mut chr_range_iter = region_str.splitn(2, ':');
+        let chr = chr_range_iter.next().unwrap().to_string();
+        let (start, end) = match chr_range_iter.next() {
+            Some(range_str) => {
+                let range_str = range_str.replace(",", "");
+                let mut start_end_iter = range_str.splitn(2, '-');
+                let start: u32 = start_end_iter.next().unwrap().parse()
+                    .expect(&format!("Error parsing start of region {}", region_str));
+                let end: u32 = match start_end_iter.next() {
+                    Some(end_str) => end_str.parse().expect(&format!("Error parsing end of region {}", region_str)),
+                    None => OPEN_END,
+                };
+                (cmp::max(start, 1) - 1, cmp::min(end, OPEN_END))
+            },
+            None => (0, OPEN_END),
+        };
+        Region { chr, start, end }
+    }
+}
+
+/// Read regions from a BED file (chr, 0-based start, end). Lines starting with #, track or browser are skipped.
+pub fn read_regions_file(regions_file_path: &Path) -> Vec<Region> {
+    let input_f = File::open(&regions_file_path)
+        .expect(&format!("Error in opening regions file {:?}", &regions_file_path));
+    let mut region_vec: Vec<Region> = Vec::new();
+    for line in BufReader::new(input_f).lines() {
+        let line = line.expect("Error reading regions file.");
+        if line.is_empty() || line.starts_with('#') || line.starts_with("track") || line.starts_with("browser") {
+            continue;
+        }
+        let mut field_iter = line.split('\t');
+        let chr = field_iter.next().unwrap().to_string();
+        let start: u32 = field_iter.next().unwrap().parse().expect(&format!("Error parsing start in {}", line));
+        let end: u32 = field_iter.next().unwrap().parse().expect(&format!("Error parsing end in {}", line));
+        region_vec.push(Region { chr, start, end: cmp::min(end, OPEN_END) });
+    }
+    region_vec
+}
+
+/// Merge overlapping or adjacent regions so that no record is read twice.
+/// Chromosomes keep the order of their first appearance.
+pub fn merge_regions(region_vec: &[Region]) -> Vec<Region> {
+    let mut chr_vec: Vec<&str> = Vec::new();
+    for region in region_vec.iter() {
+        if !chr_vec.contains(&region.chr.as_str()) {
+            chr_vec.push(&region.chr);
+        }
+    }
+    let mut merged_region_vec: Vec<Region> = Vec::new();
+    for chr in chr_vec {
+        let mut chr_region_vec: Vec<&Region> = region_vec.iter().filter(|region| region.chr == chr).collect();
+        chr_region_vec.sort_by_key(|region| region.start);
+        let mut current_region = chr_region_vec[0].clone();
+        for region in chr_region_vec[1..].iter() {
+            if region.start <= current_region.end {
+                current_region.end = cmp::max(current_region.end, region.end);
+            } else {
+                merged_region_vec.push(current_region);
+                current_region = (*region).clone();
+            }
+        }
+        merged_region_vec.push(current_region);
+    }
+    merged_region_vec
+}
@@ -0,0 +1,182 @@
+use flate2;
+use flate2::Compression;
+use std::cmp;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::{Path};
+
+
+//a run has to contain at least this many homozygous SNPs
+const MIN_NO_OF_HOM_SNPS: usize = 50;
+
+/// A run of homozygosity. start is 0-based, end exclusive, as in BED.
+pub struct RohSegment {
+    pub chr: String,
+    pub start: u64,
+    pub end: u64,
+    pub no_of_hom_snps: usize,
+    //isolated hets inside the run, taken as genotyping errors
+    pub no_of_hets: usize,
+}
+
+/// Fraction of [start, end) on chr covered by runs of homozygosity, which do not overlap one another.
+pub fn calc_roh_fraction(roh_segment_vec: &[RohSegment], chr: &str, start: u64, end: u64) -> f64 {
+    if end <= start {
+        return 0.0;
+    }
+    let overlap_len: u64 = roh_segment_vec.iter().filter(|roh_segment| roh_segment.chr == chr)
+        .map(|roh_segment| cmp::min(end, roh_segment.end).saturating_sub(cmp::max(start, roh_segment.start))).sum();
+    overlap_len as f64 / (end - start) as f64
+}
+
+/// Find runs of homozygosity from genotyped SNPs of one sample, grouped by chromosome and sorted by position.
+/// Each site is (position, is_het). Runs of homozygous SNPs are broken by hets,
+/// except a single het flanked by homozygous runs of at least MIN_NO_OF_HOM_SNPS/2 SNPs each.
+/// Runs shorter than min_roh_len bp or with fewer than MIN_NO_OF_HOM_SNPS homozygous SNPs are dropped.
+pub fn find_roh(chr_site_vec: &[(String, Vec<(u64, bool)>)], min_roh_len: u64) -> Vec<RohSegment> {
+    let mut roh_segment_vec: Vec<RohSegment> = Vec::new();
+    for &(ref chr, ref site_vec) in chr_site_vec.iter() {
+        //runs of consecutive homozygous SNPs: (first index, last index)
+        let mut hom_run_vec: Vec<(usize, usize)> = Vec::new();
+        let mut run_start: Option<usize> = None;
+        for (i, &(_, is_het)) in site_vec.iter().enumerate() {
+            if is_het {
+                if let Some(start_index) = run_start {
+                    hom_run_vec.push((start_index, i - 1));
+                    run_start = None;
+                }
+            } else if run_start.is_none() {
+                run_start = Some(i);
+            }
+        }
+        if let Some(start_index) = run_start {
+            hom_run_vec.push((start_index, site_vec.len() - 1));
+        }
+        //merge runs separated by a single het
+        let mut merged_run_vec: Vec<(usize, usize, usize)> = Vec::new();
+        for &(start_index, end_index) in hom_run_vec.iter() {
+            let run_len = end_index - start_index + 1;
+            if let Some(last_run) = merged_run_vec.last_mut() {
+                let last_run_len = last_run.1 - last_run.0 + 1 - last_run.2;
+                if start_index == last_run.1 + 2 && last_run_len >= MIN_NO_OF_HOM_SNPS / 2 &&
+                    run_len >= MIN_NO_OF_HOM_SNPS / 2 {
+                    last_run.1 = end_index;
+                    last_run.2 += 1;
+                    continue;
+                }
+            }
+            merged_run_vec.push((start_index, end_index, 0));
+        }
+        for &(start_index, end_index, no_of_hets) in merged_run_vec.iter() {
+            let no_of_hom_snps = end_index - start_index + 1 - no_of_hets;
+            let start = site_vec[start_index].0;
+            let end = site_vec[end_index].0 + 1;
+            if no_of_hom_snps >= MIN_NO_OF_HOM_SNPS && end - start >= min_roh_len {
+                roh_segment_vec.push(RohSegment {
+                    chr: chr.clone(),
+                    start,
+                    end,
+                    no_of_hom_snps,
+                    no_of_hets,
+                });
+            }
+        }
+    }
+    roh_segment_vec
+}
+
+pub fn output_roh_bed(roh_segment_vec: &[RohSegment], output_file_path: &Path, min_roh_len: u64) {
+    println_stderr!("Outputting {} runs of homozygosity to {:?} ...", roh_segment_vec.len(), output_file_path);
+    let mut output_f = File::create(&output_file_path)
+        .expect(&format!("Error in creating output file {:?}", &output_file_path));
+    output_f.write_fmt(format_args!("#min_roh_len={}, min_no_of_hom_snps={}\n",
+                                    min_roh_len, MIN_NO_OF_HOM_SNPS)).unwrap();
+    output_f.write_fmt(format_args!("#chr\tstart\tend\tno_of_hom_snps\tno_of_hets\n")).unwrap();
+    for roh_segment in roh_segment_vec.iter() {
+        output_f.write_fmt(format_args!("{}\t{}\t{}\t{}\t{}\n", roh_segment.chr, roh_segment.start,
+                                        roh_segment.end, roh_segment.no_of_hom_snps, roh_segment.no_of_hets)).unwrap();
+    }
+    println_stderr!("Output done.");
+}
+
+/// Read runs of homozygosity from a BED of output_roh_bed().
+pub fn read_roh_bed(roh_file_path: &Path) -> Vec<RohSegment> {
+    println_stderr!("Reading runs of homozygosity from {:?} ...", roh_file_path);
+    let input_f = File::open(&roh_file_path)
+        .expect(&format!("Error in opening file {:?}", &roh_file_path));
+    let mut roh_segment_vec: Vec<RohSegment> = Vec::new();
+    for line in BufReader::new(input_f).lines() {
+        let line = line.expect("Error reading line.");
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let field_vec: Vec<&str> = line.split('\t').collect();
+        roh_segment_vec.push(RohSegment {
+            chr: field_vec[0].to_string(),
+            start: field_vec[1].parse().expect(&format!("Error parsing start in {}", line)),
+            end: field_vec[2].parse().expect(&format!("Error parsing end in {}", line)),
+            no_of_hom_snps: field_vec.get(3).and_then(|field| field.parse().ok()).unwrap_or(0),
+            no_of_hets: field_vec.get(4).and_then(|field| field.parse().ok()).unwrap_or(0),
+        });
+    }
+    roh_segment_vec
+}
+
+/// Output genotyped SNPs of one sample as "chr pos is_het" for concat, which finds runs of homozygosity over
+/// the sites of all shards, as runs can span shard boundaries.
+pub fn output_genotyped_sites(chr_site_vec: &[(String, Vec<(u64, bool)>)], output_file_path: &Path,
+                              min_roh_len: u64) {
+    println_stderr!("Outputting genotyped sites to {:?} ...", output_file_path);
+    let output_f = File::create(&output_file_path)
+        .expect(&format!("Error in creating output file {:?}", &output_file_path));
+    let mut gz_writer = flate2::GzBuilder::new()
+        .filename(output_file_path.file_stem().unwrap().to_str().unwrap())
+        .comment("Comment")
+        .write(output_f, Compression::default());
+    gz_writer.write_fmt(format_args!("#min_roh_len={}\n", min_roh_len)).unwrap();
+    gz_writer.write_fmt(format_args!("chr\tpos\tis_het\n")).unwrap();
+    for &(ref chr, ref site_vec) in chr_site_vec.iter() {
+        for &(pos, is_het) in site_vec.iter() {
+            gz_writer.write_fmt(format_args!("{}\t{}\t{}\n", chr, pos, is_het)).unwrap();
+        }
+    }
+    gz_writer.finish()
+        .expect(&format!("ERROR finish() failure for gz_writer of {:?}.", &output_file_path));
+    println_stderr!("Output done.");
+}
+
+/// Append sites of an output_genotyped_sites() file to chr_site_vec, continuing its last chromosome if the file
+/// starts on it. Return min_roh_len of the file.
+pub fn read_genotyped_sites(input_file_path: &Path, chr_site_vec: &mut Vec<(String, Vec<(u64, bool)>)>) -> u64 {
+    println_stderr!("Reading genotyped sites from {:?} ...", input_file_path);
+    let input_f = File::open(&input_file_path)
+        .expect(&format!("Error in opening file {:?}", &input_file_path));
+    let reader = BufReader::new(flate2::read::MultiGzDecoder::new(input_f)
+        .expect("Error in opening gzipped file."));
+    let mut min_roh_len: Option<u64> = None;
+    for line in reader.lines() {
+        let line = line.expect("Error reading line.");
+        if line.starts_with("#min_roh_len=") {
+            min_roh_len = Some(line["#min_roh_len=".len()..].parse()
+                .expect(&format!("Error parsing min_roh_len in {}", line)));
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') || line.starts_with("chr\t") {
+            continue;
+        }
+        let field_vec: Vec<&str> = line.split('\t').collect();
+        let pos: u64 = field_vec[1].parse().expect(&format!("Error parsing pos in {}", line));
+        let is_het: bool = field_vec[2].parse().expect(&format!("Error parsing is_het in {}", line));
+        let is_new_chr = match chr_site_vec.last() {
+            Some(&(ref last_chr, _)) => last_chr != field_vec[0],
+            None => true,
+        };
+        if is_new_chr {
+            chr_site_vec.push((field_vec[0].to_string(), Vec::new()));
+        }
+        chr_site_vec.last_mut().unwrap().1.push((pos, is_het));
+    }
+    min_roh_len.expect(&format!("No #min_roh_len= line in {:?}. Is it --roh_output of select_het_snp --region?",
+                                input_file_path))
+}
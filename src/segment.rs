@@ -0,0 +1,479 @@
+use flate2;
+use flate2::Compression;
+use std::cmp;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::prelude::*;
+use std::path::{Path};
+
+use normalize;
+
+
+//percent of data points excluded (half from either end) in the robust mean/stddev of a segment, as in GADA.
+const ROBUST_PERCENT_TO_EXCLUDE: usize = 40;
+//in joint segmentation, either side of a BAF breakpoint needs this many windows with het SNPs.
+const BAF_MIN_NO_OF_WINDOWS: usize = 10;
+//number of values on either side of the running median that noise stddev is estimated around.
+const NOISE_WINDOW_HALF_SIZE: usize = 50;
+
+/// Mean and stddev of values after excluding the top and bottom percent_to_exclude/2 percent.
+/// Ported from calculate_robust_mean_stddev() in Accurity/read_para.cpp.
+pub fn calc_robust_mean_stddev(value_slice: &[f64], percent_to_exclude: usize) -> (f64, f64) {
+    let mut value_vec = value_slice.to_vec();
+    value_vec.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    let vector_size = value_vec.len();
+    let lower_index = vector_size * percent_to_exclude / 200;
+    let upper_index = cmp::min(vector_size * (100 - percent_to_exclude / 2) / 100 + 1, vector_size);
+    let sample_size = (upper_index - lower_index) as f64;
+    let mut sum = 0f64;
+    let mut sum_squared = 0f64;
+    for value in value_vec[lower_index..upper_index].iter() {
+        sum += *value;
+        sum_squared += *value * *value;
+    }
+    let mean = sum / sample_size;
+    let variance = sum_squared / sample_size - mean * mean;
+    (mean, if variance >= 0.0 { variance.sqrt() } else { 0.0 })
+}
+
+/// Noise stddev of a series from the median absolute deviation of each value from the running median of
+/// its 2*NOISE_WINDOW_HALF_SIZE+1 neighbors, robust to the level shifts between segments.
+/// Falls back to the root mean square of the deviations if more than half of them are 0,
+/// e.g. for ratios already median-smoothed by normalize. Returns 0 only if the series is flat everywhere.
+fn estimate_noise_stddev(value_vec: &[f64]) -> f64 {
+    let no_of_values = value_vec.len();
+    if no_of_values < 2 {
+        return 0.0;
+    }
+    let mut abs_deviation_vec: Vec<f64> = Vec::with_capacity(no_of_values);
+    for index in 0..no_of_values {
+        let left_index = cmp::max(0 as i64, index as i64 - NOISE_WINDOW_HALF_SIZE as i64) as usize;
+        let right_stop = cmp::min(no_of_values, index + NOISE_WINDOW_HALF_SIZE + 1);
+        let mut neighbor_vec = value_vec[left_index..right_stop].to_vec();
+        neighbor_vec.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        abs_deviation_vec.push((value_vec[index] - neighbor_vec[neighbor_vec.len() / 2]).abs());
+    }
+    let sum_squared: f64 = abs_deviation_vec.iter().map(|deviation| deviation * deviation).sum();
+    abs_deviation_vec.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median_abs_deviation = abs_deviation_vec[no_of_values / 2];
+    if median_abs_deviation > 0.0 {
+        //1.4826*MAD estimates the stddev of a normal.
+        1.4826 * median_abs_deviation
+    } else {
+        (sum_squared / no_of_values as f64).sqrt()
+    }
+}
+
+/// One series to segment, possibly with missing values (e.g. BAF of windows without het SNPs).
+/// prefix_sum_vec[i] and prefix_count_vec[i] are the sum and number of values present in the first i windows.
+struct Signal {
+    prefix_sum_vec: Vec<f64>,
+    prefix_count_vec: Vec<usize>,
+    noise_stddev: f64,
+    //a side of a breakpoint needs this many values present for the signal to score it
+    min_count: usize,
+}
+
+impl Signal {
+    fn new(value_vec: &[Option<f64>], min_count: usize) -> Signal {
+        let mut prefix_sum_vec: Vec<f64> = Vec::with_capacity(value_vec.len() + 1);
+        let mut prefix_count_vec: Vec<usize> = Vec::with_capacity(value_vec.len() + 1);
+        prefix_sum_vec.push(0.0);
+        prefix_count_vec.push(0);
+        for value in value_vec.iter() {
+            let prefix_sum = prefix_sum_vec[prefix_sum_vec.len() - 1] + value.unwrap_or(0.0);
+            let prefix_count = prefix_count_vec[prefix_count_vec.len() - 1] + if value.is_some() { 1 } else { 0 };
+            prefix_sum_vec.push(prefix_sum);
+            prefix_count_vec.push(prefix_count);
+        }
+        let present_value_vec: Vec<f64> = value_vec.iter().filter_map(|value| *value).collect();
+        Signal {
+            prefix_sum_vec,
+            prefix_count_vec,
+            noise_stddev: estimate_noise_stddev(&present_value_vec),
+            min_count,
+        }
+    }
+
+    fn count(&self, start: usize, end: usize) -> usize {
+        self.prefix_count_vec[end] - self.prefix_count_vec[start]
+    }
+
+    fn mean(&self, start: usize, end: usize) -> f64 {
+        (self.prefix_sum_vec[end] - self.prefix_sum_vec[start]) / self.count(start, end) as f64
+    }
+
+    /// t-score of the breakpoint between [start, middle) and [middle, end). 0 if either side has too few values.
+    fn t_score(&self, start: usize, middle: usize, end: usize) -> f64 {
+        let left_count = self.count(start, middle);
+        let right_count = self.count(middle, end);
+        if self.noise_stddev <= 0.0 || left_count < self.min_count || right_count < self.min_count {
+            return 0.0;
+        }
+        (self.mean(middle, end) - self.mean(start, middle)).abs() /
+            (self.noise_stddev * (1.0 / left_count as f64 + 1.0 / right_count as f64).sqrt())
+    }
+}
+
+/// Piecewise-constant segmentation of one chromosome: binary segmentation followed by backward elimination
+/// of breakpoints, as in GADA. With more than one signal, a breakpoint scores the highest t-score of all,
+/// i.e. it is kept if supported by any signal.
+struct Segmenter<'a> {
+    signal_vec: &'a [Signal],
+    no_of_values: usize,
+    min_segment_len: usize,
+    t_score_threshold: f64,
+}
+
+impl<'a> Segmenter<'a> {
+    fn t_score(&self, start: usize, middle: usize, end: usize) -> f64 {
+        self.signal_vec.iter().map(|signal| signal.t_score(start, middle, end)).fold(0f64, f64::max)
+    }
+
+    /// Breakpoint of [start, end) with the highest t-score, leaving min_segment_len on either side.
+    fn find_best_breakpoint(&self, start: usize, end: usize) -> Option<(usize, f64)> {
+        if end - start < 2 * self.min_segment_len {
+            return None;
+        }
+        let mut best_breakpoint: Option<(usize, f64)> = None;
+        for middle in (start + self.min_segment_len)..(end - self.min_segment_len + 1) {
+            let t_score = self.t_score(start, middle, end);
+            if best_breakpoint.map_or(true, |(_, best_t_score)| t_score > best_t_score) {
+                best_breakpoint = Some((middle, t_score));
+            }
+        }
+        best_breakpoint
+    }
+
+    /// Sorted breakpoints of [0, no_of_values). Each breakpoint is the first index of a segment.
+    fn run(&self) -> Vec<usize> {
+        let no_of_values = self.no_of_values;
+        let mut breakpoint_vec: Vec<usize> = Vec::new();
+        //a stack instead of recursion. Chromosomes may have millions of windows.
+        let mut interval_stack: Vec<(usize, usize)> = vec![(0, no_of_values)];
+        while let Some((start, end)) = interval_stack.pop() {
+            if let Some((middle, t_score)) = self.find_best_breakpoint(start, end) {
+                if t_score >= self.t_score_threshold {
+                    breakpoint_vec.push(middle);
+                    interval_stack.push((start, middle));
+                    interval_stack.push((middle, end));
+                }
+            }
+        }
+        breakpoint_vec.sort();
+        //backward elimination: drop the weakest breakpoint until all pass the threshold in their new context.
+        loop {
+            let mut weakest: Option<(usize, f64)> = None;
+            for i in 0..breakpoint_vec.len() {
+                let start = if i == 0 { 0 } else { breakpoint_vec[i - 1] };
+                let end = if i + 1 == breakpoint_vec.len() { no_of_values } else { breakpoint_vec[i + 1] };
+                let t_score = self.t_score(start, breakpoint_vec[i], end);
+                if weakest.map_or(true, |(_, weakest_t_score)| t_score < weakest_t_score) {
+                    weakest = Some((i, t_score));
+                }
+            }
+            match weakest {
+                Some((i, t_score)) if t_score < self.t_score_threshold => {
+                    breakpoint_vec.remove(i);
+                },
+                _ => break,
+            }
+        }
+        breakpoint_vec
+    }
+}
+
+/// Segment the coverage ratios from Normalize, chromosome by chromosome, into one gzipped file compatible
+/// with all_segments.tsv.gz of GADA: chr, start, stop (1-based starts of the first and last window),
+/// robust mean ratio, robust stddev and number of windows.
+pub struct Segment<'a> {
+    ratio_folder: &'a Path,
+    window_size: u64,
+    output_file_path: &'a Path,
+    min_segment_len: usize,
+    t_score_threshold: f64,
+}
+
+impl<'a> Segment<'a> {
+    pub fn new(ratio_folder: &'a str,
+               window_size: u64,
+               output_file_path: &'a str,
+               min_segment_len: usize,
+               t_score_threshold: f64,
+    ) -> Segment<'a> {
+        //an empty side of a breakpoint has no mean, i.e. a NaN t-score.
+        if min_segment_len == 0 {
+            panic!("min_segment_len has to be at least 1.");
+        }
+        Segment {
+            ratio_folder: Path::new(ratio_folder),
+            window_size,
+            output_file_path: Path::new(output_file_path),
+            min_segment_len,
+            t_score_threshold,
+        }
+    }
+
+    pub fn run(&self) {
+        let chr_vec = normalize::find_ratio_chr_vec(self.ratio_folder, self.window_size);
+        if chr_vec.is_empty() {
+            panic!("No coverage ratio files of window size {} in {:?}.", self.window_size, self.ratio_folder);
+        }
+        let output_f = File::create(&self.output_file_path)
+            .expect(&format!("Error in creating output file {:?}", &self.output_file_path));
+        let mut gz_writer = flate2::GzBuilder::new()
+            .filename(self.output_file_path.file_stem().unwrap().to_str().unwrap())
+            .comment("Comment")
+            .write(output_f, Compression::default());
+        let mut total_no_of_segments = 0usize;
+        for chr in chr_vec.iter() {
+            let ratio_file_path = self.ratio_folder.join(format!("{}.ratio.w{}.csv.gz", chr, self.window_size));
+            let start_ratio_vec = normalize::read_ratio_file(&ratio_file_path);
+            if start_ratio_vec.is_empty() {
+                println_stderr!("WARNING: no coverage ratios in {:?}.", ratio_file_path);
+                continue;
+            }
+            let value_vec: Vec<f64> = start_ratio_vec.iter().map(|&(_, coverage_ratio)| coverage_ratio as f64).collect();
+            let signal_vec = vec![Signal::new(&value_vec.iter().map(|value| Some(*value)).collect::<Vec<_>>(),
+                                              self.min_segment_len)];
+            let noise_stddev = signal_vec[0].noise_stddev;
+            if noise_stddev <= 0.0 {
+                println_stderr!("WARNING: noise stddev of {} is 0 (constant coverage ratios). No breakpoints will be \
+                    called and it will be output as one segment.", chr);
+            }
+            let mut breakpoint_vec: Vec<usize> = Segmenter {
+                signal_vec: &signal_vec,
+                no_of_values: value_vec.len(),
+                min_segment_len: self.min_segment_len,
+                t_score_threshold: self.t_score_threshold,
+            }.run();
+            println_stderr!("{}: {} windows, noise stddev {}, {} segments.", chr, value_vec.len(), noise_stddev,
+                            breakpoint_vec.len() + 1);
+            gz_writer.write_fmt(format_args!("# Parameters: T={}, MinSegLen={}, sigma={}. Chromosome {}.\n",
+                                             self.t_score_threshold, self.min_segment_len, noise_stddev, chr)).unwrap();
+            gz_writer.write_fmt(format_args!("# {} data points in input file\n", value_vec.len())).unwrap();
+            gz_writer.write_fmt(format_args!("# Kept {} breakpoints\n", breakpoint_vec.len())).unwrap();
+            breakpoint_vec.insert(0, 0);
+            breakpoint_vec.push(value_vec.len());
+            for boundary in breakpoint_vec.windows(2) {
+                let (mean, stddev) = calc_robust_mean_stddev(&value_vec[boundary[0]..boundary[1]],
+                                                             ROBUST_PERCENT_TO_EXCLUDE);
+                gz_writer.write_fmt(format_args!("{}\t{}\t{}\t{}\t{}\t{}\n", chr, start_ratio_vec[boundary[0]].0,
+                                                 start_ratio_vec[boundary[1] - 1].0, mean, stddev,
+                                                 boundary[1] - boundary[0])).unwrap();
+            }
+            total_no_of_segments += breakpoint_vec.len() - 1;
+        }
+        gz_writer.finish()
+            .expect(&format!("ERROR finish() failure for gz_writer of {:?}.", &self.output_file_path));
+        println_stderr!("{} segments of {} chromosomes written to {:?}.", total_no_of_segments, chr_vec.len(),
+                        self.output_file_path);
+    }
+}
+
+/// One window of the merge_features output.
+struct MergedWindow {
+    //1-based, as starts in coverage ratio files
+    start: u64,
+    coverage_ratio: f64,
+    no_of_snps: usize,
+    //None if the window has no het SNPs
+    median_mirrored_baf: Option<f64>,
+}
+
+/// Window rows of a merge_features output, by chromosome in file order.
+fn read_merged_features_file(merged_features_file_path: &Path) -> Vec<(String, Vec<MergedWindow>)> {
+    println_stderr!("Reading windows from {:?} ...", merged_features_file_path);
+    let input_f = File::open(merged_features_file_path)
+        .expect(&format!("Error in opening {:?}", merged_features_file_path));
+    let reader = BufReader::new(flate2::read::MultiGzDecoder::new(input_f)
+        .expect(&format!("Error in gz-decoding {:?}", merged_features_file_path)));
+    let mut chr_window_vec: Vec<(String, Vec<MergedWindow>)> = Vec::new();
+    for line in reader.lines() {
+        let line = line.unwrap();
+        if line.starts_with('#') {
+            continue;
+        }
+        let field_vec: Vec<&str> = line.split('\t').collect();
+        if field_vec.len() < 7 || field_vec[3] != "W" {
+            continue;
+        }
+        let chr = field_vec[0];
+        let start: u64 = field_vec[1].parse().unwrap();
+        let window = MergedWindow {
+            start: start + 1,
+            coverage_ratio: field_vec[4].parse().unwrap(),
+            no_of_snps: field_vec[5].parse().unwrap(),
+            median_mirrored_baf: if field_vec[6] == "NA" { None } else { Some(field_vec[6].parse().unwrap()) },
+        };
+        let is_new_chr = chr_window_vec.last().map_or(true, |&(ref last_chr, _)| last_chr != chr);
+        if is_new_chr {
+            chr_window_vec.push((chr.to_string(), Vec::new()));
+        }
+        chr_window_vec.last_mut().unwrap().1.push(window);
+    }
+    chr_window_vec
+}
+
+/// Joint segmentation of the coverage ratio and the median mirrored BAF of windows in the merge_features output.
+/// A breakpoint is kept if either signal supports it, so copy-neutral LOH, which leaves the ratio flat, is
+/// split off by the BAF. Output has the columns of Segment plus the mean of window median mirrored BAFs
+/// (NA if no window has het SNPs) and the number of het SNPs of each segment.
+pub struct JointSegment<'a> {
+    merged_features_file_path: &'a Path,
+    output_file_path: &'a Path,
+    min_segment_len: usize,
+    t_score_threshold: f64,
+}
+
+impl<'a> JointSegment<'a> {
+    pub fn new(merged_features_file_path: &'a str,
+               output_file_path: &'a str,
+               min_segment_len: usize,
+               t_score_threshold: f64,
+    ) -> JointSegment<'a> {
+        //an empty side of a breakpoint has no mean, i.e. a NaN t-score.
+        if min_segment_len == 0 {
+            panic!("min_segment_len has to be at least 1.");
+        }
+        JointSegment {
+            merged_features_file_path: Path::new(merged_features_file_path),
+            output_file_path: Path::new(output_file_path),
+            min_segment_len,
+            t_score_threshold,
+        }
+    }
+
+    pub fn run(&self) {
+        let chr_window_vec = read_merged_features_file(self.merged_features_file_path);
+        if chr_window_vec.is_empty() {
+            panic!("No windows in {:?}.", self.merged_features_file_path);
+        }
+        let output_f = File::create(&self.output_file_path)
+            .expect(&format!("Error in creating output file {:?}", &self.output_file_path));
+        let mut gz_writer = flate2::GzBuilder::new()
+            .filename(self.output_file_path.file_stem().unwrap().to_str().unwrap())
+            .comment("Comment")
+            .write(output_f, Compression::default());
+        gz_writer.write_fmt(format_args!("#chr\tstart\tstop\tmean\tstddev\tno_of_windows\tmirrored_baf_mean\t\
+            no_of_snps\n")).unwrap();
+        let mut total_no_of_segments = 0usize;
+        for &(ref chr, ref window_vec) in chr_window_vec.iter() {
+            let ratio_vec: Vec<f64> = window_vec.iter().map(|window| window.coverage_ratio).collect();
+            let baf_vec: Vec<Option<f64>> = window_vec.iter().map(|window| window.median_mirrored_baf).collect();
+            let signal_vec = vec![
+                Signal::new(&ratio_vec.iter().map(|ratio| Some(*ratio)).collect::<Vec<_>>(), self.min_segment_len),
+                Signal::new(&baf_vec, BAF_MIN_NO_OF_WINDOWS),
+            ];
+            if signal_vec[0].noise_stddev <= 0.0 {
+                println_stderr!("WARNING: noise stddev of coverage ratios of {} is 0 (constant ratios). \
+                    Only BAF will be used to call its breakpoints.", chr);
+            }
+            if signal_vec[1].noise_stddev <= 0.0 && signal_vec[1].count(0, window_vec.len()) > 0 {
+                println_stderr!("WARNING: noise stddev of BAF of {} is 0 (constant BAF). \
+                    Only coverage ratios will be used to call its breakpoints.", chr);
+            }
+            let mut breakpoint_vec: Vec<usize> = Segmenter {
+                signal_vec: &signal_vec,
+                no_of_values: window_vec.len(),
+                min_segment_len: self.min_segment_len,
+                t_score_threshold: self.t_score_threshold,
+            }.run();
+            println_stderr!("{}: {} windows ({} with het SNPs), noise stddev {} (ratio) and {} (BAF), {} segments.",
+                            chr, window_vec.len(), signal_vec[1].count(0, window_vec.len()),
+                            signal_vec[0].noise_stddev, signal_vec[1].noise_stddev, breakpoint_vec.len() + 1);
+            gz_writer.write_fmt(format_args!("# Parameters: T={}, MinSegLen={}, sigma={}, sigma_baf={}. Chromosome {}.\n",
+                                             self.t_score_threshold, self.min_segment_len, signal_vec[0].noise_stddev,
+                                             signal_vec[1].noise_stddev, chr)).unwrap();
+            gz_writer.write_fmt(format_args!("# {} data points in input file\n", window_vec.len())).unwrap();
+            gz_writer.write_fmt(format_args!("# Kept {} breakpoints\n", breakpoint_vec.len())).unwrap();
+            breakpoint_vec.insert(0, 0);
+            breakpoint_vec.push(window_vec.len());
+            for boundary in breakpoint_vec.windows(2) {
+                let (mean, stddev) = calc_robust_mean_stddev(&ratio_vec[boundary[0]..boundary[1]],
+                                                             ROBUST_PERCENT_TO_EXCLUDE);
+                let mirrored_baf_mean = if signal_vec[1].count(boundary[0], boundary[1]) > 0 {
+                    signal_vec[1].mean(boundary[0], boundary[1]).to_string()
+                } else {
+                    "NA".to_string()
+                };
+                let no_of_snps = window_vec[boundary[0]..boundary[1]].iter().fold(0, |sum, window| sum + window.no_of_snps);
+                gz_writer.write_fmt(format_args!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n", chr, window_vec[boundary[0]].start,
+                                                 window_vec[boundary[1] - 1].start, mean, stddev,
+                                                 boundary[1] - boundary[0], mirrored_baf_mean, no_of_snps)).unwrap();
+            }
+            total_no_of_segments += breakpoint_vec.len() - 1;
+        }
+        gz_writer.finish()
+            .expect(&format!("ERROR finish() failure for gz_writer of {:?}.", &self.output_file_path));
+        println_stderr!("{} segments of {} chromosomes written to {:?}.", total_no_of_segments, chr_window_vec.len(),
+                        self.output_file_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A linear congruential generator of uniforms in (0, 1), to simulate noise without a rand dependency.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next_uniform(&mut self) -> f64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((self.0 >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+        }
+    }
+
+    /// level_vec[i] plus uniform noise of stddev 0.1.
+    fn add_noise(level_vec: &[f64]) -> Vec<Option<f64>> {
+        let mut lcg = Lcg(3);
+        level_vec.iter().map(|level| Some(level + (lcg.next_uniform() - 0.5) * 0.1 * 12f64.sqrt())).collect()
+    }
+
+    fn segment(value_vec: &[Option<f64>], min_segment_len: usize) -> Vec<usize> {
+        let signal_vec = vec![Signal::new(value_vec, min_segment_len)];
+        Segmenter {
+            signal_vec: &signal_vec,
+            no_of_values: value_vec.len(),
+            min_segment_len,
+            t_score_threshold: 5.0,
+        }.run()
+    }
+
+    #[test]
+    fn step_gives_one_breakpoint() {
+        let level_vec: Vec<f64> = (0..400).map(|index| if index < 250 { 1.0 } else { 1.5 }).collect();
+        let value_vec = add_noise(&level_vec);
+        let present_value_vec: Vec<f64> = value_vec.iter().filter_map(|value| *value).collect();
+        let noise_stddev = estimate_noise_stddev(&present_value_vec);
+        assert!((noise_stddev - 0.1).abs() < 0.03, "noise stddev {}", noise_stddev);
+        assert_eq!(segment(&value_vec, 5), vec![250]);
+    }
+
+    #[test]
+    fn flat_series_has_no_noise_and_one_segment() {
+        let value_vec: Vec<Option<f64>> = vec![Some(1.0); 200];
+        assert_eq!(estimate_noise_stddev(&[1.0; 200]), 0.0);
+        assert!(segment(&value_vec, 5).is_empty());
+    }
+
+    #[test]
+    fn min_segment_len_at_the_edges() {
+        let min_segment_len = 10;
+        //a step exactly min_segment_len from either end is found.
+        let level_vec: Vec<f64> = (0..200).map(|index| if index < min_segment_len { 3.0 } else { 1.0 }).collect();
+        assert_eq!(segment(&add_noise(&level_vec), min_segment_len), vec![min_segment_len]);
+        let level_vec: Vec<f64> = (0..200).map(|index| if index < 200 - min_segment_len { 1.0 } else { 3.0 }).collect();
+        assert_eq!(segment(&add_noise(&level_vec), min_segment_len), vec![200 - min_segment_len]);
+        //one value closer, the breakpoint is held at min_segment_len, not shortening the first segment.
+        let level_vec: Vec<f64> = (0..200).map(|index| if index < min_segment_len - 1 { 3.0 } else { 1.0 }).collect();
+        assert_eq!(segment(&add_noise(&level_vec), min_segment_len), vec![min_segment_len]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_min_segment_len_is_rejected() {
+        Segment::new("ratio", 500, "segments.tsv.gz", 0, 5.0);
+    }
+}
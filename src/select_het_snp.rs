@@ -3,17 +3,53 @@ extern crate time;
 use flate2;
 use flate2::Compression;
 use rust_htslib::bcf;
+use rust_htslib::bcf::Read as bcfRead;
 use std::cmp;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::f32;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::{Path};
 use std::str;
 
+use contamination;
+use contamination::{ContaminationEstimate, ContaminationProfile, ContaminationSite};
+use genotype::{classify_genotype, GenotypeClass, OneGenotype};
+use haplotype_block;
+use het_snp_vcf::{HetSNPVcfRecord, HetSNPVcfWriter};
+use haplotype_block::PhasedSNP;
+use known_sites::KnownSites;
+use prob;
+use ref_bias;
+use ref_bias::RefBias;
+use region;
+use region::Region;
+use roh;
+use sex;
+use sex::{GenomeBuild, Sex, SexCount};
+use tumor_in_normal;
+use tumor_in_normal::PairedSNPCount;
+
+
+/// The major fraction of tumor allele counts after dividing each by its normal count.
+pub fn calc_tumor_maf_normalized(tumor_ref_obs: usize, tumor_alt_obs: usize,
+                                 normal_ref_obs: usize, normal_alt_obs: usize) -> f32 {
+    let ratio_ref = tumor_ref_obs as f32/normal_ref_obs as f32;
+    let ratio_alt  = tumor_alt_obs as f32/normal_alt_obs as f32;
+    if ratio_alt>ratio_ref{
+        ratio_alt/(ratio_alt + ratio_ref)
+    } else {
+        ratio_ref/(ratio_alt + ratio_ref)
+    }
+}
 
 struct OneSNP {
     chr: String,
     start: u64,
+    //REF of the record. Differs from ref_allele only for multi-allelic hets.
+    record_ref_allele: Vec<u8>,
+    ref_allele: Vec<u8>,
+    alt_allele: Vec<u8>,
     genotype: String,
     depth: usize,
     abp_value: f32,
@@ -21,11 +57,21 @@ struct OneSNP {
     sap_value: f32,
     no_of_ref_obs: usize,
     no_of_alt_obs: usize,
+    is_phased: bool,
+    //PS of a phased het. None if unphased or PS is absent.
+    phase_set: Option<i32>,
+    //whether the first haplotype in GT carries the lower-index (ref) allele, i.e. true for 0|1.
+    hap1_is_ref: bool,
+    //a het between two alternative alleles, i.e. 1/2. Its ref_obs is not of the reference allele.
+    is_multi_allelic_het: bool,
 }
 
 impl OneSNP {
     fn new(chr: String,
            start: u64,
+           record_ref_allele: Vec<u8>,
+           ref_allele: Vec<u8>,
+           alt_allele: Vec<u8>,
            genotype: String,
            depth: usize,
            abp_value: f32,
@@ -36,6 +82,9 @@ impl OneSNP {
         OneSNP {
             chr,
             start,
+            record_ref_allele,
+            ref_allele,
+            alt_allele,
             genotype,
             depth,
             abp_value,
@@ -43,23 +92,168 @@ impl OneSNP {
             sap_value,
             no_of_ref_obs,
             no_of_alt_obs,
+            is_phased: false,
+            phase_set: None,
+            hap1_is_ref: true,
+            is_multi_allelic_het: false,
         }
     }
+
+    fn set_phase(&mut self, phase_set: Option<i32>, hap1_is_ref: bool) {
+        self.is_phased = true;
+        self.phase_set = phase_set;
+        self.hap1_is_ref = hap1_is_ref;
+    }
 }
 
 struct OneGenomeSNP{
     chr_start2snp: HashMap<(u64, u64), OneSNP>,
     no_of_total_records: usize,
     no_of_good_hets: usize,
+    no_of_indels_mnps: usize,
+    no_of_multi_allelic: usize,
+    no_of_not_known_sites: usize,
+    no_of_allele_imbalanced: usize,
+    //genotyped biallelic SNPs, het or hom, only kept for the contamination estimate.
+    contamination_site_vec: Vec<ContaminationSite>,
+    contamination: Option<ContaminationEstimate>,
+    //kept in region mode, for concat to add up over shards
+    contamination_profile: Option<ContaminationProfile>,
+    //alleles and their read counts of every SNP record, whatever the genotype, only kept for the tumor to estimate
+    //tumor in normal at normal hets.
+    chr_start2allele_obs: HashMap<(u64, u64), (Vec<Vec<u8>>, Vec<usize>)>,
+    //(position, is_het) of all genotyped SNPs per chromosome, only kept to find runs of homozygosity.
+    chr_genotyped_site_vec: Vec<(String, Vec<(u64, bool)>)>,
+    no_of_roh: usize,
+    //het/hom calls on non-PAR chrX and autosomes, before any het filter
+    sex_count: SexCount,
+    no_of_x_non_par_hets: usize,
+    no_of_x_par_hets: usize,
+    no_of_x_non_par_hets_dropped: usize,
 }
 
 impl OneGenomeSNP{
-    fn new() -> OneGenomeSNP{
+    fn new(genome_build: GenomeBuild) -> OneGenomeSNP{
         OneGenomeSNP{
             chr_start2snp: HashMap::new(),
             no_of_total_records: 0,
             no_of_good_hets: 0,
+            no_of_indels_mnps: 0,
+            no_of_multi_allelic: 0,
+            no_of_not_known_sites: 0,
+            no_of_allele_imbalanced: 0,
+            contamination_site_vec: Vec::new(),
+            contamination: None,
+            contamination_profile: None,
+            chr_start2allele_obs: HashMap::new(),
+            chr_genotyped_site_vec: Vec::new(),
+            no_of_roh: 0,
+            sex_count: SexCount::new(genome_build),
+            no_of_x_non_par_hets: 0,
+            no_of_x_par_hets: 0,
+            no_of_x_non_par_hets_dropped: 0,
+        }
+    }
+
+    /// Drop hets that are not population SNPs in the known-sites panel or whose population AF is below the floor.
+    fn retain_known_sites(&mut self, known_sites: &KnownSites, min_population_af: f32) {
+        let no_of_hets_before = self.chr_start2snp.len();
+        self.chr_start2snp.retain(|_, snp| {
+            match known_sites.get(&snp.chr, snp.start) {
+                Some(known_site) => {
+                    let af_ok = match known_site.population_af {
+                        //population AF is folded so that the floor applies to the minor allele.
+                        Some(af) => af.min(1.0 - af) >= min_population_af,
+                        None => min_population_af <= 0.0,
+                    };
+                    known_site.is_snp() && af_ok && known_site.matches_alleles(&snp.ref_allele, &snp.alt_allele)
+                },
+                None => false,
+            }
+        });
+        self.no_of_not_known_sites = no_of_hets_before - self.chr_start2snp.len();
+        self.no_of_good_hets -= self.no_of_not_known_sites;
+    }
+
+    /// Replace the phase of hets by that from an external phasing run. Hets absent or unphased there become unphased.
+    fn apply_external_phase(&mut self, chr_pos2phase: &HashMap<(String, u64), (Option<i32>, Vec<u8>)>) -> usize {
+        let mut no_of_phased = 0usize;
+        for snp in self.chr_start2snp.values_mut() {
+            snp.is_phased = false;
+            snp.phase_set = None;
+            if let Some(&(phase_set, ref hap1_allele)) = chr_pos2phase.get(&(snp.chr.clone(), snp.start)) {
+                if *hap1_allele == snp.ref_allele || *hap1_allele == snp.alt_allele {
+                    let hap1_is_ref = *hap1_allele == snp.ref_allele;
+                    snp.set_phase(phase_set, hap1_is_ref);
+                    no_of_phased += 1;
+                }
+            }
+        }
+        no_of_phased
+    }
+
+    /// Fit the beta-binomial overdispersion of allele counts at hets, assumed germline 50/50.
+    fn fit_beta_binomial_rho(&self) -> f64 {
+        let k_n_vec: Vec<(usize, usize)> = self.chr_start2snp.values()
+            .map(|snp| (snp.no_of_alt_obs, snp.depth)).collect();
+        prob::fit_beta_binomial_het_rho(&k_n_vec)
+    }
+
+    /// Drop hets whose allele balance is unlikely under a 50/50 beta-binomial with overdispersion rho.
+    fn retain_beta_binomial(&mut self, rho: f64, pvalue_min: f64) {
+        let no_of_hets_before = self.chr_start2snp.len();
+        self.chr_start2snp.retain(|_, snp| {
+            prob::beta_binomial_het_pvalue(snp.no_of_alt_obs, snp.depth, rho) >= pvalue_min
+        });
+        self.no_of_allele_imbalanced = no_of_hets_before - self.chr_start2snp.len();
+        self.no_of_good_hets -= self.no_of_allele_imbalanced;
+    }
+
+    fn add_genotyped_site(&mut self, chr: &[u8], pos: u64, is_het: bool) {
+        let is_new_chr = match self.chr_genotyped_site_vec.last() {
+            Some(&(ref last_chr, _)) => last_chr.as_bytes() != chr,
+            None => true,
+        };
+        if is_new_chr {
+            self.chr_genotyped_site_vec.push((String::from_utf8_lossy(chr).to_string(), Vec::new()));
         }
+        self.chr_genotyped_site_vec.last_mut().unwrap().1.push((pos, is_het));
+    }
+
+    /// Count selected chrX hets within and outside PARs. For males, drop the latter, which are mapping artefacts.
+    fn apply_sex(&mut self, sex: Sex, genome_build: GenomeBuild) {
+        for snp in self.chr_start2snp.values() {
+            if sex::is_chr_x(snp.chr.as_bytes()) {
+                if sex::is_x_non_par(snp.start, genome_build) {
+                    self.no_of_x_non_par_hets += 1;
+                } else {
+                    self.no_of_x_par_hets += 1;
+                }
+            }
+        }
+        if sex == Sex::Male {
+            self.chr_start2snp.retain(|_, snp| {
+                !(sex::is_chr_x(snp.chr.as_bytes()) && sex::is_x_non_par(snp.start, genome_build))
+            });
+            self.no_of_x_non_par_hets_dropped = self.no_of_x_non_par_hets;
+            self.no_of_good_hets -= self.no_of_x_non_par_hets_dropped;
+        }
+    }
+
+    /// depth => (number of hets, total ref count) of bi-allelic (0/1) hets, to estimate ref-mapping bias.
+    fn calc_ref_bias_depth_histogram(&self) -> BTreeMap<usize, (usize, usize)> {
+        let ref_obs_depth_vec: Vec<(usize, usize)> = self.chr_start2snp.values()
+            .filter(|snp| !snp.is_multi_allelic_het)
+            .map(|snp| (snp.no_of_ref_obs, snp.depth)).collect();
+        ref_bias::calc_depth_histogram(&ref_obs_depth_vec)
+    }
+
+    fn get_chr_pos_set(&self) -> HashSet<(String, u64)> {
+        self.chr_start2snp.values().map(|snp| (snp.chr.clone(), snp.start)).collect()
+    }
+
+    fn get_contamination_chr_pos_set(&self) -> HashSet<(String, u64)> {
+        self.contamination_site_vec.iter().map(|site| (site.chr.clone(), site.pos)).collect()
     }
 }
 
@@ -67,6 +261,24 @@ pub struct SelectHetSNP<'a> {
     snp_file_path_tumor: &'a Path,
     snp_file_path_normal: &'a Path,
     output_file_path: &'a Path,
+    known_sites_path: Option<&'a Path>,
+    min_population_af: f32,
+    multi_allelic_het: bool,
+    phase_block_output_path: Option<&'a Path>,
+    phased_normal_vcf_path: Option<&'a Path>,
+    //None: normal hets are filtered by abp_max_normal.
+    beta_binomial_pvalue_min: Option<f64>,
+    //None is to fit it to normal hets
+    beta_binomial_rho: Option<f64>,
+    contamination_qc_path: Option<&'a Path>,
+    vcf_output_path: Option<&'a Path>,
+    //None: whole VCFs are streamed.
+    region_vec: Option<Vec<Region>>,
+    roh_output_path: Option<&'a Path>,
+    min_roh_len: u64,
+    //None: inferred from the normal.
+    sex_option: Option<Sex>,
+    genome_build: GenomeBuild,
     abp_max_tumor: f32,
     abp_max_normal: f32,
     srp_max: f32,
@@ -94,6 +306,20 @@ impl<'a> SelectHetSNP<'a> {
             snp_file_path_tumor: Path::new(snp_file_path_tumor),
             snp_file_path_normal: Path::new(snp_file_path_normal),
             output_file_path: Path::new(output_file_path),
+            known_sites_path: None,
+            min_population_af: 0.0,
+            multi_allelic_het: false,
+            phase_block_output_path: None,
+            phased_normal_vcf_path: None,
+            beta_binomial_pvalue_min: None,
+            beta_binomial_rho: None,
+            contamination_qc_path: None,
+            vcf_output_path: None,
+            region_vec: None,
+            roh_output_path: None,
+            min_roh_len: 0,
+            sex_option: None,
+            genome_build: GenomeBuild::Hg19,
             abp_max_tumor,
             abp_max_normal,
             srp_max,
@@ -104,46 +330,299 @@ impl<'a> SelectHetSNP<'a> {
         }
     }
 
-    fn read_in_het_snp(&'a self, snp_file_path: &'a Path, abp_max: f32) -> OneGenomeSNP {
+    /// Keep only hets at sites of a known-sites panel (VCF/BED, bgzipped and indexed).
+    /// min_population_af applies to the minor allele frequency in the panel's AF tag.
+    pub fn set_known_sites(&mut self, known_sites_path: &'a str, min_population_af: f32) {
+        self.known_sites_path = Some(Path::new(known_sites_path));
+        self.min_population_af = min_population_af;
+    }
+
+    /// Accept hets between two alternative alleles (i.e. 1/2) of multi-allelic records.
+    /// Their two allele counts take the place of RO/AO.
+    pub fn set_multi_allelic_het(&mut self, multi_allelic_het: bool) {
+        self.multi_allelic_het = multi_allelic_het;
+    }
+
+    /// Sum tumor allele counts per haplotype across each phase set (PS) of the normal and output a block-level BAF table.
+    /// Phase comes from the normal VCF itself or, if given, from an externally phased normal VCF.
+    pub fn set_phase_block_output(&mut self, phase_block_output_path: &'a str, phased_normal_vcf_path: Option<&'a str>) {
+        self.phase_block_output_path = Some(Path::new(phase_block_output_path));
+        self.phased_normal_vcf_path = phased_normal_vcf_path.map(|path| Path::new(path));
+    }
+
+    /// Replace the ABP cut on normal hets by a beta-binomial test.
+    /// Its overdispersion is fitted to normal allele counts genome-wide and hets with p-value below pvalue_min are dropped.
+    pub fn set_beta_binomial_filter(&mut self, pvalue_min: f64) {
+        self.beta_binomial_pvalue_min = Some(pvalue_min);
+    }
+
+    /// Use this overdispersion in the beta-binomial test instead of fitting it, i.e. the same one for all shards.
+    pub fn set_beta_binomial_rho(&mut self, rho: f64) {
+        if rho < 0.0 || rho >= 1.0 {
+            panic!("beta-binomial rho {} is not within [0, 1).", rho);
+        }
+        self.beta_binomial_rho = Some(rho);
+    }
+
+    /// Estimate foreign-DNA contamination of each sample from allele reads of its genotyped SNPs and population AF
+    /// of the known sites.
+    /// Requires set_known_sites() with a VCF carrying the AF tag.
+    pub fn set_contamination_qc(&mut self, contamination_qc_path: &'a str) {
+        self.contamination_qc_path = Some(Path::new(contamination_qc_path));
+    }
+
+    /// Also write selected hets into a bgzipped, tabix-indexed VCF (.vcf.gz), with counts as FORMAT/INFO fields.
+    pub fn set_vcf_output(&mut self, vcf_output_path: &'a str) {
+        self.vcf_output_path = Some(Path::new(vcf_output_path));
+    }
+
+    /// Read only these regions of the tumor and normal VCFs, which then have to be bgzipped and indexed.
+    /// Overlapping regions are merged. Sex and, for the beta-binomial filter, rho drop sites and have to be given,
+    /// so that all shards share them. The ref bias is re-estimated genome-wide by concat from the per-depth
+    /// counts of normal hets, which go to the output header.
+    pub fn set_regions(&mut self, region_vec: &[Region]) {
+        self.region_vec = Some(region::merge_regions(region_vec));
+    }
+
+    /// Find runs of homozygosity (germline UPD, consanguinity) in the normal and output them as BED.
+    /// They hold almost no hets, so call takes the BED to not mistake them for tumor LOH.
+    pub fn set_roh_output(&mut self, roh_output_path: &'a str, min_roh_len: u64) {
+        self.roh_output_path = Some(Path::new(roh_output_path));
+        self.min_roh_len = min_roh_len;
+    }
+
+    /// Sex of the individual. None (default) is to infer it from chrX het calls of the normal.
+    /// Hets on chrX outside PARs are dropped for males.
+    pub fn set_sex(&mut self, sex_option: Option<Sex>) {
+        self.sex_option = sex_option;
+    }
+
+    /// Genome build of the SNP files, for the chrX pseudo-autosomal regions. Default is hg19.
+    pub fn set_genome_build(&mut self, genome_build: GenomeBuild) {
+        self.genome_build = genome_build;
+    }
+
+    /// Filter settings as (key, value), for the VCF header, in the order of the output's header lines,
+    /// from which concat rebuilds them.
+    fn get_setting_vec(&self, normal_beta_binomial_rho: Option<f64>) -> Vec<(String, String)> {
+        let mut setting_vec: Vec<(String, String)> = vec![
+            ("abp_max_tumor".to_string(), self.abp_max_tumor.to_string()),
+            ("abp_max_normal".to_string(), self.abp_max_normal.to_string()),
+            ("srp_max".to_string(), self.srp_max.to_string()),
+            ("sap_max".to_string(), self.sap_max.to_string()),
+            ("min_coverage".to_string(), self.min_coverage.to_string()),
+            ("max_coverage".to_string(), self.max_coverage.to_string()),
+            ("multi_allelic_het".to_string(), self.multi_allelic_het.to_string()),
+        ];
+        if let Some(known_sites_path) = self.known_sites_path {
+            setting_vec.push(("known_sites".to_string(), format!("{:?}", known_sites_path)));
+            setting_vec.push(("min_population_af".to_string(), self.min_population_af.to_string()));
+        }
+        if let (Some(rho), Some(pvalue_min)) = (normal_beta_binomial_rho, self.beta_binomial_pvalue_min) {
+            setting_vec.push(("normal_beta_binomial_rho".to_string(), rho.to_string()));
+            setting_vec.push(("beta_binomial_pvalue_min".to_string(), pvalue_min.to_string()));
+        }
+        setting_vec.push(("snp_file_path_tumor".to_string(), format!("{:?}", self.snp_file_path_tumor)));
+        setting_vec.push(("snp_file_path_normal".to_string(), format!("{:?}", self.snp_file_path_normal)));
+        setting_vec
+    }
+
+    /// Classify one SNP record and add it to one_genome_snp as a het if it passes filters
+    /// (and as a contamination site if it is a genotyped biallelic SNP).
+    fn add_record(&self, mut record: bcf::Record, vcf_header: &bcf::HeaderView, abp_max: f32,
+                  collect_genotyped_sites: bool, collect_allele_obs: bool, one_genome_snp: &mut OneGenomeSNP) {
+        one_genome_snp.no_of_total_records += 1;
+        let abp_value = record.info(b"ABP").float().ok().expect("Error reading ABP float.").expect("Missing tag ABP")[0];
+        let srp_value = record.info(b"SRP").float().ok().expect("Error reading SRP float.").expect("Missing tag SRP")[0];
+        let sap_value = record.info(b"SAP").float().ok().expect("Error reading SAP float.").expect("Missing tag SAP")[0];
+        let allele_vec: Vec<Vec<u8>>;
+        {
+            //RO/AO are only defined for SNPs. Indels and MNPs are excluded.
+            //Multi-allelic records are excluded unless multi_allelic_het is on.
+            allele_vec = record.alleles().iter().map(|allele| allele.to_vec()).collect();
+            if allele_vec.iter().any(|allele| allele.len() != 1) {
+                one_genome_snp.no_of_indels_mnps += 1;
+                return;
+            }
+            if allele_vec.len() > 2 && !self.multi_allelic_het {
+                one_genome_snp.no_of_multi_allelic += 1;
+                return;
+            }
+        }
+        if collect_allele_obs {
+            let ro_value = record.format(b"RO").integer().ok().expect("Error reading RO integer.")[0][0];
+            let ao_vec = record.format(b"AO").integer().ok().expect("Error reading AO integer.")[0].to_vec();
+            let mut obs_vec: Vec<usize> = vec![ro_value as usize];
+            obs_vec.extend(ao_vec.iter().map(|ao_value| *ao_value as usize));
+            let snp_key = (record.rid().expect("Error reading rid.") as u64, record.pos() as u64);
+            one_genome_snp.chr_start2allele_obs.insert(snp_key, (allele_vec.clone(), obs_vec));
+        }
+        let sample_1_genotype: String;
+        let one_genotype: OneGenotype;
+        {
+            //a separate scope due to conflict between mutable borrow, record.genotypes(), and immutable borrows, record.rid(), etc..
+            let genotypes = record.genotypes().expect("Error reading genotypes");
+            let genotype = genotypes.get(0);
+            sample_1_genotype = format!("{}", genotype);
+            one_genotype = classify_genotype(&genotype);
+        }
+        if one_genotype.is_het() || one_genotype.is_hom() {
+            let chr = vcf_header.rid2name(record.rid().expect("Error reading rid."));
+            one_genome_snp.sex_count.add(chr, record.pos() as u64, one_genotype.is_het());
+            if collect_genotyped_sites {
+                one_genome_snp.add_genotyped_site(chr, record.pos() as u64, one_genotype.is_het());
+            }
+            //whatever the genotype and ABP, as contamination changes both.
+            if self.contamination_qc_path.is_some() && allele_vec.len() == 2 {
+                let ro_value = record.format(b"RO").integer().ok().expect("Error reading RO integer.")[0][0];
+                let ao_value = record.format(b"AO").integer().ok().expect("Error reading AO integer.")[0][0];
+                let depth = (ro_value + ao_value) as usize;
+                if depth >= self.min_coverage && depth <= self.max_coverage {
+                    one_genome_snp.contamination_site_vec.push(ContaminationSite {
+                        chr: String::from_utf8_lossy(chr).to_string(),
+                        pos: record.pos() as u64,
+                        ref_allele: allele_vec[0].clone(),
+                        alt_allele: allele_vec[1].clone(),
+                        no_of_alt_obs: ao_value as usize,
+                        depth,
+                    });
+                }
+            }
+        }
+        if let GenotypeClass::Het(allele_index_1, allele_index_2) = one_genotype.class {
+            if abp_value > abp_max || (one_genotype.is_multi_allelic_het() && !self.multi_allelic_het) {
+                return;
+            }
+            //ref_obs/alt_obs are the counts of the lower/higher-index allele of the het, RO/AO for a 0/1 het.
+            let lower_allele_index = cmp::min(allele_index_1, allele_index_2) as usize;
+            let higher_allele_index = cmp::max(allele_index_1, allele_index_2) as usize;
+            let no_of_ref_obs: usize;
+            let no_of_alt_obs: usize;
+            {
+                let ro_value = record.format(b"RO").integer().ok().expect("Error reading RO integer.")[0][0];
+                let ao_vec = record.format(b"AO").integer().ok().expect("Error reading AO integer.")[0].to_vec();
+                let allele_obs = |allele_index: usize| -> usize {
+                    if allele_index == 0 {
+                        ro_value as usize
+                    } else {
+                        ao_vec[allele_index - 1] as usize
+                    }
+                };
+                no_of_ref_obs = allele_obs(lower_allele_index);
+                no_of_alt_obs = allele_obs(higher_allele_index);
+            }
+            let depth = no_of_ref_obs + no_of_alt_obs;
+            if depth >= self.min_coverage && depth <= self.max_coverage {
+                one_genome_snp.no_of_good_hets += 1;
+                let phase_set: Option<i32> = if one_genotype.is_phased {
+                    match record.format(b"PS").integer() {
+                        Ok(ps_vec) => {
+                            //missing PS is i32::MIN in htslib
+                            if ps_vec[0][0] > 0 { Some(ps_vec[0][0]) } else { None }
+                        },
+                        Err(_) => None,
+                    }
+                } else {
+                    None
+                };
+                let ref_id = record.rid().expect("Error reading rid.") as u64;
+                let snp_key = (ref_id, record.pos() as u64);
+                let chr = String::from_utf8_lossy(vcf_header.rid2name(ref_id as u32)).to_string();
+                let mut one_snp = OneSNP::new(chr, snp_key.1, allele_vec[0].clone(), allele_vec[lower_allele_index].clone(),
+                                              allele_vec[higher_allele_index].clone(), sample_1_genotype,
+                                              depth, abp_value, srp_value, sap_value, no_of_ref_obs, no_of_alt_obs);
+                one_snp.is_multi_allelic_het = one_genotype.is_multi_allelic_het();
+                if one_genotype.is_phased {
+                    one_snp.set_phase(phase_set, allele_index_1 < allele_index_2);
+                }
+                one_genome_snp.chr_start2snp.insert(snp_key, one_snp);
+            }
+        }
+    }
+
+    fn read_in_het_snp(&'a self, snp_file_path: &'a Path, abp_max: f32, collect_genotyped_sites: bool,
+                       collect_allele_obs: bool) -> OneGenomeSNP {
         println_stderr!("Reading from {:?} with abp_max={}, srp_max={}, sap_max={}, min_coverage={}, max_coverage={} ...",
             &snp_file_path, abp_max, self.srp_max, self.sap_max, self.min_coverage, self.max_coverage);
-        let mut vcf = bcf::Reader::from_path(&snp_file_path).ok().expect("Error opening file.");
-        let mut one_genome_snp = OneGenomeSNP::new();
+        let mut one_genome_snp = OneGenomeSNP::new(self.genome_build);
+        match self.region_vec {
+            Some(ref region_vec) => {
+                //only the requested regions are read, through the VCF index.
+                let mut vcf = bcf::IndexedReader::from_path(&snp_file_path)
+                    .ok().expect("Error opening indexed file. Is it bgzipped and indexed?");
+                let vcf_header = vcf.header().clone();
+                for region in region_vec.iter() {
+                    let rid = match vcf_header.name2rid(region.chr.as_bytes()) {
+                        Ok(rid) => rid,
+                        Err(_) => {
+                            println_stderr!("WARNING: chromosome {} is not in {:?}.", region.chr, snp_file_path);
+                            continue;
+                        }
+                    };
+                    vcf.fetch(rid, region.start, region.end).ok().expect("Error fetching region.");
+                    for rec in vcf.records() {
+                        let record = rec.ok().expect("Error reading record.");
+                        //records starting before the region belong to the previous shard.
+                        if record.pos() < region.start {
+                            continue;
+                        }
+                        self.add_record(record, &vcf_header, abp_max, collect_genotyped_sites, collect_allele_obs,
+                                        &mut one_genome_snp);
+                    }
+                }
+            },
+            None => {
+                let mut vcf = bcf::Reader::from_path(&snp_file_path).ok().expect("Error opening file.");
+                let vcf_header = vcf.header().clone();
+                for rec in vcf.records() {
+                    let record = rec.ok().expect("Error reading record.");
+                    self.add_record(record, &vcf_header, abp_max, collect_genotyped_sites, collect_allele_obs,
+                                        &mut one_genome_snp);
+                }
+            },
+        }
+        println_stderr!("{} good hets out of {} SNPs in total. {} indels/MNPs and {} multi-allelic records excluded.",
+                        one_genome_snp.no_of_good_hets, one_genome_snp.no_of_total_records,
+                        one_genome_snp.no_of_indels_mnps, one_genome_snp.no_of_multi_allelic);
+        return one_genome_snp;
+    }
+
+    /// Read GT/PS of the first sample of a phased VCF. Returns the allele on the first haplotype of each phased het.
+    fn read_in_external_phase(&self, phased_vcf_path: &Path) -> HashMap<(String, u64), (Option<i32>, Vec<u8>)> {
+        println_stderr!("Reading phase from {:?} ...", phased_vcf_path);
+        let mut vcf = bcf::Reader::from_path(&phased_vcf_path).ok().expect("Error opening phased VCF file.");
         let vcf_header = vcf.header().clone();
-        for (i, rec) in vcf.records().enumerate() {
-            one_genome_snp.no_of_total_records += 1;
+        let mut chr_pos2phase: HashMap<(String, u64), (Option<i32>, Vec<u8>)> = HashMap::new();
+        for rec in vcf.records() {
             let mut record = rec.ok().expect("Error reading record.");
-            let abp_value = record.info(b"ABP").float().ok().expect("Error reading ABP float.").expect("Missing tag ABP")[0];
-            let srp_value = record.info(b"SRP").float().ok().expect("Error reading SRP float.").expect("Missing tag SRP")[0];
-            let sap_value = record.info(b"SAP").float().ok().expect("Error reading SAP float.").expect("Missing tag SAP")[0];
-            let sample_1_genotype: String;
+            let one_genotype: OneGenotype;
             {
-                //a separate scope due to conflict between mutable borrow, record.genotypes(), and immutable borrows, record.rid(), etc..
                 let genotypes = record.genotypes().expect("Error reading genotypes");
-                sample_1_genotype = format!("{}", genotypes.get(0));
-            }
-            if sample_1_genotype == "0/1" && abp_value<=abp_max {
-                //sample_1_genotype could be . (uncalled), then RO and AO are undefined (a big minus number)
-                //  and integer().ok() still works.
-                let no_of_ref_obs = record.format(b"RO").integer().ok().expect("Error reading RO integer.")[0][0] as usize;
-                let no_of_alt_obs = record.format(b"AO").integer().ok().expect("Error reading AO integer.")[0][0] as usize;
-                let depth = no_of_ref_obs + no_of_alt_obs;
-                if depth >= self.min_coverage && depth <= self.max_coverage {
-                    one_genome_snp.no_of_good_hets += 1;
-                    let ref_id = record.rid().expect("Error reading rid.") as u64;
-                    let snp_key = (ref_id, record.pos() as u64);
-                    let chr = String::from_utf8_lossy(vcf_header.rid2name(ref_id as u32)).to_string();
-                    let one_snp = OneSNP::new(chr, snp_key.1, sample_1_genotype,
-                                              depth, abp_value, srp_value, sap_value, no_of_ref_obs, no_of_alt_obs);
-                    one_genome_snp.chr_start2snp.insert(snp_key, one_snp);
-                }
+                one_genotype = classify_genotype(&genotypes.get(0));
+            }
+            if !one_genotype.is_phased {
+                continue;
+            }
+            if let GenotypeClass::Het(hap1_allele_index, _) = one_genotype.class {
+                let phase_set: Option<i32> = match record.format(b"PS").integer() {
+                    Ok(ps_vec) => if ps_vec[0][0] > 0 { Some(ps_vec[0][0]) } else { None },
+                    Err(_) => None,
+                };
+                let hap1_allele = record.alleles()[hap1_allele_index as usize].to_vec();
+                let ref_id = record.rid().expect("Error reading rid.");
+                let chr = String::from_utf8_lossy(vcf_header.rid2name(ref_id)).to_string();
+                chr_pos2phase.insert((chr, record.pos() as u64), (phase_set, hap1_allele));
             }
         }
-        println_stderr!("{} good hets out of {} SNPs in total.", one_genome_snp.no_of_good_hets, one_genome_snp.no_of_total_records);
-        return one_genome_snp;
+        println_stderr!("{} phased hets.", chr_pos2phase.len());
+        chr_pos2phase
     }
 
-    fn intersect_snp(&self, one_genome_snp_tumor: &OneGenomeSNP, one_genome_snp_normal: &OneGenomeSNP){
+    fn intersect_snp(&self, one_genome_snp_tumor: &OneGenomeSNP, one_genome_snp_normal: &OneGenomeSNP,
+                     normal_beta_binomial_rho: Option<f64>, ref_bias_depth2count: &BTreeMap<usize, (usize, usize)>,
+                     sex: Sex){
+        let ref_bias = RefBias::estimate_from_depth_histogram(ref_bias_depth2count);
+        let sex_count = &one_genome_snp_normal.sex_count;
         let output_f = File::create(&self.output_file_path)
             .expect(&format!("Error in creating output file {:?}", &self.output_file_path));
         let mut gz_writer = flate2::GzBuilder::new()
@@ -151,26 +630,103 @@ impl<'a> SelectHetSNP<'a> {
             .comment("Comment")
             .write(output_f, Compression::default());
         gz_writer.write_fmt(format_args!("#abp_max_tumor={}, abp_max_normal={}, srp_max={}, \
-                sap_max={}, min_coverage={}, max_coverage={}\n",
+                sap_max={}, min_coverage={}, max_coverage={}, multi_allelic_het={}\n",
                                          self.abp_max_tumor, self.abp_max_normal, self.srp_max,
-                                         self.sap_max, self.min_coverage, self.max_coverage)).unwrap();
+                                         self.sap_max, self.min_coverage, self.max_coverage,
+                                         self.multi_allelic_het)).unwrap();
+        if let Some(known_sites_path) = self.known_sites_path {
+            gz_writer.write_fmt(format_args!("#known_sites={:?}, min_population_af={}\n",
+                                             known_sites_path, self.min_population_af)).unwrap();
+        }
+        //NA without calls, e.g. no chrX in a shard
+        gz_writer.write_fmt(format_args!("#sex={}, sex_option={}, genome_build={}, normal x_non_par_het_ratio={}, \
+                autosome_het_ratio={}\n",
+                                         sex, self.sex_option.map_or("auto".to_string(), |sex| sex.to_string()),
+                                         self.genome_build,
+                                         contamination::format_option(sex_count.x_non_par_het_ratio()),
+                                         contamination::format_option(sex_count.autosome_het_ratio()))).unwrap();
+        if let Some(ref region_vec) = self.region_vec {
+            gz_writer.write_fmt(format_args!("#no_of_regions: {}\n", region_vec.len())).unwrap();
+        }
+        if let (Some(rho), Some(pvalue_min)) = (normal_beta_binomial_rho, self.beta_binomial_pvalue_min) {
+            //abp_max_normal is not applied in this mode.
+            gz_writer.write_fmt(format_args!("#normal_beta_binomial_rho={}, beta_binomial_pvalue_min={}\n",
+                                             rho, pvalue_min)).unwrap();
+        }
         gz_writer.write_fmt(format_args!("#tumor snp:{:?}\n", &self.snp_file_path_tumor)).unwrap();
         gz_writer.write_fmt(format_args!("#tumor no_of_total_records: {}\n", one_genome_snp_tumor.no_of_total_records)).unwrap();
+        gz_writer.write_fmt(format_args!("#tumor no_of_indels_mnps: {}\n", one_genome_snp_tumor.no_of_indels_mnps)).unwrap();
+        gz_writer.write_fmt(format_args!("#tumor no_of_multi_allelic: {}\n", one_genome_snp_tumor.no_of_multi_allelic)).unwrap();
+        gz_writer.write_fmt(format_args!("#tumor no_of_not_known_sites: {}\n", one_genome_snp_tumor.no_of_not_known_sites)).unwrap();
+        gz_writer.write_fmt(format_args!("#tumor no_of_x_non_par_hets: {}\n", one_genome_snp_tumor.no_of_x_non_par_hets)).unwrap();
+        gz_writer.write_fmt(format_args!("#tumor no_of_x_par_hets: {}\n", one_genome_snp_tumor.no_of_x_par_hets)).unwrap();
+        gz_writer.write_fmt(format_args!("#tumor no_of_x_non_par_hets_dropped: {}\n", one_genome_snp_tumor.no_of_x_non_par_hets_dropped)).unwrap();
         gz_writer.write_fmt(format_args!("#tumor no_of_good_hets: {}\n", one_genome_snp_tumor.no_of_good_hets)).unwrap();
-        gz_writer.write_fmt(format_args!("#normal snp:{:?}\n", &self.snp_file_path_tumor)).unwrap();
+        if let Some(ref estimate) = one_genome_snp_tumor.contamination {
+            gz_writer.write_fmt(format_args!("#tumor contamination: {}\n",
+                                             contamination::format_estimate(estimate))).unwrap();
+        }
+        //summed over shards by concat, which re-estimates contamination from it.
+        if let Some(ref profile) = one_genome_snp_tumor.contamination_profile {
+            gz_writer.write_fmt(format_args!("#tumor contamination_profile: {}\n", profile.to_comment_value())).unwrap();
+        }
+        gz_writer.write_fmt(format_args!("#normal snp:{:?}\n", &self.snp_file_path_normal)).unwrap();
         gz_writer.write_fmt(format_args!("#normal no_of_total_records: {}\n", one_genome_snp_normal.no_of_total_records)).unwrap();
+        gz_writer.write_fmt(format_args!("#normal no_of_indels_mnps: {}\n", one_genome_snp_normal.no_of_indels_mnps)).unwrap();
+        gz_writer.write_fmt(format_args!("#normal no_of_multi_allelic: {}\n", one_genome_snp_normal.no_of_multi_allelic)).unwrap();
+        gz_writer.write_fmt(format_args!("#normal no_of_not_known_sites: {}\n", one_genome_snp_normal.no_of_not_known_sites)).unwrap();
+        if self.roh_output_path.is_some() && self.region_vec.is_none() {
+            gz_writer.write_fmt(format_args!("#normal no_of_roh: {}\n", one_genome_snp_normal.no_of_roh)).unwrap();
+        }
+        gz_writer.write_fmt(format_args!("#normal no_of_allele_imbalanced: {}\n", one_genome_snp_normal.no_of_allele_imbalanced)).unwrap();
+        gz_writer.write_fmt(format_args!("#normal no_of_x_non_par_hets: {}\n", one_genome_snp_normal.no_of_x_non_par_hets)).unwrap();
+        gz_writer.write_fmt(format_args!("#normal no_of_x_par_hets: {}\n", one_genome_snp_normal.no_of_x_par_hets)).unwrap();
+        gz_writer.write_fmt(format_args!("#normal no_of_x_non_par_hets_dropped: {}\n", one_genome_snp_normal.no_of_x_non_par_hets_dropped)).unwrap();
         gz_writer.write_fmt(format_args!("#normal no_of_good_hets: {}\n", one_genome_snp_normal.no_of_good_hets)).unwrap();
+        if let Some(ref estimate) = one_genome_snp_normal.contamination {
+            gz_writer.write_fmt(format_args!("#normal contamination: {}\n",
+                                             contamination::format_estimate(estimate))).unwrap();
+        }
+        //summed over shards by concat, which re-estimates contamination from it.
+        if let Some(ref profile) = one_genome_snp_normal.contamination_profile {
+            gz_writer.write_fmt(format_args!("#normal contamination_profile: {}\n", profile.to_comment_value())).unwrap();
+        }
+        //tumor_baf_corrected is the alt fraction after undoing the ref bias of normal hets, binned by normal depth.
+        for (i, bin_max_depth) in ref_bias.bin_max_depth_vec.iter().enumerate() {
+            gz_writer.write_fmt(format_args!("#normal_ref_fraction depth<={}: {} ({} hets)\n", bin_max_depth,
+                                             ref_bias.ref_fraction_vec[i], ref_bias.no_of_snps_vec[i])).unwrap();
+        }
+        //summed over shards by concat, which re-estimates the ref bias from them.
+        if self.region_vec.is_some() {
+            for (depth, &(no_of_snps, total_ref_obs)) in ref_bias_depth2count.iter() {
+                gz_writer.write_fmt(format_args!("#normal no_of_hets of depth {}: {}\n", depth, no_of_snps)).unwrap();
+                gz_writer.write_fmt(format_args!("#normal ref_obs of depth {}: {}\n", depth, total_ref_obs)).unwrap();
+            }
+        }
         gz_writer.write_fmt(format_args!("chr\tpos\ttumor_maf_normalized\ttumor_depth\t\
                 tumor_maf\ttumor_ro\ttumor_ao\t\
-                normal_maf\tnormal_ro\tnormal_ao\n")).unwrap();
+                normal_maf\tnormal_ro\tnormal_ao\t\
+                tumor_baf\ttumor_baf_corrected\t\
+                record_ref_allele\tallele_1\tallele_2\tis_multi_allelic_het\n")).unwrap();
 
         let mut no_of_intersect = 0usize;
+        let mut phased_snp_vec: Vec<PhasedSNP> = Vec::new();
 
         let mut snp_key_tumor_vec: Vec<(u64, u64)> = one_genome_snp_tumor.chr_start2snp.keys().map(|snp_key| *snp_key).collect();
         //let mut snp_key_tumor_vec = one_genome_snp_tumor.chr_start2snp.iter().map(|(snp_key, _)| *snp_key).collect::<Vec<(u64, u64)>>();
         //snp_key_tumor_vec.sort_by(|a, b| ((a.0<<30)+a.1).cmp(&((b.0<<30)+b.1)));
         //plus operator has precedence over bit operator. Without () for (k.0<<31), shift will cause overflow.
         snp_key_tumor_vec.sort_by_key(|k| (k.0<<31)+k.1);
+        let mut vcf_writer: Option<HetSNPVcfWriter> = self.vcf_output_path.map(|vcf_output_path| {
+            let mut chr_vec: Vec<String> = Vec::new();
+            for snp_key in snp_key_tumor_vec.iter() {
+                let chr = &one_genome_snp_tumor.chr_start2snp[snp_key].chr;
+                if chr_vec.last() != Some(chr) {
+                    chr_vec.push(chr.clone());
+                }
+            }
+            HetSNPVcfWriter::new(vcf_output_path, &chr_vec, &self.get_setting_vec(normal_beta_binomial_rho))
+        });
         for snp_key in snp_key_tumor_vec.iter() {
             let snp_tumor = &one_genome_snp_tumor.chr_start2snp[snp_key];
             if one_genome_snp_normal.chr_start2snp.contains_key(snp_key) {
@@ -179,36 +735,240 @@ impl<'a> SelectHetSNP<'a> {
                 let snp_normal = &one_genome_snp_normal.chr_start2snp[snp_key];
                 let tumor_maf = cmp::max(snp_tumor.no_of_alt_obs, snp_tumor.no_of_ref_obs) as f32/snp_tumor.depth as f32;
                 let normal_maf = cmp::max(snp_normal.no_of_alt_obs, snp_normal.no_of_ref_obs) as f32/snp_normal.depth as f32;
-                let ratio_ref = snp_tumor.no_of_ref_obs as f32/snp_normal.no_of_ref_obs as f32;
-                let ratio_alt  = snp_tumor.no_of_alt_obs as f32/snp_normal.no_of_alt_obs as f32;
-                let tumor_maf_normalized: f32;
-                if ratio_alt>ratio_ref{
-                    tumor_maf_normalized = ratio_alt/(ratio_alt + ratio_ref);
+                let tumor_maf_normalized = calc_tumor_maf_normalized(snp_tumor.no_of_ref_obs, snp_tumor.no_of_alt_obs,
+                                                                     snp_normal.no_of_ref_obs, snp_normal.no_of_alt_obs);
+                let tumor_baf = snp_tumor.no_of_alt_obs as f64 / snp_tumor.depth as f64;
+                let tumor_baf_corrected = if snp_tumor.is_multi_allelic_het {
+                    tumor_baf
                 } else {
-                    tumor_maf_normalized = ratio_ref/(ratio_alt + ratio_ref);
-                }
+                    //the bias table is binned by normal depth.
+                    1.0 - ref_bias.correct_ref_fraction(1.0 - tumor_baf, snp_normal.depth)
+                };
                 gz_writer.write_fmt(format_args!("{}\t{}\t{}\t{}\t\
                         {}\t{}\t{}\t\
-                        {}\t{}\t{}\n",
+                        {}\t{}\t{}\t\
+                        {}\t{}\t\
+                        {}\t{}\t{}\t{}\n",
                                                  snp_tumor.chr, snp_tumor.start, tumor_maf_normalized, snp_tumor.depth,
                                                  tumor_maf, snp_tumor.no_of_ref_obs, snp_tumor.no_of_alt_obs,
-                                                 normal_maf, snp_normal.no_of_ref_obs, snp_normal.no_of_alt_obs)
+                                                 normal_maf, snp_normal.no_of_ref_obs, snp_normal.no_of_alt_obs,
+                                                 tumor_baf, tumor_baf_corrected,
+                                                 String::from_utf8_lossy(&snp_tumor.record_ref_allele),
+                                                 String::from_utf8_lossy(&snp_tumor.ref_allele),
+                                                 String::from_utf8_lossy(&snp_tumor.alt_allele),
+                                                 snp_tumor.is_multi_allelic_het)
                 ).unwrap();
+                if let Some(ref mut vcf_writer) = vcf_writer {
+                    vcf_writer.write(&HetSNPVcfRecord {
+                        chr: &snp_tumor.chr,
+                        pos: snp_tumor.start,
+                        record_ref_allele: &snp_tumor.record_ref_allele,
+                        allele_1: &snp_tumor.ref_allele,
+                        allele_2: &snp_tumor.alt_allele,
+                        tumor_ro: snp_tumor.no_of_ref_obs,
+                        tumor_ao: snp_tumor.no_of_alt_obs,
+                        normal_ro: snp_normal.no_of_ref_obs,
+                        normal_ao: snp_normal.no_of_alt_obs,
+                        tumor_maf,
+                        normal_maf,
+                        tumor_maf_normalized,
+                        tumor_baf,
+                        tumor_baf_corrected,
+                    });
+                }
+                if self.phase_block_output_path.is_some() && snp_normal.is_phased {
+                    //phased hets without PS are treated as phased along the whole chromosome.
+                    phased_snp_vec.push(PhasedSNP::new(snp_normal.chr.clone(), snp_normal.start,
+                                                       snp_normal.phase_set.unwrap_or(0), snp_normal.hap1_is_ref,
+                                                       snp_tumor.no_of_ref_obs, snp_tumor.no_of_alt_obs,
+                                                       snp_normal.no_of_ref_obs, snp_normal.no_of_alt_obs));
+                }
             }
 
         }
 
         gz_writer.write_fmt(format_args!("#no_of_intersect: {}\n", no_of_intersect)).unwrap();
+        //all normal hets with tumor reads of both alleles, not only those that are also tumor hets passing ABP,
+        //as strong tumor LOH makes the tumor call them hom or fail ABP.
+        let mut snp_key_normal_vec: Vec<(u64, u64)> = one_genome_snp_normal.chr_start2snp.keys().map(|snp_key| *snp_key).collect();
+        snp_key_normal_vec.sort_by_key(|k| (k.0<<31)+k.1);
+        let mut paired_snp_count_vec: Vec<PairedSNPCount> = Vec::new();
+        for snp_key in snp_key_normal_vec.iter() {
+            let snp_normal = &one_genome_snp_normal.chr_start2snp[snp_key];
+            //absent if the tumor VCF has no record, e.g. hom-ref without --report-monomorphic.
+            let &(ref allele_vec, ref obs_vec) = match one_genome_snp_tumor.chr_start2allele_obs.get(snp_key) {
+                Some(allele_obs) => allele_obs,
+                None => continue,
+            };
+            let allele_obs = |allele: &Vec<u8>| allele_vec.iter().position(|tumor_allele| tumor_allele == allele)
+                .map(|allele_index| obs_vec[allele_index]).unwrap_or(0);
+            paired_snp_count_vec.push(PairedSNPCount {
+                chr: snp_normal.chr.clone(),
+                tumor_ref_obs: allele_obs(&snp_normal.ref_allele),
+                tumor_alt_obs: allele_obs(&snp_normal.alt_allele),
+                normal_ref_obs: snp_normal.no_of_ref_obs,
+                normal_alt_obs: snp_normal.no_of_alt_obs,
+                normal_ref_fraction_expected: if snp_normal.is_multi_allelic_het {
+                    0.5
+                } else {
+                    ref_bias.ref_fraction(snp_normal.depth)
+                },
+            });
+        }
+        //infer reads this line and reports it along with purity. concat adds up the two sums over shards.
+        let tumor_in_normal_estimate = tumor_in_normal::estimate_tumor_in_normal(&paired_snp_count_vec);
+        if tumor_in_normal_estimate.tumor_in_normal_fraction.is_some() || self.region_vec.is_some() {
+            gz_writer.write_fmt(format_args!("#tumor_in_normal_fraction={}, no_of_loh_blocks={}, no_of_loh_snps={}, \
+                    normal_excess_sum={}, tumor_excess_sum={}\n",
+                                             contamination::format_option(tumor_in_normal_estimate.tumor_in_normal_fraction),
+                                             tumor_in_normal_estimate.no_of_loh_blocks,
+                                             tumor_in_normal_estimate.no_of_loh_snps,
+                                             tumor_in_normal_estimate.normal_excess_sum,
+                                             tumor_in_normal_estimate.tumor_excess_sum)).unwrap();
+        }
+        match tumor_in_normal_estimate.tumor_in_normal_fraction {
+            Some(tumor_in_normal_fraction) => println_stderr!("Tumor-in-normal fraction: {} from {} LOH blocks.",
+                                                              tumor_in_normal_fraction,
+                                                              tumor_in_normal_estimate.no_of_loh_blocks),
+            None => println_stderr!("No LOH block in the tumor. Tumor-in-normal fraction is not estimated."),
+        }
         gz_writer.finish()
             .expect(&format!("ERROR finish() failure for gz_writer of {:?}.", &self.output_file_path));
         println_stderr!("{} intersect SNPs.", no_of_intersect);
+        if let Some(vcf_writer) = vcf_writer {
+            vcf_writer.finish();
+        }
+
+        if let Some(phase_block_output_path) = self.phase_block_output_path {
+            let block_vec = haplotype_block::aggregate_blocks(&phased_snp_vec);
+            haplotype_block::output_blocks(&block_vec, phase_block_output_path, phased_snp_vec.len());
+        }
     }
 
     pub fn run(&self){
-        let one_genome_snp_tumor =
-              self.read_in_het_snp(self.snp_file_path_tumor, self.abp_max_tumor);
-        let one_genome_snp_normal =
-              self.read_in_het_snp(self.snp_file_path_normal, self.abp_max_normal);
-        self.intersect_snp(&one_genome_snp_tumor, &one_genome_snp_normal);
+        if self.region_vec.is_some() {
+            if self.sex_option.is_none() {
+                panic!("--sex male or female is required with --region or --regions_file. \
+                        Each shard would infer sex on its own.");
+            }
+            if self.beta_binomial_pvalue_min.is_some() && self.beta_binomial_rho.is_none() {
+                panic!("--beta_binomial_rho is required with --beta_binomial_pvalue_min and --region or --regions_file. \
+                        Each shard would fit rho on its own.");
+            }
+        }
+        let mut one_genome_snp_tumor =
+              self.read_in_het_snp(self.snp_file_path_tumor, self.abp_max_tumor, false, true);
+        let abp_max_normal = match self.beta_binomial_pvalue_min {
+            Some(_) => f32::MAX,
+            None => self.abp_max_normal,
+        };
+        let mut one_genome_snp_normal =
+              self.read_in_het_snp(self.snp_file_path_normal, abp_max_normal, self.roh_output_path.is_some(),
+                                   false);
+        if let Some(roh_output_path) = self.roh_output_path {
+            if self.region_vec.is_some() {
+                //runs can span shards. concat finds them from the sites of all shards.
+                roh::output_genotyped_sites(&one_genome_snp_normal.chr_genotyped_site_vec, roh_output_path,
+                                            self.min_roh_len);
+            } else {
+                let roh_segment_vec = roh::find_roh(&one_genome_snp_normal.chr_genotyped_site_vec, self.min_roh_len);
+                roh::output_roh_bed(&roh_segment_vec, roh_output_path, self.min_roh_len);
+                one_genome_snp_normal.no_of_roh = roh_segment_vec.len();
+            }
+            one_genome_snp_normal.chr_genotyped_site_vec.clear();
+        }
+        if let Some(known_sites_path) = self.known_sites_path {
+            //only normal hets can end up in the output. No need to look up other sites, except sites for contamination.
+            let mut wanted_chr_pos_set = one_genome_snp_normal.get_chr_pos_set();
+            if self.contamination_qc_path.is_some() {
+                wanted_chr_pos_set.extend(one_genome_snp_tumor.get_contamination_chr_pos_set());
+                wanted_chr_pos_set.extend(one_genome_snp_normal.get_contamination_chr_pos_set());
+            }
+            let known_sites = KnownSites::from_path(known_sites_path, &wanted_chr_pos_set);
+            //a panel without AF (i.e. a BED file) would silently drop every site.
+            if self.min_population_af > 0.0 && !known_sites.chr_pos2site.is_empty() && !known_sites.has_population_af {
+                panic!("min_population_af={} is given but the known-sites file {:?} has no AF field.",
+                       self.min_population_af, known_sites_path);
+            }
+            one_genome_snp_normal.retain_known_sites(&known_sites, self.min_population_af);
+            one_genome_snp_tumor.retain_known_sites(&known_sites, self.min_population_af);
+            println_stderr!("{} tumor hets and {} normal hets at known sites.",
+                            one_genome_snp_tumor.no_of_good_hets, one_genome_snp_normal.no_of_good_hets);
+            if let Some(contamination_qc_path) = self.contamination_qc_path {
+                if !known_sites.has_population_af {
+                    println_stderr!("WARNING: no population AF in {:?}. Contamination can not be estimated.",
+                                    known_sites_path);
+                }
+                let profile_tumor = contamination::calc_profile(&one_genome_snp_tumor.contamination_site_vec,
+                                                                &known_sites);
+                let profile_normal = contamination::calc_profile(&one_genome_snp_normal.contamination_site_vec,
+                                                                 &known_sites);
+                let estimate_tumor = profile_tumor.estimate();
+                let estimate_normal = profile_normal.estimate();
+                for &(sample, ref estimate) in [("tumor", &estimate_tumor), ("normal", &estimate_normal)].iter() {
+                    println_stderr!("Contamination of {}: {} (95% CI {}-{}) from {} sites, error rate {}{}.", sample,
+                                    contamination::format_option(estimate.contamination),
+                                    contamination::format_option(estimate.lower),
+                                    contamination::format_option(estimate.upper), estimate.no_of_sites,
+                                    estimate.error_rate,
+                                    if estimate.is_error_rate_from_rare_sites { "" } else { " (fitted, no rare-allele sites)" });
+                }
+                contamination::output_qc(contamination_qc_path, &estimate_tumor, &estimate_normal);
+                one_genome_snp_tumor.contamination = Some(estimate_tumor);
+                one_genome_snp_normal.contamination = Some(estimate_normal);
+                if self.region_vec.is_some() {
+                    one_genome_snp_tumor.contamination_profile = Some(profile_tumor);
+                    one_genome_snp_normal.contamination_profile = Some(profile_normal);
+                }
+            }
+        }
+        let sex = match self.sex_option {
+            Some(sex) => sex,
+            None => {
+                let sex = one_genome_snp_normal.sex_count.infer_sex();
+                let sex_count = &one_genome_snp_normal.sex_count;
+                println_stderr!("Inferred sex: {}. Het ratio of non-PAR chrX: {}, autosomes: {}.", sex,
+                                contamination::format_option(sex_count.x_non_par_het_ratio()),
+                                contamination::format_option(sex_count.autosome_het_ratio()));
+                if sex == Sex::Unknown {
+                    println_stderr!("WARNING: sex can not be inferred from {} non-PAR chrX and {} autosome calls. \
+                                    Non-PAR chrX hets are kept. Give --sex if the individual is male.",
+                                    one_genome_snp_normal.sex_count.no_of_x_non_par_hets +
+                                        one_genome_snp_normal.sex_count.no_of_x_non_par_homs,
+                                    one_genome_snp_normal.sex_count.no_of_autosome_hets +
+                                        one_genome_snp_normal.sex_count.no_of_autosome_homs);
+                }
+                sex
+            },
+        };
+        one_genome_snp_tumor.apply_sex(sex, self.genome_build);
+        one_genome_snp_normal.apply_sex(sex, self.genome_build);
+        if sex == Sex::Male {
+            println_stderr!("{} normal hets on non-PAR chrX dropped for a male.", one_genome_snp_normal.no_of_x_non_par_hets_dropped);
+        }
+        let mut normal_beta_binomial_rho: Option<f64> = None;
+        if let Some(pvalue_min) = self.beta_binomial_pvalue_min {
+            let rho = match self.beta_binomial_rho {
+                Some(rho) => rho,
+                None => {
+                    let rho = one_genome_snp_normal.fit_beta_binomial_rho();
+                    println_stderr!("Fitted beta-binomial overdispersion of normal hets: rho={}", rho);
+                    rho
+                },
+            };
+            one_genome_snp_normal.retain_beta_binomial(rho, pvalue_min);
+            println_stderr!("{} normal hets with p-value < {} dropped. {} left.",
+                            one_genome_snp_normal.no_of_allele_imbalanced, pvalue_min,
+                            one_genome_snp_normal.no_of_good_hets);
+            normal_beta_binomial_rho = Some(rho);
+        }
+        if let Some(phased_normal_vcf_path) = self.phased_normal_vcf_path {
+            let chr_pos2phase = self.read_in_external_phase(phased_normal_vcf_path);
+            let no_of_phased = one_genome_snp_normal.apply_external_phase(&chr_pos2phase);
+            println_stderr!("{} normal hets phased by {:?}.", no_of_phased, phased_normal_vcf_path);
+        }
+        let ref_bias_depth2count = one_genome_snp_normal.calc_ref_bias_depth_histogram();
+        self.intersect_snp(&one_genome_snp_tumor, &one_genome_snp_normal, normal_beta_binomial_rho,
+                           &ref_bias_depth2count, sex);
     }
 }
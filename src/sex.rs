@@ -0,0 +1,157 @@
+use std::fmt;
+
+
+//pseudo-autosomal regions of chrX, 0-based [start, end)
+const HG19_PAR_ARRAY: [(u64, u64); 2] = [(60000, 2699520), (154931043, 155260560)];
+const HG38_PAR_ARRAY: [(u64, u64); 2] = [(10000, 2781479), (155701382, 156030895)];
+//male if the het ratio of non-PAR chrX is below this fraction of the autosomal one
+const MALE_X_HET_RATIO_VS_AUTOSOME: f64 = 0.25;
+
+/// Reference genome build, for the coordinates of the pseudo-autosomal regions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GenomeBuild {
+    //also GRCh37
+    Hg19,
+    //also GRCh38
+    Hg38,
+}
+
+impl GenomeBuild {
+    pub fn parse(build_str: &str) -> GenomeBuild {
+        match build_str {
+            "hg19" | "GRCh37" => GenomeBuild::Hg19,
+            "hg38" | "GRCh38" => GenomeBuild::Hg38,
+            _ => panic!("Unknown genome build {}. It should be hg19 (GRCh37) or hg38 (GRCh38).", build_str),
+        }
+    }
+
+    fn par_array(&self) -> &'static [(u64, u64); 2] {
+        match *self {
+            GenomeBuild::Hg19 => &HG19_PAR_ARRAY,
+            GenomeBuild::Hg38 => &HG38_PAR_ARRAY,
+        }
+    }
+}
+
+impl fmt::Display for GenomeBuild {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GenomeBuild::Hg19 => write!(f, "hg19"),
+            GenomeBuild::Hg38 => write!(f, "hg38"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sex {
+    Male,
+    Female,
+    //too few calls on non-PAR chrX or autosomes to infer it
+    Unknown,
+}
+
+impl fmt::Display for Sex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Sex::Male => write!(f, "male"),
+            Sex::Female => write!(f, "female"),
+            Sex::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// "male" or "female". None for "auto", i.e. to be inferred.
+pub fn parse_sex_option(sex_str: &str) -> Option<Sex> {
+    match sex_str {
+        "male" => Some(Sex::Male),
+        "female" => Some(Sex::Female),
+        "auto" => None,
+        _ => panic!("Unknown sex {}. It should be male, female or auto.", sex_str),
+    }
+}
+
+pub fn is_chr_x(chr: &[u8]) -> bool {
+    chr == b"chrX" || chr == b"X"
+}
+
+pub fn is_autosome(chr: &[u8]) -> bool {
+    let chr = if chr.starts_with(b"chr") { &chr[3..] } else { chr };
+    match String::from_utf8_lossy(chr).parse::<u32>() {
+        Ok(chr_no) => chr_no >= 1 && chr_no <= 22,
+        Err(_) => false,
+    }
+}
+
+/// Whether a 0-based chrX position is outside the pseudo-autosomal regions of genome_build.
+pub fn is_x_non_par(pos: u64, genome_build: GenomeBuild) -> bool {
+    !genome_build.par_array().iter().any(|&(start, end)| pos >= start && pos < end)
+}
+
+/// Counts of het and homozygous genotype calls of one sample, for sex inference.
+pub struct SexCount {
+    genome_build: GenomeBuild,
+    pub no_of_x_non_par_hets: usize,
+    pub no_of_x_non_par_homs: usize,
+    pub no_of_autosome_hets: usize,
+    pub no_of_autosome_homs: usize,
+}
+
+impl SexCount {
+    pub fn new(genome_build: GenomeBuild) -> SexCount {
+        SexCount {
+            genome_build,
+            no_of_x_non_par_hets: 0,
+            no_of_x_non_par_homs: 0,
+            no_of_autosome_hets: 0,
+            no_of_autosome_homs: 0,
+        }
+    }
+
+    pub fn add(&mut self, chr: &[u8], pos: u64, is_het: bool) {
+        if is_chr_x(chr) && is_x_non_par(pos, self.genome_build) {
+            if is_het {
+                self.no_of_x_non_par_hets += 1;
+            } else {
+                self.no_of_x_non_par_homs += 1;
+            }
+        } else if is_autosome(chr) {
+            if is_het {
+                self.no_of_autosome_hets += 1;
+            } else {
+                self.no_of_autosome_homs += 1;
+            }
+        }
+    }
+
+    /// Het fraction of non-PAR chrX calls. None without such calls.
+    pub fn x_non_par_het_ratio(&self) -> Option<f64> {
+        let no_of_calls = self.no_of_x_non_par_hets + self.no_of_x_non_par_homs;
+        if no_of_calls == 0 {
+            return None;
+        }
+        Some(self.no_of_x_non_par_hets as f64 / no_of_calls as f64)
+    }
+
+    /// Het fraction of autosome calls. None without such calls.
+    pub fn autosome_het_ratio(&self) -> Option<f64> {
+        let no_of_calls = self.no_of_autosome_hets + self.no_of_autosome_homs;
+        if no_of_calls == 0 {
+            return None;
+        }
+        Some(self.no_of_autosome_hets as f64 / no_of_calls as f64)
+    }
+
+    /// A male has (almost) no hets on non-PAR chrX. Unknown if there are no non-PAR chrX or no autosome calls
+    /// to judge from, i.e. a shard of some chromosomes.
+    pub fn infer_sex(&self) -> Sex {
+        let (x_non_par_het_ratio, autosome_het_ratio) = match (self.x_non_par_het_ratio(), self.autosome_het_ratio()) {
+            (Some(x_non_par_het_ratio), Some(autosome_het_ratio)) => (x_non_par_het_ratio, autosome_het_ratio),
+            _ => return Sex::Unknown,
+        };
+        if x_non_par_het_ratio < MALE_X_HET_RATIO_VS_AUTOSOME * autosome_het_ratio {
+            Sex::Male
+        } else {
+            Sex::Female
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path};
+
+use autocor::kernel_smoothing;
+use call;
+use call::format_float;
+use infer;
+use infer::InferSegment;
+use maf_model::{calc_folded_maf, clamp_purity, expected_ratio, CopyState};
+
+
+//subclonal fractions are searched at this step within [0, 1].
+const FRACTION_STEP: f64 = 0.01;
+//chi-square quantile of 1 degree of freedom at 0.95. A mixture needs twice its log-likelihood gain above this.
+const LR_STATISTIC_MIN: f64 = 3.841;
+//subclonal fractions closer than this to 0 or 1 are clonal.
+const FRACTION_MARGIN: f64 = 0.05;
+//a population peak must be at least this fraction of the highest one.
+const POPULATION_PEAK_HEIGHT_MIN: f64 = 0.1;
+//a population peak must be the highest within this many fraction steps on either side.
+const POPULATION_PEAK_SCOPE: usize = 5;
+//the smallest kernel bandwidth of a subclonal fraction, in fraction steps.
+const POPULATION_BANDWIDTH_MIN: f64 = 2.0;
+
+/// Two-state mixture fit of one segment: a fraction of tumor cells carries state_b, the rest state_a.
+pub struct SubcloneFit {
+    pub clonal_copy_state: CopyState,
+    pub clonal_log_likelihood: f64,
+    pub state_a: CopyState,
+    pub state_b: CopyState,
+    //fraction of tumor cells in state_b
+    pub fraction: f64,
+    //bounds where the log-likelihood is within LR_STATISTIC_MIN/2 of its maximum, i.e. a 95% profile likelihood interval
+    pub fraction_lower: f64,
+    pub fraction_upper: f64,
+    pub mixture_log_likelihood: f64,
+    pub lr_statistic: f64,
+    pub is_subclonal: bool,
+}
+
+/// States one allele copy above state_a, the adjacent states of a subclonal gain (or, from the other side, loss).
+fn find_adjacent_state_vec(state_a: CopyState) -> Vec<CopyState> {
+    let mut state_b_vec = vec![CopyState::new(state_a.total_cn + 1, state_a.minor_cn)];
+    if 2 * (state_a.minor_cn + 1) <= state_a.total_cn + 1 {
+        state_b_vec.push(CopyState::new(state_a.total_cn + 1, state_a.minor_cn + 1));
+    }
+    state_b_vec
+}
+
+/// Log-likelihood of a segment in which fraction of tumor cells carry state_b and the rest state_a.
+fn calc_mixture_log_likelihood(segment: &InferSegment, purity: f64, ploidy: f64, state_a: CopyState,
+                               state_b: CopyState, fraction: f64) -> f64 {
+    let ratio = (1.0 - fraction) * expected_ratio(purity, ploidy, state_a.total_cn) +
+        fraction * expected_ratio(purity, ploidy, state_b.total_cn);
+    let total_cn = (1.0 - fraction) * state_a.total_cn as f64 + fraction * state_b.total_cn as f64;
+    let major_cn = (1.0 - fraction) * state_a.major_cn() as f64 + fraction * state_b.major_cn() as f64;
+    let maf = (1.0 - purity + major_cn * purity) / (2.0 - 2.0 * purity + total_cn * purity);
+    let maf = if segment.no_of_snps == 0 {
+        maf
+    } else {
+        calc_folded_maf(maf.max(1.0 - maf), segment.snp_coverage_mean.round() as usize)
+    };
+    call::calc_segment_log_likelihood(segment, ratio, maf)
+}
+
+/// Fit a mixture of two adjacent copy states to a segment and test it against the best clonal state by
+/// a likelihood ratio test with one degree of freedom (the fraction).
+pub fn fit_subclone(segment: &InferSegment, purity: f64, ploidy: f64) -> SubcloneFit {
+    let segment_call = call::call_segment(segment, purity, ploidy);
+    let total_cn_a = segment_call.copy_no_float.floor().max(0.0) as usize;
+    let no_of_fractions = (1.0 / FRACTION_STEP).round() as usize + 1;
+    //(state_a, state_b, log-likelihood of each fraction)
+    let mut best_fit: Option<(CopyState, CopyState, Vec<f64>, usize)> = None;
+    for minor_cn_a in 0..(total_cn_a / 2 + 1) {
+        let state_a = CopyState::new(total_cn_a, minor_cn_a);
+        for state_b in find_adjacent_state_vec(state_a) {
+            let log_likelihood_vec: Vec<f64> = (0..no_of_fractions)
+                .map(|i| calc_mixture_log_likelihood(segment, purity, ploidy, state_a, state_b,
+                                                     i as f64 * FRACTION_STEP))
+                .collect();
+            let max_index = (0..no_of_fractions)
+                .fold(0, |max_index, i| if log_likelihood_vec[i] > log_likelihood_vec[max_index] { i } else { max_index });
+            if best_fit.as_ref().map_or(true, |best| log_likelihood_vec[max_index] > best.2[best.3]) {
+                best_fit = Some((state_a, state_b, log_likelihood_vec, max_index));
+            }
+        }
+    }
+    let (state_a, state_b, log_likelihood_vec, max_index) = best_fit.unwrap();
+    let mixture_log_likelihood = log_likelihood_vec[max_index];
+    let threshold = mixture_log_likelihood - LR_STATISTIC_MIN / 2.0;
+    let mut lower_index = max_index;
+    while lower_index > 0 && log_likelihood_vec[lower_index - 1] >= threshold {
+        lower_index -= 1;
+    }
+    let mut upper_index = max_index;
+    while upper_index + 1 < no_of_fractions && log_likelihood_vec[upper_index + 1] >= threshold {
+        upper_index += 1;
+    }
+    let fraction = max_index as f64 * FRACTION_STEP;
+    let lr_statistic = (2.0 * (mixture_log_likelihood - segment_call.log_likelihood)).max(0.0);
+    SubcloneFit {
+        clonal_copy_state: segment_call.copy_state,
+        clonal_log_likelihood: segment_call.log_likelihood,
+        state_a,
+        state_b,
+        fraction,
+        fraction_lower: lower_index as f64 * FRACTION_STEP,
+        fraction_upper: upper_index as f64 * FRACTION_STEP,
+        mixture_log_likelihood,
+        lr_statistic,
+        is_subclonal: lr_statistic > LR_STATISTIC_MIN && fraction >= FRACTION_MARGIN &&
+            fraction <= 1.0 - FRACTION_MARGIN,
+    }
+}
+
+/// Subclonal populations supported by subclonal segments: peaks of the window-weighted kernel density of
+/// their fractions, each segment smoothed by its confidence interval. Returns (fraction, no_of_windows) of each
+/// peak, where no_of_windows counts segments whose fraction is nearest to the peak.
+pub fn find_populations(window_fit_vec: &[(usize, &SubcloneFit)]) -> Vec<(f64, usize)> {
+    let no_of_fractions = (1.0 / FRACTION_STEP).round() as usize + 1;
+    let mut density_vec = vec![0f64; no_of_fractions];
+    for &(no_of_windows, fit) in window_fit_vec.iter() {
+        //the interval spans about 4 stddevs
+        let stddev = ((fit.fraction_upper - fit.fraction_lower) / 4.0 / FRACTION_STEP).max(POPULATION_BANDWIDTH_MIN);
+        kernel_smoothing(fit.fraction / FRACTION_STEP, stddev, no_of_windows as f64, &mut density_vec);
+    }
+    let max_density = density_vec.iter().cloned().fold(0f64, f64::max);
+    let mut population_vec: Vec<(f64, usize)> = Vec::new();
+    for i in 0..no_of_fractions {
+        let density = density_vec[i];
+        if density <= 0.0 || density < POPULATION_PEAK_HEIGHT_MIN * max_density {
+            continue;
+        }
+        let scope_start = if i >= POPULATION_PEAK_SCOPE { i - POPULATION_PEAK_SCOPE } else { 0 };
+        let scope_end = (i + POPULATION_PEAK_SCOPE).min(no_of_fractions - 1);
+        if density_vec[scope_start..i].iter().all(|other| *other < density) &&
+            density_vec[(i + 1)..(scope_end + 1)].iter().all(|other| *other <= density) {
+            population_vec.push((i as f64 * FRACTION_STEP, 0));
+        }
+    }
+    if population_vec.is_empty() {
+        return population_vec;
+    }
+    for &(no_of_windows, fit) in window_fit_vec.iter() {
+        let nearest_index = (0..population_vec.len()).fold(0, |nearest_index, i| {
+            if (population_vec[i].0 - fit.fraction).abs() < (population_vec[nearest_index].0 - fit.fraction).abs() {
+                i
+            } else {
+                nearest_index
+            }
+        });
+        population_vec[nearest_index].1 += no_of_windows;
+    }
+    population_vec
+}
+
+/// Detect subclonal copy number segments given purity and ploidy, which come from infer.out.tsv or the user.
+/// Writes subclone.segments.tsv (one line per segment, like cnv.interval.tsv of the C++ infer) and
+/// subclone.populations.tsv into output_dir.
+pub struct Subclone<'a> {
+    segment_file_path: &'a Path,
+    het_snp_file_path: &'a Path,
+    output_dir: &'a Path,
+    purity: f64,
+    ploidy: f64,
+    segment_stddev_divider: f64,
+    snp_coverage_min: usize,
+}
+
+impl<'a> Subclone<'a> {
+    pub fn new(segment_file_path: &'a str,
+               het_snp_file_path: &'a str,
+               output_dir: &'a str,
+               purity: f64,
+               ploidy: f64,
+               segment_stddev_divider: f64,
+               snp_coverage_min: usize,
+    ) -> Subclone<'a> {
+        if purity <= 0.0 || purity > 1.0 {
+            panic!("purity {} is not within (0, 1].", purity);
+        }
+        if ploidy <= 0.0 {
+            panic!("ploidy {} is not positive.", ploidy);
+        }
+        Subclone {
+            segment_file_path: Path::new(segment_file_path),
+            het_snp_file_path: Path::new(het_snp_file_path),
+            output_dir: Path::new(output_dir),
+            purity: clamp_purity(purity),
+            ploidy,
+            segment_stddev_divider,
+            snp_coverage_min,
+        }
+    }
+
+    pub fn run(&self) {
+        println_stderr!("Detecting subclonal segments at purity={}, ploidy={} ...", self.purity, self.ploidy);
+        let (mut segment_vec, _) = infer::read_segment_file(self.segment_file_path, self.segment_stddev_divider,
+                                                            infer::MAX_RATIO);
+        let het_snp_data = infer::read_het_snp_file(self.het_snp_file_path, self.snp_coverage_min);
+        infer::assign_snps_to_segments(&mut segment_vec, &het_snp_data);
+        fs::create_dir_all(self.output_dir)
+            .expect(&format!("Error in creating output folder {:?}", self.output_dir));
+
+        let fit_vec: Vec<SubcloneFit> = segment_vec.iter()
+            .map(|segment| fit_subclone(segment, self.purity, self.ploidy)).collect();
+        let output_file_path = self.output_dir.join("subclone.segments.tsv");
+        let mut output_f = File::create(&output_file_path)
+            .expect(&format!("Error in creating output file {:?}", &output_file_path));
+        output_f.write_fmt(format_args!("#purity={}, ploidy={}\n", self.purity, self.ploidy)).unwrap();
+        output_f.write_fmt(format_args!("chr\tstart\tend\tratio\tno_of_windows\tno_of_snps\tmaf_mean\t\
+            clonal_cp\tclonal_minor_cp\tclonal_logL\tcp_a\tminor_cp_a\tcp_b\tminor_cp_b\tsubclonal_fraction\t\
+            fraction_lower\tfraction_upper\tmixture_logL\tlr_statistic\tis_subclonal\n")).unwrap();
+        for (segment, fit) in segment_vec.iter().zip(fit_vec.iter()) {
+            output_f.write_fmt(format_args!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.2}\t{:.2}\t{:.2}\t{}\t{}\t{}\n",
+                                            segment.chr, segment.start, segment.end, segment.ratio,
+                                            segment.no_of_windows, segment.no_of_snps,
+                                            if segment.no_of_snps > 0 { format_float(segment.maf_mean) } else { "NA".to_string() },
+                                            fit.clonal_copy_state.total_cn, fit.clonal_copy_state.minor_cn,
+                                            fit.clonal_log_likelihood, fit.state_a.total_cn, fit.state_a.minor_cn,
+                                            fit.state_b.total_cn, fit.state_b.minor_cn, fit.fraction,
+                                            fit.fraction_lower, fit.fraction_upper, fit.mixture_log_likelihood,
+                                            fit.lr_statistic, fit.is_subclonal)).unwrap();
+        }
+
+        let window_fit_vec: Vec<(usize, &SubcloneFit)> = segment_vec.iter().zip(fit_vec.iter())
+            .filter(|&(_, fit)| fit.is_subclonal)
+            .map(|(segment, fit)| (segment.no_of_windows, fit)).collect();
+        let population_vec = find_populations(&window_fit_vec);
+        let output_file_path = self.output_dir.join("subclone.populations.tsv");
+        let mut output_f = File::create(&output_file_path)
+            .expect(&format!("Error in creating output file {:?}", &output_file_path));
+        output_f.write_fmt(format_args!("#no_of_subclonal_segments={}, no_of_populations={}\n",
+                                        window_fit_vec.len(), population_vec.len())).unwrap();
+        output_f.write_fmt(format_args!("subclonal_fraction\tno_of_windows\n")).unwrap();
+        for &(fraction, no_of_windows) in population_vec.iter() {
+            output_f.write_fmt(format_args!("{:.2}\t{}\n", fraction, no_of_windows)).unwrap();
+        }
+        println_stderr!("{} of {} segments subclonal, in {} populations. Output to {:?}.", window_fit_vec.len(),
+                        segment_vec.len(), population_vec.len(), self.output_dir);
+    }
+}
@@ -0,0 +1,210 @@
+use maf_model::calc_folded_maf;
+use prob::ln_choose;
+
+
+//consecutive hets pooled into one block
+const NO_OF_SNPS_PER_BLOCK: usize = 50;
+//a block is in strong LOH if the tumor major-allele fraction, after undoing the folding, is at least this
+const MIN_LOH_TUMOR_MAF: f64 = 0.7;
+//bisection steps to undo the folding of the tumor major-allele fraction
+const NO_OF_BISECTION_STEPS: usize = 30;
+
+/// Allele counts of one normal het in tumor and normal. Tumor counts are of the same two alleles, whatever the
+/// tumor genotype is.
+pub struct PairedSNPCount {
+    pub chr: String,
+    pub tumor_ref_obs: usize,
+    pub tumor_alt_obs: usize,
+    pub normal_ref_obs: usize,
+    pub normal_alt_obs: usize,
+    //the normal ref fraction expected of a het without tumor DNA, i.e. the ref bias at this normal depth.
+    //0.5 for multi-allelic hets.
+    pub normal_ref_fraction_expected: f64,
+}
+
+pub struct TumorInNormalEstimate {
+    //None if there is no LOH block to estimate from
+    pub tumor_in_normal_fraction: Option<f64>,
+    pub no_of_loh_blocks: usize,
+    pub no_of_loh_snps: usize,
+    //the numerator and denominator of tumor_in_normal_fraction, which add up over shards of a genome.
+    pub normal_excess_sum: f64,
+    pub tumor_excess_sum: f64,
+}
+
+/// P(the tumor major allele is called the major one) for a het whose true major-allele fraction is maf,
+/// as the allele with more reads, and ties go either way.
+fn calc_major_allele_call_probability(maf: f64, depth: usize) -> f64 {
+    if depth == 0 {
+        return 0.5;
+    }
+    let mut probability = 0f64;
+    for k in 0..(depth + 1) {
+        if 2 * k < depth {
+            continue;
+        }
+        let pmf = (ln_choose(depth, k) + k as f64 * maf.ln() + (depth - k) as f64 * (1.0 - maf).ln()).exp();
+        probability += if 2 * k == depth { 0.5 * pmf } else { pmf };
+    }
+    probability
+}
+
+/// Sum of depth*calc_folded_maf(maf, depth) over the block, i.e. its expected number of major-allele reads.
+fn calc_expected_major_obs(block: &[PairedSNPCount], maf: f64) -> f64 {
+    block.iter().map(|snp_count| {
+        let depth = snp_count.tumor_ref_obs + snp_count.tumor_alt_obs;
+        depth as f64 * calc_folded_maf(maf, depth)
+    }).sum()
+}
+
+/// normal_excess_sum/tumor_excess_sum within [0, 1]. None if there is no tumor excess, i.e. no LOH block.
+/// concat calls this on sums over shards.
+pub fn calc_tumor_in_normal_fraction(normal_excess_sum: f64, tumor_excess_sum: f64) -> Option<f64> {
+    if tumor_excess_sum > 0.0 {
+        Some((normal_excess_sum / tumor_excess_sum).max(0.0).min(1.0))
+    } else {
+        None
+    }
+}
+
+/// Estimate the fraction of tumor cells in the normal from allelic imbalance of the normal where the tumor shows LOH.
+/// Normal hets are ordered by position and pooled into blocks of consecutive hets per chromosome.
+/// The tumor major-allele fraction m of a block is the one whose expected folded fraction (max(ref, alt) is inflated
+/// at low depth) matches the observed one. Within LOH blocks, the normal fraction of the allele the tumor calls major
+/// at each site is its ref-bias-expected fraction plus t*(m - 0.5)*(2q - 1), for a tumor fraction t in the normal and
+/// the probability q that the tumor calls the true major allele at its depth. So
+/// t = sum(normal reads of that allele - expected) / sum(normal depth * (m - 0.5) * (2q - 1)).
+/// t is the fraction of tumor-sample DNA. The fraction of tumor cells is t*purity.
+pub fn estimate_tumor_in_normal(paired_snp_count_vec: &[PairedSNPCount]) -> TumorInNormalEstimate {
+    let mut no_of_loh_blocks = 0usize;
+    let mut no_of_loh_snps = 0usize;
+    let mut normal_excess_sum = 0f64;
+    let mut tumor_excess_sum = 0f64;
+    let mut block_start = 0usize;
+    while block_start < paired_snp_count_vec.len() {
+        let chr = &paired_snp_count_vec[block_start].chr;
+        let mut block_end = block_start;
+        while block_end < paired_snp_count_vec.len() && block_end - block_start < NO_OF_SNPS_PER_BLOCK &&
+            paired_snp_count_vec[block_end].chr == *chr {
+            block_end += 1;
+        }
+        let block = &paired_snp_count_vec[block_start..block_end];
+        block_start = block_end;
+        if block.len() < NO_OF_SNPS_PER_BLOCK / 2 {
+            continue;
+        }
+        let tumor_major_obs = block.iter()
+            .map(|snp_count| snp_count.tumor_ref_obs.max(snp_count.tumor_alt_obs)).sum::<usize>() as f64;
+        //the folded fraction increases with maf, so a block is in LOH iff it has more major reads than expected at
+        //MIN_LOH_TUMOR_MAF.
+        if tumor_major_obs == 0.0 || tumor_major_obs < calc_expected_major_obs(block, MIN_LOH_TUMOR_MAF) {
+            continue;
+        }
+        let mut maf_low = MIN_LOH_TUMOR_MAF;
+        let mut maf_high = 1.0;
+        for _ in 0..NO_OF_BISECTION_STEPS {
+            let maf_middle = 0.5 * (maf_low + maf_high);
+            if calc_expected_major_obs(block, maf_middle) <= tumor_major_obs {
+                maf_low = maf_middle;
+            } else {
+                maf_high = maf_middle;
+            }
+        }
+        let tumor_maf = 0.5 * (maf_low + maf_high);
+        no_of_loh_blocks += 1;
+        no_of_loh_snps += block.len();
+        for snp_count in block.iter() {
+            let normal_depth = snp_count.normal_ref_obs + snp_count.normal_alt_obs;
+            //normal reads are independent of tumor reads. Choosing the allele by the tumor does not bias them.
+            let (normal_major_obs, normal_major_fraction_expected) =
+                if snp_count.tumor_ref_obs >= snp_count.tumor_alt_obs {
+                    (snp_count.normal_ref_obs, snp_count.normal_ref_fraction_expected)
+                } else {
+                    (snp_count.normal_alt_obs, 1.0 - snp_count.normal_ref_fraction_expected)
+                };
+            normal_excess_sum += normal_major_obs as f64 - normal_depth as f64 * normal_major_fraction_expected;
+            let call_probability = calc_major_allele_call_probability(
+                tumor_maf, snp_count.tumor_ref_obs + snp_count.tumor_alt_obs);
+            tumor_excess_sum += normal_depth as f64 * (tumor_maf - 0.5) * (2.0 * call_probability - 1.0);
+        }
+    }
+    TumorInNormalEstimate {
+        tumor_in_normal_fraction: calc_tumor_in_normal_fraction(normal_excess_sum, tumor_excess_sum),
+        no_of_loh_blocks,
+        no_of_loh_snps,
+        normal_excess_sum,
+        tumor_excess_sum,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A linear congruential generator of uniforms in (0, 1), to simulate reads without a rand dependency.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next_uniform(&mut self) -> f64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((self.0 >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+        }
+
+        fn next_binomial(&mut self, n: usize, p: f64) -> usize {
+            (0..n).filter(|_| self.next_uniform() < p).count()
+        }
+    }
+
+    /// Observed ref fraction of a true ref fraction under ref bias b, as in RefBias.
+    fn apply_ref_bias(ref_fraction: f64, b: f64) -> f64 {
+        ref_fraction * b / (ref_fraction * b + (1.0 - ref_fraction) * (1.0 - b))
+    }
+
+    /// Half of the blocks in LOH of tumor MAF 0.85, the other half balanced. Tumor depth is low, so max(ref, alt)
+    /// is inflated, and both samples have a ref bias of 0.53.
+    fn simulate(tumor_in_normal_fraction: f64) -> Vec<PairedSNPCount> {
+        let mut lcg = Lcg(11);
+        let ref_bias = 0.53;
+        let mut paired_snp_count_vec: Vec<PairedSNPCount> = Vec::new();
+        for block_index in 0..200 {
+            let tumor_maf = if block_index % 2 == 0 { 0.85 } else { 0.5 };
+            for _ in 0..NO_OF_SNPS_PER_BLOCK {
+                let is_ref_major = lcg.next_uniform() < 0.5;
+                let tumor_ref_fraction = if is_ref_major { tumor_maf } else { 1.0 - tumor_maf };
+                let normal_ref_fraction = 0.5 + tumor_in_normal_fraction * (tumor_ref_fraction - 0.5);
+                let tumor_ref_obs = lcg.next_binomial(12, apply_ref_bias(tumor_ref_fraction, ref_bias));
+                let normal_ref_obs = lcg.next_binomial(30, apply_ref_bias(normal_ref_fraction, ref_bias));
+                paired_snp_count_vec.push(PairedSNPCount {
+                    chr: format!("chr{}", block_index / 20 + 1),
+                    tumor_ref_obs,
+                    tumor_alt_obs: 12 - tumor_ref_obs,
+                    normal_ref_obs,
+                    normal_alt_obs: 30 - normal_ref_obs,
+                    normal_ref_fraction_expected: ref_bias,
+                });
+            }
+        }
+        paired_snp_count_vec
+    }
+
+    #[test]
+    fn clean_normal_has_no_tumor() {
+        let estimate = estimate_tumor_in_normal(&simulate(0.0));
+        assert_eq!(estimate.no_of_loh_blocks, 100);
+        assert!(estimate.tumor_in_normal_fraction.unwrap() < 0.02, "{:?}", estimate.tumor_in_normal_fraction);
+    }
+
+    #[test]
+    fn tumor_in_normal_is_recovered() {
+        let estimate = estimate_tumor_in_normal(&simulate(0.1));
+        let tumor_in_normal_fraction = estimate.tumor_in_normal_fraction.unwrap();
+        assert!((tumor_in_normal_fraction - 0.1).abs() < 0.02, "{}", tumor_in_normal_fraction);
+    }
+
+    #[test]
+    fn no_loh_block_gives_no_estimate() {
+        let paired_snp_count_vec: Vec<PairedSNPCount> = simulate(0.1).into_iter().enumerate()
+            .filter(|&(index, _)| (index / NO_OF_SNPS_PER_BLOCK) % 2 == 1).map(|(_, snp_count)| snp_count).collect();
+        assert!(estimate_tumor_in_normal(&paired_snp_count_vec).tumor_in_normal_fraction.is_none());
+    }
+}
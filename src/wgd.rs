@@ -0,0 +1,66 @@
+use maf_model::CopyState;
+
+
+//a genome is doubled if more than this fraction of it has major copy number >= 2 (Bielski et al. 2018).
+const MAJOR_CN_FRACTION_MIN: f64 = 0.5;
+//ploidy-dependent test of PCAWG (Dentro et al. 2021): doubled if ploidy > PLOIDY_INTERCEPT + PLOIDY_LOH_SLOPE * loh_fraction.
+const PLOIDY_INTERCEPT: f64 = 2.9;
+const PLOIDY_LOH_SLOPE: f64 = -2.0;
+
+/// Whole-genome doubling status of a tumor from its allele-specific copy numbers.
+#[derive(Clone, Debug)]
+pub struct WgdStatus {
+    pub fraction_major_cn_ge_2: f64,
+    pub fraction_major_cn_ge_4: f64,
+    //fraction with minor copy number 0
+    pub loh_fraction: f64,
+    pub is_wgd: bool,
+    pub no_of_doublings: usize,
+}
+
+/// Ploidy above which a genome with loh_fraction of LOH is considered doubled.
+pub fn wgd_ploidy_threshold(loh_fraction: f64) -> f64 {
+    PLOIDY_INTERCEPT + PLOIDY_LOH_SLOPE * loh_fraction
+}
+
+/// WGD status from autosomal segment calls, each weighted by its length (e.g. number of windows).
+/// A genome is doubled if more than half of it has major copy number >= 2 and its ploidy passes the
+/// LOH-dependent threshold. A doubled genome with more than half at major copy number >= 4 is doubled twice.
+pub fn detect_wgd(weight_copy_state_vec: &[(f64, CopyState)], ploidy: f64) -> WgdStatus {
+    let mut total_weight = 0f64;
+    let mut weight_major_cn_ge_2 = 0f64;
+    let mut weight_major_cn_ge_4 = 0f64;
+    let mut loh_weight = 0f64;
+    for &(weight, copy_state) in weight_copy_state_vec.iter() {
+        total_weight += weight;
+        if copy_state.major_cn() >= 2 {
+            weight_major_cn_ge_2 += weight;
+        }
+        if copy_state.major_cn() >= 4 {
+            weight_major_cn_ge_4 += weight;
+        }
+        if copy_state.minor_cn == 0 {
+            loh_weight += weight;
+        }
+    }
+    if total_weight <= 0.0 {
+        return WgdStatus {
+            fraction_major_cn_ge_2: 0.0,
+            fraction_major_cn_ge_4: 0.0,
+            loh_fraction: 0.0,
+            is_wgd: false,
+            no_of_doublings: 0,
+        };
+    }
+    let fraction_major_cn_ge_2 = weight_major_cn_ge_2 / total_weight;
+    let fraction_major_cn_ge_4 = weight_major_cn_ge_4 / total_weight;
+    let loh_fraction = loh_weight / total_weight;
+    let is_wgd = fraction_major_cn_ge_2 > MAJOR_CN_FRACTION_MIN && ploidy > wgd_ploidy_threshold(loh_fraction);
+    WgdStatus {
+        fraction_major_cn_ge_2,
+        fraction_major_cn_ge_4,
+        loh_fraction,
+        is_wgd,
+        no_of_doublings: if !is_wgd { 0 } else if fraction_major_cn_ge_4 > MAJOR_CN_FRACTION_MIN { 2 } else { 1 },
+    }
+}